@@ -1,7 +1,7 @@
 use acteur::{Actor, ActorAssistant, Receive, Respond};
 
 use crate::{
-    actor::messages::{GetAppState, SetAppState},
+    actor::messages::{GetAppState, IsAppStateSet, SetAppState},
     types::ConnectionParameters,
 };
 
@@ -46,3 +46,11 @@ impl Respond<GetAppState> for AppState {
         self.0.clone().expect("You tried to retrieve the application state before setting it. Make sure to send a `SetAppState` message.")
     }
 }
+
+#[async_trait::async_trait]
+impl Respond<IsAppStateSet> for AppState {
+    type Response = bool;
+    async fn handle(&mut self, _: IsAppStateSet, _: &ActorAssistant<AppState>) -> Self::Response {
+        self.0.is_some()
+    }
+}
@@ -0,0 +1,31 @@
+use acteur::{Listen, Service, ServiceAssistant, ServiceConfiguration};
+use std::sync::Mutex;
+
+use crate::actor::messages::RecordAudit;
+
+///
+/// The service appends entries to the audit logs of the tenants, one at a
+/// time, so entries written at the same time don't mix. See `AuditLog`.
+///
+#[derive(Debug)]
+pub struct AuditRecorder(Mutex<()>);
+
+#[async_trait::async_trait]
+impl Service for AuditRecorder {
+    async fn initialize(_: &ServiceAssistant<Self>) -> (Self, ServiceConfiguration) {
+        (
+            AuditRecorder(Mutex::new(())),
+            ServiceConfiguration::default(),
+        )
+    }
+}
+
+#[async_trait::async_trait]
+impl Listen<RecordAudit> for AuditRecorder {
+    async fn handle(&self, message: RecordAudit, _: &ServiceAssistant<Self>) {
+        let _guard = self.0.lock().unwrap();
+        if let Err(e) = message.0.append(&message.1) {
+            error!("{}", e);
+        }
+    }
+}
@@ -2,14 +2,16 @@ use acteur::{Serve, Service, ServiceAssistant, ServiceConfiguration};
 
 use crate::{
     actor::{
-        messages::{GetUserData, LoadSettings, ResolveToProfileId},
-        ResolverClient, UserServiceClient,
+        messages::{GetUserData, LoadSettings, ResolveToProfileId, SendInvalidSettingsNotice},
+        JustClient, ResolverClient, UserServiceClient,
     },
-    types::{ProfileId, Settings},
+    service::SettingsError,
+    types::{ProfileId, RichMessage, Settings, Synchronization},
 };
 
 /// An actor service that is a facade to other services and used to group repetitively
-/// used functions in one point.
+/// used functions in one point. Forwards to the actors of the tenant given in
+/// the message.
 #[derive(Debug)]
 pub struct ControllerClient;
 
@@ -22,24 +24,52 @@ impl Service for ControllerClient {
 
 #[async_trait::async_trait]
 impl Serve<GetUserData> for ControllerClient {
-    type Response = (Option<ProfileId>, Option<Settings>);
+    type Response = (Synchronization<ProfileId>, Option<Settings>);
     async fn handle(
         &self,
         message: GetUserData,
         assistant: &acteur::ServiceAssistant<Self>,
     ) -> Self::Response {
+        let tenant = message.2.clone();
         // Don't await both, use something like join!
-        let profile_id: Option<ProfileId> = assistant
-            .call_service::<ResolverClient, ResolveToProfileId>(message.clone().into())
+        let mapping: Synchronization<ProfileId> = assistant
+            .call_actor::<ResolverClient, ResolveToProfileId>(
+                tenant.clone(),
+                message.clone().into(),
+            )
             .await
-            .ok()
-            .flatten();
+            .unwrap_or(Synchronization::NotMappedYet);
 
-        let settings: Option<Settings> = assistant
-            .call_service::<UserServiceClient, LoadSettings>(message.into())
+        let username = message.0.clone();
+        let (settings, settings_error): (Option<Settings>, Option<SettingsError>) = assistant
+            .call_actor::<UserServiceClient, LoadSettings>(tenant.clone(), message.into())
             .await
-            .ok();
+            .map(|(settings, error)| (Some(settings), error))
+            .unwrap_or((None, None));
 
-        (profile_id, settings)
+        // Without a profile the user can't be told yet, the next trigger tries
+        // again
+        if let (Synchronization::Some(profile_id), Some(error)) = (&mapping, settings_error) {
+            assistant
+                .send_to_actor::<JustClient, SendInvalidSettingsNotice>(
+                    tenant.clone(),
+                    SendInvalidSettingsNotice(
+                        tenant,
+                        username,
+                        profile_id.clone(),
+                        RichMessage::text(invalid_settings_message(&error)),
+                    ),
+                )
+                .await;
+        }
+
+        (mapping, settings)
     }
 }
+
+fn invalid_settings_message(error: &SettingsError) -> String {
+    format!(
+        "⚠️ Your chtbtr settings file is broken. Default settings are used until you fix it. {}",
+        error
+    )
+}
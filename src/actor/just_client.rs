@@ -1,36 +1,57 @@
 use std::{collections::HashMap, time::Instant};
 
-use acteur::{Listen, Serve, Service, ServiceAssistant, ServiceConfiguration};
+use acteur::{Actor, ActorAssistant, Receive, Respond};
+use chrono::{DateTime, Utc};
 use futures::lock::Mutex;
 use reqwest::blocking::Client;
 
 use crate::{
     actor::{
-        messages::{GetAppState, SearchProfileId, SendChatMessage},
-        AppState,
+        messages::{
+            DeliverNotification, FetchChatMessages, GetAppState, InvalidSettingsNoticeSent,
+            ProbeJustApi, RecordAudit, RecordMetric, SearchProfileId, SendChatMessage,
+            SendInvalidSettingsNotice,
+        },
+        AppState, AuditRecorder, MetricsCollector, UserServiceClient,
     },
-    service::JustError,
-    just::{requests::*, responses::*, utils::*},
+    service::{AuditOutcome, JustError, Metric},
+    just::{requests::*, responses::*},
     types::*,
 };
 
+/// Talks to the Just API as the chatbot of a tenant. There is an actor per
+/// tenant, addressed by its `TenantId`.
 #[derive(Debug)]
 pub struct JustClient {
     sender: Mutex<ProfileId>,
     domain: Mutex<String>,
     oauth_token: Mutex<String>,
+    // The richest format the Just installation shows messages in
+    message_format: MessageFormat,
 }
 
 impl JustClient {
-    pub fn new(profile_id: ProfileId, domain: String, oauth_token: String) -> JustClient {
+    pub fn new(
+        profile_id: ProfileId,
+        domain: String,
+        oauth_token: String,
+        message_format: MessageFormat,
+    ) -> JustClient {
         JustClient {
             sender: Mutex::new(profile_id),
             domain: Mutex::new(domain),
             oauth_token: Mutex::new(oauth_token),
+            message_format,
         }
     }
 
-    pub async fn send_chat_message(&self, receiver: &ProfileId, message: &str) {
+    /// Sends a message to `receiver` in the chat with the chatbot. Fails if
+    /// Just can't be reached or doesn't take the message.
+    pub async fn send_chat_message(
+        &self,
+        receiver: &ProfileId,
+        message: &RichMessage,
+    ) -> Result<(), JustError> {
         let client = Client::new();
         let sender = self.sender.lock().await;
         let domain = self.domain.lock().await;
@@ -44,24 +65,29 @@ impl JustClient {
             .post(&make_api_url(&domain, "/toro/chat/api/v2/chats"))
             .bearer_auth(&*oauth_token)
             .json(&chat)
-            .send()
-            .expect("Could not send chat creation request.");
+            .send()?;
 
         let res: ChatCreationResult = match res.status() {
-            reqwest::StatusCode::OK => res.json().expect("Just didn't reply with JSON."),
-            _ => {
-                let error: JustError = res.json().expect("Just didn't reply with JSON.");
-                error!(
-                    "Error occurred during chat creation. Response: {}",
-                    error.message
-                );
-                return;
+            reqwest::StatusCode::OK => res.json()?,
+            status => {
+                let cause = res
+                    .json::<JustError>()
+                    .map(|error| error.message)
+                    .unwrap_or_else(|_| status.to_string());
+                return Err(JustError {
+                    message: format!("Error occurred during chat creation. Response: {}", cause),
+                });
             }
         };
 
         let conversation: ConversationId = res.id;
-        let chat_message = ChatMessage::create(receiver.clone(), conversation.clone(), message);
-        client
+        let chat_message = ChatMessage::create_formatted(
+            receiver.clone(),
+            conversation.clone(),
+            message,
+            self.message_format,
+        );
+        let res = client
             .post(&format!(
                 "{}/{}/messages",
                 &make_api_url(&domain, "/toro/chat/api/v2/chats"),
@@ -69,17 +95,80 @@ impl JustClient {
             ))
             .bearer_auth(&*oauth_token)
             .json(&chat_message)
+            .send()?;
+        if !res.status().is_success() {
+            return Err(JustError {
+                message: format!(
+                    "Just didn't take the chat message, it replied with {}.",
+                    res.status()
+                ),
+            });
+        }
+        Ok(())
+    }
+
+    /// Fetches all messages other participants sent to the chatbot after
+    /// `since`, oldest first.
+    pub async fn fetch_messages(
+        &self,
+        since: &DateTime<Utc>,
+    ) -> Result<Vec<ReceivedChatMessage>, reqwest::Error> {
+        let client = Client::new();
+        let sender = self.sender.lock().await;
+        let domain = self.domain.lock().await;
+        let oauth_token = self.oauth_token.lock().await;
+
+        let chats_url = make_api_url(&domain, "/toro/chat/api/v2/chats");
+        let chats: ChatListResult = client
+            .get(&chats_url)
+            .bearer_auth(&*oauth_token)
+            .send()?
+            .json()?;
+
+        let mut messages = vec![];
+        for chat in chats.items {
+            let result: ChatMessageListResult = client
+                .get(&format!("{}/{}/messages", &chats_url, chat.id.value()))
+                .bearer_auth(&*oauth_token)
+                .send()?
+                .json()?;
+            messages.extend(result.items.into_iter().filter(|message| {
+                message.create_date > *since && message.author_id.to_profile_id() != *sender
+            }));
+        }
+
+        messages.sort_by_key(|message| message.create_date);
+        Ok(messages)
+    }
+
+    /// Lists the chats of the chatbot. Fails if Just is unreachable or rejects
+    /// the OAuth token.
+    pub async fn probe(&self) -> Result<(), String> {
+        let domain = self.domain.lock().await;
+        let oauth_token = self.oauth_token.lock().await;
+        if oauth_token.is_empty() {
+            return Err(String::from("The chatbot has no OAuth token."));
+        }
+
+        let response = Client::new()
+            .get(&make_api_url(&domain, "/toro/chat/api/v2/chats"))
+            .bearer_auth(&*oauth_token)
             .send()
-            .expect("Error while sending chat message request.")
-            .text()
-            .expect("Response could not be read.");
+            .map_err(|e| format!("Just isn't reachable. Cause: {}", e))?;
+        match response.status() {
+            status if status.is_success() => Ok(()),
+            reqwest::StatusCode::UNAUTHORIZED => {
+                Err(String::from("Just rejected the OAuth token."))
+            }
+            status => Err(format!("Just replied with {}.", status)),
+        }
     }
 
     // Error message is valid as long the access token, as it's related to the API request
     async fn request_users<'a, 'b>(
         &self,
         filter: &'b str,
-    ) -> Result<Option<ProfileId>, reqwest::Error> {
+    ) -> Result<Vec<Candidate<ProfileId>>, reqwest::Error> {
         let params: HashMap<&str, &str> = [("filter", filter)].iter().cloned().collect();
 
         let oauth_token = self.oauth_token.lock().await;
@@ -93,45 +182,94 @@ impl JustClient {
             .send()?
             .json()?;
 
-        let mut profiles: Vec<JustUserProfile> = result.items;
-        if !profiles.is_empty() && profiles.len() == 1 {
-            // call .remove, it allows us to take ownership and return the ProfileId struct
-            // another option would be to call nth(), which would also allow us to remove mut
-            debug!("Found exactly one user.");
-            return Ok(Some(profiles.remove(0).id.to_profile_id()));
-        } else {
-            info!(
-                "Too many results for user search '{}'. Expected to find exactly one result. Found {}.",
-                filter,
-                profiles.len()
-            );
-            return Ok(None);
-        }
+        Ok(result
+            .items
+            .into_iter()
+            .map(|profile| Candidate {
+                id: profile.id.to_profile_id(),
+                name: profile.name,
+            })
+            .collect())
     }
 
-    pub async fn search_profile_id(&self, change_owner: &str) -> Result<Option<ProfileId>, String> {
-        let search = [
-            change_owner,
-            user_firstname(change_owner),
-            user_lastname(change_owner),
-        ];
-        for s in search.iter() {
-            let request_result = self.request_users(s).await;
+    /// Searches the profile of a Gerrit account, given as `Name <email>`. Tries
+    /// the email first, then the name. See `GerritAccount::search_terms`.
+    ///
+    /// If no search finds exactly one profile, the candidates of the first
+    /// search with several results are returned, so an admin can pick one.
+    pub async fn search_profile_id(&self, change_owner: &str) -> Result<SyncRecord, String> {
+        let account = GerritAccount::from(change_owner);
+        let mut ambiguous = None;
+        for (strategy, s) in account.search_terms() {
+            let request_result = self.request_users(&s).await;
             if request_result.is_err() {
                 error!("API request failed.");
                 return Err(String::from("Error when searching for user."));
             }
 
-            let result = request_result.unwrap();
-            if result.is_some() {
-                debug!("Found search result for '{}'.", s);
-                return Ok(result);
+            let mut candidates = request_result.unwrap();
+            match candidates.len() {
+                0 => debug!("Search for user with search '{}' returned no result.", s),
+                1 => {
+                    debug!("Found search result for '{}' by {:?}.", s, strategy);
+                    let profile_id = candidates.remove(0).id;
+                    return Ok(SyncRecord::new(
+                        Synchronization::Some(profile_id),
+                        Some(strategy),
+                    ));
+                }
+                n => {
+                    info!(
+                        "Too many results for user search '{}'. Expected to find exactly one result. Found {}.",
+                        s, n
+                    );
+                    if ambiguous.is_none() {
+                        ambiguous = Some(SyncRecord::new(
+                            Synchronization::Ambiguous(candidates),
+                            Some(strategy),
+                        ));
+                    }
+                }
             }
-
-            debug!("Search for user with search '{}' returned no result.", s);
         }
 
-        return Ok(None);
+        Ok(ambiguous.unwrap_or_else(|| SyncRecord::new(Synchronization::None, None)))
+    }
+}
+
+/// Counts a request to the Just API, how long it took and whether it failed.
+async fn record_request<A: Actor>(
+    system: &ActorAssistant<A>,
+    operation: &'static str,
+    start: Instant,
+    failed: bool,
+) {
+    system
+        .send_to_service::<MetricsCollector, _>(RecordMetric(Metric::JustRequest {
+            operation,
+            duration: start.elapsed(),
+            failed,
+        }))
+        .await;
+}
+
+/// What the audit log and the metrics record for a notification to
+/// `receiver`, given how sending it went.
+fn delivery_outcome(receiver: ProfileId, result: Result<(), JustError>) -> (AuditOutcome, Metric) {
+    match result {
+        Ok(()) => (
+            AuditOutcome::Delivered {
+                profile_id: receiver,
+            },
+            Metric::NotificationSent,
+        ),
+        Err(error) => (
+            AuditOutcome::DeliveryFailed {
+                profile_id: receiver,
+                error: error.message,
+            },
+            Metric::NotificationFailed,
+        ),
     }
 }
 
@@ -139,7 +277,7 @@ fn make_api_url(domain: &str, path: &str) -> String {
     format!("https://{}/{}", domain, path)
 }
 
-pub async fn get_oauth_token(params: &ConnectionParameters) -> String {
+pub async fn get_oauth_token(params: &Tenant) -> Result<String, reqwest::Error> {
     let mut map = HashMap::new();
     map.insert("client_id", params.client_id.as_str());
     map.insert("grant_type", "password");
@@ -149,44 +287,97 @@ pub async fn get_oauth_token(params: &ConnectionParameters) -> String {
     let response: AccesTokenResponse = reqwest::blocking::Client::new()
         .post(&make_api_url(&params.domain, "/toro/oauth/token"))
         .query(&map)
-        .send()
-        .expect("Could not send access token request.")
-        .json()
-        .expect("Requesting access token didn't return no JSON.");
+        .send()?
+        .json()?;
 
-    response.access_token
+    Ok(response.access_token)
 }
 
 #[async_trait::async_trait]
-impl Service for JustClient {
-    async fn initialize(system: &ServiceAssistant<Self>) -> (Self, ServiceConfiguration) {
+impl Actor for JustClient {
+    type Id = TenantId;
+
+    async fn activate(id: Self::Id, system: &ActorAssistant<Self>) -> Self {
         let state: ConnectionParameters = system
             .call_actor::<AppState, GetAppState>(0, GetAppState {})
             .await
             .expect("Could not retrieve application state.");
+        let tenant = state
+            .tenant(&id)
+            .unwrap_or_else(|| panic!("Unknown tenant '{}'.", id));
 
-        print!("JustClient actor is starting. Requesting OAuth token...");
+        print!(
+            "JustClient actor of tenant '{}' is starting. Requesting OAuth token...",
+            id
+        );
         let receive_oauth_token_start = Instant::now();
-        let oauth_token = get_oauth_token(&state).await;
+        // Without a token every request fails, which the readiness probe
+        // reports, instead of the actor panicking.
+        let oauth_token = get_oauth_token(&tenant).await.unwrap_or_else(|e| {
+            error!(
+                "Couldn't get an OAuth token for tenant '{}'. Cause: {}",
+                id, e
+            );
+            String::new()
+        });
         println!("{}ms.", receive_oauth_token_start.elapsed().as_millis());
         info!(
-            "JustClient actor is starting. Requesting OAuth token took {}ms.",
+            "JustClient actor of tenant '{}' is starting. Requesting OAuth token took {}ms.",
+            id,
             receive_oauth_token_start.elapsed().as_millis()
         );
 
         // TODO Try out the non blocking reqwest
-        let just_client =
-            JustClient::new(state.profile_id.clone(), state.domain.clone(), oauth_token);
-        (just_client, ServiceConfiguration::default())
+        JustClient::new(
+            tenant.profile_id,
+            tenant.domain,
+            oauth_token,
+            tenant.message_format,
+        )
+    }
+}
+
+#[async_trait::async_trait]
+impl Respond<SearchProfileId> for JustClient {
+    type Response = Result<SyncRecord, String>;
+
+    async fn handle(
+        &mut self,
+        message: SearchProfileId,
+        system: &ActorAssistant<Self>,
+    ) -> Self::Response {
+        let start = Instant::now();
+        let result = self.search_profile_id(&message.0).await;
+        record_request(system, "search_profile_id", start, result.is_err()).await;
+        result
     }
 }
 
 #[async_trait::async_trait]
-impl Serve<SearchProfileId> for JustClient {
-    type Response = Result<Option<ProfileId>, String>;
+impl Respond<FetchChatMessages> for JustClient {
+    type Response = Result<Vec<ReceivedChatMessage>, String>;
 
-    async fn handle(&self, message: SearchProfileId, _: &ServiceAssistant<Self>) -> Self::Response {
-        self.search_profile_id(&message.0).await
+    async fn handle(
+        &mut self,
+        message: FetchChatMessages,
+        system: &ActorAssistant<Self>,
+    ) -> Self::Response {
+        let start = Instant::now();
+        let result = self.fetch_messages(&message.0).await;
+        record_request(system, "fetch_messages", start, result.is_err()).await;
+        result.map_err(|e| format!("Error when fetching chat messages. Cause: {}", e))
+    }
+}
+
+#[async_trait::async_trait]
+impl Respond<ProbeJustApi> for JustClient {
+    type Response = Result<(), String>;
+
+    async fn handle(&mut self, _: ProbeJustApi, system: &ActorAssistant<Self>) -> Self::Response {
+        let start = Instant::now();
+        let result = self.probe().await;
+        record_request(system, "probe", start, result.is_err()).await;
+        result
     }
 }
 
@@ -195,9 +386,117 @@ impl Serve<SearchProfileId> for JustClient {
 ///
 /// However, should an error occur this method will log a warning.
 #[async_trait::async_trait]
-impl Listen<SendChatMessage> for JustClient {
-    async fn handle(&self, message: SendChatMessage, _: &ServiceAssistant<Self>) {
+impl Receive<SendChatMessage> for JustClient {
+    async fn handle(&mut self, message: SendChatMessage, system: &ActorAssistant<Self>) {
         debug!("Sending '{}' a chat message '{}'.", &message.0, &message.1);
-        self.send_chat_message(&message.0, &message.1).await;
+        let start = Instant::now();
+        let result = self.send_chat_message(&message.0, &message.1).await;
+        record_request(system, "send_chat_message", start, result.is_err()).await;
+        if let Err(e) = result {
+            error!("{}", e.message);
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Receive<SendInvalidSettingsNotice> for JustClient {
+    async fn handle(&mut self, message: SendInvalidSettingsNotice, system: &ActorAssistant<Self>) {
+        let SendInvalidSettingsNotice(tenant, user, receiver, chat_message) = message;
+        let start = Instant::now();
+        let result = self.send_chat_message(&receiver, &chat_message).await;
+        record_request(system, "send_chat_message", start, result.is_err()).await;
+        match result {
+            Ok(()) => {
+                system
+                    .send_to_actor::<UserServiceClient, _>(tenant, InvalidSettingsNoticeSent(user))
+                    .await
+            }
+            Err(e) => error!(
+                "Couldn't tell '{}' about invalid settings: {}",
+                user, e.message
+            ),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Receive<DeliverNotification> for JustClient {
+    async fn handle(&mut self, message: DeliverNotification, system: &ActorAssistant<Self>) {
+        let DeliverNotification(receiver, chat_message, log, entry) = message;
+        debug!(
+            "Sending '{}' a notification '{}'.",
+            &receiver, &chat_message
+        );
+        let start = Instant::now();
+        let result = self.send_chat_message(&receiver, &chat_message).await;
+        record_request(system, "send_chat_message", start, result.is_err()).await;
+
+        if let Err(error) = &result {
+            error!("{}", error.message);
+        }
+
+        let (outcome, metric) = delivery_outcome(receiver, result);
+        system
+            .send_to_service::<MetricsCollector, _>(RecordMetric(metric))
+            .await;
+        system
+            .send_to_service::<AuditRecorder, _>(RecordAudit(log, entry.with_outcome(outcome)))
+            .await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{delivery_outcome, JustClient};
+    use crate::{
+        service::{AuditOutcome, JustError, Metric},
+        types::{MessageFormat, ProfileId, RichMessage},
+    };
+
+    #[test]
+    fn fails_to_send_without_just() {
+        // Nothing listens on the discard port.
+        let client = JustClient::new(
+            ProfileId(1),
+            String::from("127.0.0.1:9"),
+            String::from("token"),
+            MessageFormat::PlainText,
+        );
+        let result = futures::executor::block_on(
+            client.send_chat_message(&ProfileId(2), &RichMessage::text("Hello")),
+        );
+
+        assert!(result
+            .unwrap_err()
+            .message
+            .starts_with("Request to Just failed."));
+    }
+
+    #[test]
+    fn counts_failed_notifications() {
+        assert_eq!(
+            delivery_outcome(ProfileId(2), Ok(())),
+            (
+                AuditOutcome::Delivered {
+                    profile_id: ProfileId(2)
+                },
+                Metric::NotificationSent
+            )
+        );
+        assert_eq!(
+            delivery_outcome(
+                ProfileId(2),
+                Err(JustError {
+                    message: String::from("Request to Just failed.")
+                })
+            ),
+            (
+                AuditOutcome::DeliveryFailed {
+                    profile_id: ProfileId(2),
+                    error: String::from("Request to Just failed.")
+                },
+                Metric::NotificationFailed
+            )
+        );
     }
 }
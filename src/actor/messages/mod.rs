@@ -16,19 +16,25 @@ mod app_state {
     /// The call will panic if there is no valid state set.
     #[derive(Debug)]
     pub struct GetAppState;
+
+    /// The actor returns whether the application state is set, e.g. to check
+    /// if the server is ready.
+    #[derive(Debug)]
+    pub struct IsAppStateSet;
 }
 
 mod user {
 
-    use crate::types::{GerritUsername, ProfileId, Synchronization};
+    use crate::types::{GerritUsername, Settings, SyncRecord, TenantId};
+    use std::fmt;
 
     /// A message that retrieves the profile id and settings mapped to a particular
-    /// `GerritUsername`.
+    /// `GerritUsername` of a tenant.
     ///
     /// In case no data is available, it will be tried to request it using the fully
     /// qualified name of the user.
     #[derive(Clone, Debug)]
-    pub struct GetUserData(pub GerritUsername, pub String);
+    pub struct GetUserData(pub GerritUsername, pub String, pub TenantId);
 
     /// Load a cache with user mapping data.
     #[derive(Debug)]
@@ -37,7 +43,9 @@ mod user {
     /// Actor executes `LoadSettings` and returns settings for a given username.
     ///
     /// If no settings for a given username are found, the actor will return
-    /// a default user setting.
+    /// a default user setting. If the settings are invalid, the actor will
+    /// return default settings and the cause. The cause is only returned until
+    /// the user was told about it, see `InvalidSettingsNoticeSent`.
     #[derive(Clone, Debug)]
     pub struct LoadSettings(pub GerritUsername);
 
@@ -47,26 +55,77 @@ mod user {
         }
     }
 
+    /// Changes the settings of a user, e.g. by a `ChatCommand` or the form of
+    /// the settings page, and saves them. The actor returns the new settings.
+    ///
+    /// Fails if the current settings can't be loaded, so a broken settings
+    /// file isn't replaced with default settings.
+    pub struct UpdateSettings(
+        pub GerritUsername,
+        pub Box<dyn Fn(Settings) -> Settings + Send>,
+    );
+
+    impl fmt::Debug for UpdateSettings {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "UpdateSettings({:?}, ..)", self.0)
+        }
+    }
+
+    /// Replaces the settings of a user, e.g. with settings edited in the web UI.
+    #[derive(Debug)]
+    pub struct SaveSettings(pub GerritUsername, pub Settings);
+
+    /// The user was told that their settings are invalid. `LoadSettings` stops
+    /// returning the cause until the settings are valid again.
+    #[derive(Debug)]
+    pub struct InvalidSettingsNoticeSent(pub GerritUsername);
+
+    /// The actor returns the settings of a user as they are stored, `None` if
+    /// there are none. Unlike `LoadSettings`, there is no fallback to default
+    /// settings and nothing is written, e.g. outdated settings aren't migrated.
+    #[derive(Clone, Debug)]
+    pub struct ReadSettings(pub GerritUsername);
+
+    /// The actor returns the users who watch the change with the given number
+    /// of the project, see `Settings::watches`.
+    #[derive(Clone, Debug)]
+    pub struct GetWatchers(pub String, pub String);
+
     // Save a ProfileId mapping for a GerritUsername.
     //
     // This call will fail silently (e.g. we can't write a synchronisation file) and
     // won't provide a response for the sake of performance.
     #[derive(Debug)]
-    pub struct SetProfileIdMapping(pub GerritUsername, pub Synchronization<ProfileId>);
+    pub struct SetProfileIdMapping(pub GerritUsername, pub SyncRecord);
 }
 
 mod just {
 
     use super::user::GetUserData;
-    use crate::types::{GerritUsername, ProfileId};
+    use crate::{
+        service::{AuditEntry, AuditLog},
+        types::{GerritUsername, ProfileId, RichMessage, Synchronization, TenantId},
+    };
+    use chrono::{DateTime, Utc};
 
+    /// The actor searches Just for the profile of a Gerrit account, given as
+    /// `Name <email>`. Returns the mapping and how it was found. The mapping
+    /// lists the candidates if several profiles match.
     #[derive(Debug)]
     pub struct SearchProfileId(pub String);
 
+    /// The actor returns all chat messages other users sent to the chatbot
+    /// after the given point in time, oldest first.
+    #[derive(Debug)]
+    pub struct FetchChatMessages(pub DateTime<Utc>);
+
     /// Message will trigger a Chat message to be send to the given recipient.
+    /// The actor renders it in the richest format of the tenant's chat.
     #[derive(Debug)]
-    pub struct SendChatMessage(pub ProfileId, pub String);
+    pub struct SendChatMessage(pub ProfileId, pub RichMessage);
 
+    /// The actor returns the mapping of a user, resolving it via the Just API
+    /// using the given name if there is none yet.
     #[derive(Debug)]
     pub struct ResolveToProfileId(pub GerritUsername, pub String);
 
@@ -75,8 +134,127 @@ mod just {
             ResolveToProfileId(origin.0, origin.1)
         }
     }
+
+    /// The actor returns the `GerritUsername` that is mapped to the given
+    /// `ProfileId`, if any. Only known mappings are used, the Just API isn't
+    /// asked.
+    #[derive(Debug)]
+    pub struct ResolveToGerritUsername(pub ProfileId);
+
+    /// The actor returns all known `ProfileId` mappings.
+    #[derive(Debug)]
+    pub struct GetProfileIdMappings;
+
+    /// Replaces the `ProfileId` mapping of a user in the cache and on disk,
+    /// e.g. to fix a wrong mapping by hand. The actor delivers the pending
+    /// notifications of the user and returns how many were delivered.
+    #[derive(Debug)]
+    pub struct OverrideProfileIdMapping(pub GerritUsername, pub Synchronization<ProfileId>);
+
+    /// Like `OverrideProfileIdMapping`, but fails unless the `ProfileId` is one
+    /// of the candidates of an ambiguous mapping.
+    #[derive(Debug)]
+    pub struct PickProfileId(pub GerritUsername, pub ProfileId);
+
+    /// Resolves the `ProfileId` of a user again using the given name, if no
+    /// `ProfileId` is mapped yet. Existing mappings are returned as they are.
+    #[derive(Debug)]
+    pub struct ReresolveProfileId(pub GerritUsername, pub String);
+
+    /// Reloads the `ProfileId` mappings of the given users from disk, e.g.
+    /// after their sync files were changed by hand. Users without a sync file
+    /// are forgotten, so they're resolved again.
+    #[derive(Debug)]
+    pub struct ReloadProfileIdMappings(pub Vec<GerritUsername>);
+
+    /// Makes a cheap request to the Just API, to check that the OAuth token of
+    /// the chatbot is still accepted.
+    #[derive(Debug)]
+    pub struct ProbeJustApi;
+
+    /// Sends a notification like `SendChatMessage` and records in the audit log
+    /// whether it was delivered.
+    #[derive(Debug)]
+    pub struct DeliverNotification(pub ProfileId, pub RichMessage, pub AuditLog, pub AuditEntry);
+
+    /// Tells a user that their settings are invalid. Once the message is sent,
+    /// the actor sends `InvalidSettingsNoticeSent` to the `UserServiceClient`
+    /// of the given tenant.
+    #[derive(Debug)]
+    pub struct SendInvalidSettingsNotice(
+        pub TenantId,
+        pub GerritUsername,
+        pub ProfileId,
+        pub RichMessage,
+    );
+
+    /// Keeps a notification for a user with an ambiguous mapping, until an
+    /// admin picks the right profile. The entry is recorded in the audit log
+    /// once it is delivered.
+    #[derive(Debug)]
+    pub struct QueueNotification(pub GerritUsername, pub RichMessage, pub AuditEntry);
+
+    /// The actor returns all users with an ambiguous mapping, their candidates
+    /// and how many notifications are pending.
+    #[derive(Debug)]
+    pub struct GetAmbiguousMappings;
+}
+
+mod history {
+
+    use crate::types::GerritTrigger;
+
+    /// Remembers a trigger received from Gerrit.
+    #[derive(Debug)]
+    pub struct RecordTrigger(pub GerritTrigger);
+
+    /// The actor returns the remembered triggers and when they were received,
+    /// newest first.
+    #[derive(Debug)]
+    pub struct GetRecentTriggers;
+
+    /// Remembers that the trigger with the id the hook gave it was handled.
+    #[derive(Debug)]
+    pub struct RecordHandledTrigger(pub String);
+
+    /// The actor returns whether the trigger with the id was handled.
+    #[derive(Debug)]
+    pub struct WasTriggerHandled(pub String);
+}
+
+mod audit {
+
+    use crate::service::{AuditEntry, AuditLog};
+
+    /// Appends an entry to the audit log of a tenant.
+    #[derive(Debug)]
+    pub struct RecordAudit(pub AuditLog, pub AuditEntry);
+}
+
+mod metrics {
+
+    use crate::service::Metric;
+
+    /// Counts something the server did, see `Metrics`.
+    #[derive(Debug)]
+    pub struct RecordMetric(pub Metric);
+
+    /// The actor returns the metrics in the Prometheus text format.
+    #[derive(Debug)]
+    pub struct GetMetrics;
 }
 
-pub use app_state::{GetAppState, SetAppState};
-pub use just::{ResolveToProfileId, SearchProfileId, SendChatMessage};
-pub use user::{GetUserData, InitializeCache, LoadSettings, SetProfileIdMapping};
+pub use app_state::{GetAppState, IsAppStateSet, SetAppState};
+pub use audit::RecordAudit;
+pub use history::{GetRecentTriggers, RecordHandledTrigger, RecordTrigger, WasTriggerHandled};
+pub use just::{
+    DeliverNotification, FetchChatMessages, GetAmbiguousMappings, GetProfileIdMappings,
+    OverrideProfileIdMapping, PickProfileId, ProbeJustApi, QueueNotification,
+    ReloadProfileIdMappings, ReresolveProfileId, ResolveToGerritUsername, ResolveToProfileId,
+    SearchProfileId, SendChatMessage, SendInvalidSettingsNotice,
+};
+pub use metrics::{GetMetrics, RecordMetric};
+pub use user::{
+    GetUserData, GetWatchers, InitializeCache, InvalidSettingsNoticeSent, LoadSettings,
+    ReadSettings, SaveSettings, SetProfileIdMapping, UpdateSettings,
+};
@@ -0,0 +1,41 @@
+use acteur::{Listen, Serve, Service, ServiceAssistant, ServiceConfiguration};
+use std::sync::Mutex;
+
+use crate::{
+    actor::messages::{GetMetrics, RecordMetric},
+    service::Metrics,
+};
+
+///
+/// The service counts triggers, notifications and requests to the Just API for
+/// `/metrics`. The metrics are kept in memory only, they start over with every
+/// restart.
+///
+#[derive(Debug)]
+pub struct MetricsCollector(Mutex<Metrics>);
+
+#[async_trait::async_trait]
+impl Service for MetricsCollector {
+    async fn initialize(_: &ServiceAssistant<Self>) -> (Self, ServiceConfiguration) {
+        (
+            MetricsCollector(Mutex::new(Metrics::default())),
+            ServiceConfiguration::default(),
+        )
+    }
+}
+
+#[async_trait::async_trait]
+impl Listen<RecordMetric> for MetricsCollector {
+    async fn handle(&self, message: RecordMetric, _: &ServiceAssistant<Self>) {
+        self.0.lock().unwrap().record(message.0);
+    }
+}
+
+#[async_trait::async_trait]
+impl Serve<GetMetrics> for MetricsCollector {
+    type Response = String;
+
+    async fn handle(&self, _: GetMetrics, _: &ServiceAssistant<Self>) -> Self::Response {
+        self.0.lock().unwrap().render()
+    }
+}
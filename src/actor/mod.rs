@@ -1,12 +1,18 @@
 mod app_state;
+mod audit_recorder;
 mod controller_client;
 mod just_client;
+mod metrics_collector;
 mod resolver_service_client;
+mod trigger_history;
 mod user_service_client;
 
 pub mod messages;
 pub use app_state::AppState;
+pub use audit_recorder::AuditRecorder;
 pub use controller_client::ControllerClient;
 pub use just_client::JustClient;
+pub use metrics_collector::MetricsCollector;
 pub use resolver_service_client::ResolverClient;
+pub use trigger_history::TriggerHistory;
 pub use user_service_client::UserServiceClient;
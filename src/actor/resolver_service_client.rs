@@ -1,54 +1,379 @@
-use acteur::{Serve, Service, ServiceAssistant, ServiceConfiguration};
+use acteur::{Actor, ActorAssistant, Receive, Respond};
+use chrono::Duration;
 use futures::lock::{Mutex, MutexGuard};
 use std::collections::HashMap;
 
 use crate::{
     actor::{
-        messages::{GetAppState, InitializeCache, ResolveToProfileId},
-        AppState, UserServiceClient,
+        messages::{
+            DeliverNotification, GetAmbiguousMappings, GetAppState, GetProfileIdMappings,
+            InitializeCache, OverrideProfileIdMapping, PickProfileId, QueueNotification,
+            ReloadProfileIdMappings, ReresolveProfileId, ResolveToGerritUsername,
+            ResolveToProfileId, SetProfileIdMapping,
+        },
+        AppState, JustClient, UserServiceClient,
+    },
+    service::{
+        DirectoryEntry, DirectoryResolver, PendingNotification, ProfileIdResolver, ResolverService,
+        UserDirectory,
+    },
+    types::{
+        Candidate, GerritUsername, ProfileId, ResolutionStrategy, SyncRecord, Synchronization,
+        TenantId,
     },
-    types::{GerritUsername, ProfileId, Synchronization},
-    service::{ProfileIdResolver, ResolverService},
 };
+use std::path::Path;
 
+/// Resolves users via the user directory, if one is configured, and the Just
+/// API. See `DirectoryResolver`. There is an actor per tenant, it only knows
+/// the users of the tenant.
 #[derive(Debug)]
-pub struct ResolverClient(Mutex<ProfileIdResolver>);
+pub struct ResolverClient(Mutex<ProfileIdResolver>, UserDirectory);
+
+impl ResolverClient {
+    async fn resolve(
+        &self,
+        instance: &mut ProfileIdResolver,
+        username: &GerritUsername,
+        name: &str,
+    ) -> Result<Option<ProfileId>, String> {
+        DirectoryResolver {
+            directory: &self.1,
+            inner: instance,
+        }
+        .resolve(username, name)
+        .await
+    }
+}
 
 #[async_trait::async_trait]
-impl Service for ResolverClient {
-    async fn initialize(system: &ServiceAssistant<Self>) -> (Self, ServiceConfiguration) {
+impl Actor for ResolverClient {
+    type Id = TenantId;
+
+    async fn activate(id: Self::Id, system: &ActorAssistant<Self>) -> Self {
         let state = system
             .call_actor::<AppState, GetAppState>(0, GetAppState {})
             .await
             .expect("Couldn't retrieve application state.");
-        let cache: HashMap<GerritUsername, Synchronization<ProfileId>> = system
-            .call_service::<UserServiceClient, InitializeCache>(InitializeCache(state.data_dir))
+        let tenant = state
+            .tenant(&id)
+            .unwrap_or_else(|| panic!("Unknown tenant '{}'.", id));
+        let unresolved_ttl = match state.unresolved_ttl {
+            0 => None,
+            hours => Some(Duration::hours(hours as i64)),
+        };
+        let directory = match &tenant.user_directory {
+            Some(path) => UserDirectory::load(Path::new(path)).unwrap_or_else(|e| {
+                error!("{} Continuing without user directory.", e);
+                UserDirectory::default()
+            }),
+            None => UserDirectory::default(),
+        };
+        let cache: HashMap<GerritUsername, SyncRecord> = system
+            .call_actor::<UserServiceClient, InitializeCache>(
+                id.clone(),
+                InitializeCache(tenant.data_dir.clone()),
+            )
             .await
             .unwrap_or_else(|_| {
                 warn!("ResolverClient could not get the sync data. Using empty HashMap. Performance may suffer.");
                 HashMap::new()
             });
 
-        let service = Mutex::new(ProfileIdResolver {
+        let mappings = cache
+            .iter()
+            .map(|(username, record)| (username.clone(), record.mapping.clone()))
+            .collect();
+        for conflict in directory.conflicts(&mappings) {
+            warn!("{} Using the directory.", conflict);
+        }
+
+        let service = Mutex::new(ProfileIdResolver::new(
             cache,
-            acteur: system.clone(),
-        });
+            system.clone(),
+            id,
+            &tenant.data_dir,
+            unresolved_ttl,
+        ));
 
-        (ResolverClient(service), ServiceConfiguration::default())
+        ResolverClient(service, directory)
     }
 }
 
 #[async_trait::async_trait]
-impl Serve<ResolveToProfileId> for ResolverClient {
-    type Response = Option<ProfileId>;
+impl Respond<ResolveToProfileId> for ResolverClient {
+    type Response = Synchronization<ProfileId>;
 
     async fn handle(
-        &self,
+        &mut self,
         message: ResolveToProfileId,
-        _system: &ServiceAssistant<Self>,
+        _system: &ActorAssistant<Self>,
+    ) -> Self::Response {
+        let mut instance: MutexGuard<ProfileIdResolver> = self.0.lock().await;
+        match self.resolve(&mut instance, &message.0, &message.1).await {
+            Ok(Some(profile_id)) => Synchronization::Some(profile_id),
+            Ok(None) => instance.lookup_cache(&message.0),
+            Err(_) => Synchronization::NotMappedYet,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Respond<ResolveToGerritUsername> for ResolverClient {
+    type Response = Option<GerritUsername>;
+
+    async fn handle(
+        &mut self,
+        message: ResolveToGerritUsername,
+        _system: &ActorAssistant<Self>,
+    ) -> Self::Response {
+        let instance: MutexGuard<ProfileIdResolver> = self.0.lock().await;
+        self.1
+            .username_for(&message.0)
+            .or_else(|| instance.username_for(&message.0))
+    }
+}
+
+#[async_trait::async_trait]
+impl Respond<GetProfileIdMappings> for ResolverClient {
+    type Response = HashMap<GerritUsername, Synchronization<ProfileId>>;
+
+    async fn handle(
+        &mut self,
+        _: GetProfileIdMappings,
+        _system: &ActorAssistant<Self>,
+    ) -> Self::Response {
+        let instance: MutexGuard<ProfileIdResolver> = self.0.lock().await;
+        let mut mappings: HashMap<GerritUsername, Synchronization<ProfileId>> = instance
+            .cache
+            .iter()
+            .map(|(username, record)| (username.clone(), record.mapping.clone()))
+            .collect();
+        for (username, entry) in self.1.entries() {
+            if let DirectoryEntry::ProfileId(profile_id) = entry {
+                mappings.insert(username.clone(), Synchronization::Some(profile_id.clone()));
+            }
+        }
+        mappings
+    }
+}
+
+#[async_trait::async_trait]
+impl Respond<OverrideProfileIdMapping> for ResolverClient {
+    type Response = usize;
+
+    async fn handle(
+        &mut self,
+        message: OverrideProfileIdMapping,
+        system: &ActorAssistant<Self>,
+    ) -> Self::Response {
+        let mut instance: MutexGuard<ProfileIdResolver> = self.0.lock().await;
+        override_mapping(&mut instance, message.0, message.1, system).await
+    }
+}
+
+#[async_trait::async_trait]
+impl Respond<PickProfileId> for ResolverClient {
+    type Response = Result<usize, String>;
+
+    async fn handle(
+        &mut self,
+        message: PickProfileId,
+        system: &ActorAssistant<Self>,
     ) -> Self::Response {
         let mut instance: MutexGuard<ProfileIdResolver> = self.0.lock().await;
-        let fut = instance.resolve(&message.0, &message.1).await;
-        fut.ok().flatten()
+        if !instance.lookup_cache(&message.0).is_candidate(&message.1) {
+            return Err(format!(
+                "{} is no candidate for '{}'.",
+                message.1, message.0
+            ));
+        }
+
+        let mapping = Synchronization::Some(message.1);
+        Ok(override_mapping(&mut instance, message.0, mapping, system).await)
+    }
+}
+
+#[async_trait::async_trait]
+impl Receive<QueueNotification> for ResolverClient {
+    async fn handle(&mut self, message: QueueNotification, _system: &ActorAssistant<Self>) {
+        let mut instance: MutexGuard<ProfileIdResolver> = self.0.lock().await;
+        let QueueNotification(username, message, entry) = message;
+        info!(
+            "Keeping notification for '{}' until the mapping is fixed.",
+            username
+        );
+        instance
+            .pending
+            .push(&username, PendingNotification { message, entry });
+    }
+}
+
+#[async_trait::async_trait]
+impl Receive<ReloadProfileIdMappings> for ResolverClient {
+    async fn handle(&mut self, message: ReloadProfileIdMappings, system: &ActorAssistant<Self>) {
+        let state = match system
+            .call_actor::<AppState, GetAppState>(0, GetAppState {})
+            .await
+        {
+            Ok(state) => state,
+            Err(e) => {
+                error!(
+                    "Couldn't retrieve application state to reload mappings: {}",
+                    e
+                );
+                return;
+            }
+        };
+        let mut instance: MutexGuard<ProfileIdResolver> = self.0.lock().await;
+        let data_dir = match state.tenant(&instance.tenant) {
+            Some(tenant) => tenant.data_dir,
+            None => return,
+        };
+        let mut on_disk: HashMap<GerritUsername, SyncRecord> = match system
+            .call_actor::<UserServiceClient, InitializeCache>(
+                instance.tenant.clone(),
+                InitializeCache(data_dir),
+            )
+            .await
+        {
+            Ok(records) => records,
+            Err(e) => {
+                error!("Couldn't read the sync data to reload mappings: {}", e);
+                return;
+            }
+        };
+
+        for username in message.0 {
+            match on_disk.remove(&username) {
+                Some(record) => {
+                    if instance.cache.get(&username) == Some(&record) {
+                        continue;
+                    }
+                    info!(
+                        "Reloaded ProfileId mapping of '{}' from disk: {:?}.",
+                        username, record.mapping
+                    );
+                    instance.cache.insert(username.clone(), record.clone());
+                    if let Synchronization::Some(profile_id) = record.mapping {
+                        deliver_pending(&mut instance, &username, profile_id, system).await;
+                    }
+                }
+                None => {
+                    if instance.cache.remove(&username).is_some() {
+                        info!(
+                            "Forgot ProfileId mapping of '{}', its sync file was removed.",
+                            username
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Respond<GetAmbiguousMappings> for ResolverClient {
+    type Response = Vec<(GerritUsername, Vec<Candidate<ProfileId>>, usize)>;
+
+    async fn handle(
+        &mut self,
+        _: GetAmbiguousMappings,
+        _system: &ActorAssistant<Self>,
+    ) -> Self::Response {
+        let instance: MutexGuard<ProfileIdResolver> = self.0.lock().await;
+        instance
+            .cache
+            .iter()
+            .filter_map(|(username, record)| match &record.mapping {
+                Synchronization::Ambiguous(candidates) => {
+                    let pending = instance.pending.count(username);
+                    Some((username.clone(), candidates.clone(), pending))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Saves a mapping set by an admin and delivers the pending notifications of
+/// the user, if the user has a profile now. Returns how many were delivered.
+async fn override_mapping(
+    instance: &mut ProfileIdResolver,
+    username: GerritUsername,
+    mapping: Synchronization<ProfileId>,
+    system: &ActorAssistant<ResolverClient>,
+) -> usize {
+    info!(
+        "Overriding ProfileId mapping of '{}' with {:?}.",
+        username, mapping
+    );
+    let record = SyncRecord::new(mapping.clone(), Some(ResolutionStrategy::Manual));
+    instance.cache.insert(username.clone(), record.clone());
+    system
+        .send_to_actor::<UserServiceClient, SetProfileIdMapping>(
+            instance.tenant.clone(),
+            SetProfileIdMapping(username.clone(), record),
+        )
+        .await;
+
+    match mapping {
+        Synchronization::Some(profile_id) => {
+            deliver_pending(instance, &username, profile_id, system).await
+        }
+        _ => 0,
+    }
+}
+
+async fn deliver_pending(
+    instance: &mut ProfileIdResolver,
+    username: &GerritUsername,
+    profile_id: ProfileId,
+    system: &ActorAssistant<ResolverClient>,
+) -> usize {
+    let pending = instance.pending.take(username);
+    info!(
+        "Delivering {} pending notifications to '{}'.",
+        pending.len(),
+        username
+    );
+    for notification in &pending {
+        system
+            .send_to_actor::<JustClient, DeliverNotification>(
+                instance.tenant.clone(),
+                DeliverNotification(
+                    profile_id.clone(),
+                    notification.message.clone(),
+                    instance.audit_log.clone(),
+                    notification.entry.clone(),
+                ),
+            )
+            .await;
+    }
+    pending.len()
+}
+
+#[async_trait::async_trait]
+impl Respond<ReresolveProfileId> for ResolverClient {
+    type Response = Result<Synchronization<ProfileId>, String>;
+
+    async fn handle(
+        &mut self,
+        message: ReresolveProfileId,
+        system: &ActorAssistant<Self>,
+    ) -> Self::Response {
+        let mut instance: MutexGuard<ProfileIdResolver> = self.0.lock().await;
+        if let Synchronization::Some(profile_id) = instance.lookup_cache(&message.0) {
+            return Ok(Synchronization::Some(profile_id));
+        }
+
+        instance.cache.remove(&message.0);
+        match self.resolve(&mut instance, &message.0, &message.1).await? {
+            Some(profile_id) => {
+                deliver_pending(&mut instance, &message.0, profile_id.clone(), system).await;
+                Ok(Synchronization::Some(profile_id))
+            }
+            None => Ok(instance.lookup_cache(&message.0)),
+        }
     }
 }
@@ -0,0 +1,79 @@
+use acteur::{Listen, Serve, Service, ServiceAssistant, ServiceConfiguration};
+use chrono::{DateTime, Utc};
+use std::{collections::VecDeque, sync::Mutex};
+
+use crate::{
+    actor::messages::{GetRecentTriggers, RecordHandledTrigger, RecordTrigger, WasTriggerHandled},
+    types::GerritTrigger,
+};
+
+/// How many triggers are kept. Older triggers are dropped.
+const CAPACITY: usize = 200;
+
+/// How many ids of handled triggers are kept. Older ids are dropped.
+const HANDLED_CAPACITY: usize = 1000;
+
+///
+/// The service remembers the most recent Gerrit triggers, e.g. to show users
+/// which events would have notified them, and the ids of the handled ones, so
+/// a trigger the hook spooled anyway isn't handled twice. The history is kept
+/// in memory only.
+///
+#[derive(Debug)]
+pub struct TriggerHistory(
+    Mutex<VecDeque<(DateTime<Utc>, GerritTrigger)>>,
+    Mutex<VecDeque<String>>,
+);
+
+#[async_trait::async_trait]
+impl Service for TriggerHistory {
+    async fn initialize(_: &ServiceAssistant<Self>) -> (Self, ServiceConfiguration) {
+        (
+            TriggerHistory(
+                Mutex::new(VecDeque::with_capacity(CAPACITY)),
+                Mutex::new(VecDeque::with_capacity(HANDLED_CAPACITY)),
+            ),
+            ServiceConfiguration::default(),
+        )
+    }
+}
+
+#[async_trait::async_trait]
+impl Listen<RecordTrigger> for TriggerHistory {
+    async fn handle(&self, message: RecordTrigger, _: &ServiceAssistant<Self>) {
+        let mut history = self.0.lock().unwrap();
+        if history.len() == CAPACITY {
+            history.pop_front();
+        }
+        history.push_back((Utc::now(), message.0));
+    }
+}
+
+#[async_trait::async_trait]
+impl Serve<GetRecentTriggers> for TriggerHistory {
+    type Response = Vec<(DateTime<Utc>, GerritTrigger)>;
+
+    async fn handle(&self, _: GetRecentTriggers, _: &ServiceAssistant<Self>) -> Self::Response {
+        self.0.lock().unwrap().iter().rev().cloned().collect()
+    }
+}
+
+#[async_trait::async_trait]
+impl Listen<RecordHandledTrigger> for TriggerHistory {
+    async fn handle(&self, message: RecordHandledTrigger, _: &ServiceAssistant<Self>) {
+        let mut handled = self.1.lock().unwrap();
+        if handled.len() == HANDLED_CAPACITY {
+            handled.pop_front();
+        }
+        handled.push_back(message.0);
+    }
+}
+
+#[async_trait::async_trait]
+impl Serve<WasTriggerHandled> for TriggerHistory {
+    type Response = bool;
+
+    async fn handle(&self, message: WasTriggerHandled, _: &ServiceAssistant<Self>) -> bool {
+        self.1.lock().unwrap().contains(&message.0)
+    }
+}
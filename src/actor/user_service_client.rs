@@ -1,81 +1,184 @@
 use crate::{
     actor::{
-        messages::{GetAppState, InitializeCache, LoadSettings, SetProfileIdMapping},
+        messages::{
+            GetAppState, GetWatchers, InitializeCache, InvalidSettingsNoticeSent, LoadSettings,
+            ReadSettings, SaveSettings, SetProfileIdMapping, UpdateSettings,
+        },
         AppState,
     },
-    types::{ConnectionParameters, GerritUsername, ProfileId, Settings, Synchronization},
-    service::{FileBackedUserService, UserService},
+    default::default_settings,
+    types::{
+        ConnectionParameters, GerritUsername, PathToUserData, Settings, Storage, SyncRecord,
+        TenantId,
+    },
+    service::{FileBackedUserService, SettingsError, SqliteUserService, UserService},
+};
+use acteur::{Actor, ActorAssistant, Receive, Respond};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
 };
-use acteur::{Listen, Serve, Service, ServiceAssistant, ServiceConfiguration};
-use std::{collections::HashMap, sync::Mutex};
 
 ///
-/// The service is responsible for common interactions with user objects.
+/// The actor is responsible for common interactions with user objects. There is
+/// an actor per tenant, it stores the users in the data directory of the
+/// tenant.
 ///
 #[derive(Debug)]
 pub struct UserServiceClient {
-    service: Mutex<FileBackedUserService>,
+    service: Mutex<Box<dyn UserService + Send>>,
+    // Users with an invalid settings file, that have been told about it already.
+    invalid_settings: Mutex<HashSet<GerritUsername>>,
 }
 
 #[async_trait::async_trait]
-impl Service for UserServiceClient {
-    async fn initialize(system: &ServiceAssistant<Self>) -> (Self, ServiceConfiguration) {
+impl Actor for UserServiceClient {
+    type Id = TenantId;
+
+    async fn activate(id: Self::Id, system: &ActorAssistant<Self>) -> Self {
         let app_state: ConnectionParameters = system
             .call_actor::<AppState, _>(0, GetAppState {})
             .await
             .expect("AppState couldn't be retrieved.");
-        let service = FileBackedUserService {
-            data_dir: app_state.data_dir.clone(),
+        let data_dir = app_state
+            .tenant(&id)
+            .unwrap_or_else(|| panic!("Unknown tenant '{}'.", id))
+            .data_dir;
+        let service: Box<dyn UserService + Send> = match app_state.storage {
+            Storage::Files => Box::new(FileBackedUserService { data_dir }),
+            Storage::Sqlite => Box::new(
+                SqliteUserService::open(PathToUserData::database(&data_dir).as_path())
+                    .expect("Couldn't open the database."),
+            ),
         };
 
-        (
-            UserServiceClient {
-                service: Mutex::new(service),
-            },
-            ServiceConfiguration::default(),
-        )
+        UserServiceClient {
+            service: Mutex::new(service),
+            invalid_settings: Mutex::new(HashSet::new()),
+        }
     }
 }
 
 #[async_trait::async_trait]
-impl Serve<InitializeCache> for UserServiceClient {
-    type Response = HashMap<GerritUsername, Synchronization<ProfileId>>;
+impl Respond<InitializeCache> for UserServiceClient {
+    type Response = HashMap<GerritUsername, SyncRecord>;
 
-    async fn handle(&self, _: InitializeCache, _: &ServiceAssistant<Self>) -> Self::Response {
+    async fn handle(&mut self, _: InitializeCache, _: &ActorAssistant<Self>) -> Self::Response {
         let instance = self.service.lock().unwrap();
         return instance.load_sync_cache();
     }
 }
 
 #[async_trait::async_trait]
-impl Serve<LoadSettings> for UserServiceClient {
-    type Response = Settings;
+impl Respond<LoadSettings> for UserServiceClient {
+    type Response = (Settings, Option<SettingsError>);
 
     /// Implementation of `LoadSettings`.
     ///
-    /// If no service instance is initialized:
+    /// If the settings can't be loaded:
     /// * The actor will log a warning.
     /// * The actor will return default settings.
-    async fn handle(&self, message: LoadSettings, _: &ServiceAssistant<Self>) -> Self::Response {
-        let instance: &FileBackedUserService = &*self.service.lock().unwrap();
-        instance.load_settings(&message.0).unwrap_or_else(|_| {
-            warn!(
-                "Couldn't retrieve settings for user '{}'. Return default settings instead.",
-                message.0
-            );
-            panic!("No default settings implemented yet.")
-        })
+    /// * The actor will return the cause, if the settings are invalid and the
+    ///   user wasn't told yet. Once the settings are valid again, the user is
+    ///   forgotten.
+    async fn handle(&mut self, message: LoadSettings, _: &ActorAssistant<Self>) -> Self::Response {
+        let instance = self.service.lock().unwrap();
+        match instance.load_settings(&message.0) {
+            Ok(settings) => {
+                self.invalid_settings.lock().unwrap().remove(&message.0);
+                (settings, None)
+            }
+            Err(e) => {
+                warn!(
+                    "Couldn't retrieve settings for user '{}'. Return default settings instead. Cause: {}",
+                    message.0, e
+                );
+                let untold = match e {
+                    SettingsError::Invalid(_) => {
+                        !self.invalid_settings.lock().unwrap().contains(&message.0)
+                    }
+                    SettingsError::Io(_) => false,
+                };
+                (default_settings(), if untold { Some(e) } else { None })
+            }
+        }
     }
 }
 
 #[async_trait::async_trait]
-impl Listen<SetProfileIdMapping> for UserServiceClient {
-    async fn handle(&self, message: SetProfileIdMapping, _: &ServiceAssistant<Self>) {
+impl Receive<InvalidSettingsNoticeSent> for UserServiceClient {
+    async fn handle(&mut self, message: InvalidSettingsNoticeSent, _: &ActorAssistant<Self>) {
+        self.invalid_settings.lock().unwrap().insert(message.0);
+    }
+}
+
+#[async_trait::async_trait]
+impl Respond<UpdateSettings> for UserServiceClient {
+    type Response = Result<Settings, SettingsError>;
+
+    /// Implementation of `UpdateSettings`. The settings are loaded, changed
+    /// and saved in one `UserService::update_settings`, so concurrent updates,
+    /// e.g. by `chtbtr-admin`, don't overwrite each other.
+    async fn handle(
+        &mut self,
+        message: UpdateSettings,
+        _: &ActorAssistant<Self>,
+    ) -> Self::Response {
+        let instance = self.service.lock().unwrap();
+        let settings = instance.update_settings(&message.0, &*message.1)?;
+        self.invalid_settings.lock().unwrap().remove(&message.0);
+        Ok(settings)
+    }
+}
+
+#[async_trait::async_trait]
+impl Respond<ReadSettings> for UserServiceClient {
+    type Response = Result<Option<Settings>, SettingsError>;
+
+    async fn handle(&mut self, message: ReadSettings, _: &ActorAssistant<Self>) -> Self::Response {
+        let instance = self.service.lock().unwrap();
+        instance.read_settings(&message.0)
+    }
+}
+
+#[async_trait::async_trait]
+impl Respond<GetWatchers> for UserServiceClient {
+    type Response = Vec<GerritUsername>;
+
+    async fn handle(&mut self, message: GetWatchers, _: &ActorAssistant<Self>) -> Self::Response {
+        let instance = self.service.lock().unwrap();
+        instance
+            .watchers(&message.0, &message.1)
+            .unwrap_or_else(|e| {
+                error!(
+                    "Couldn't find watchers of {}/+/{}. Cause: {}",
+                    message.0, message.1, e
+                );
+                vec![]
+            })
+    }
+}
+
+#[async_trait::async_trait]
+impl Respond<SaveSettings> for UserServiceClient {
+    type Response = Result<(), SettingsError>;
+
+    async fn handle(&mut self, message: SaveSettings, _: &ActorAssistant<Self>) -> Self::Response {
+        let instance = self.service.lock().unwrap();
+        instance.save_settings(&message.0, &message.1)?;
+        self.invalid_settings.lock().unwrap().remove(&message.0);
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl Receive<SetProfileIdMapping> for UserServiceClient {
+    async fn handle(&mut self, message: SetProfileIdMapping, _: &ActorAssistant<Self>) {
         debug!(
             "Writing profile id mapping for '{}' => '{:?}'",
             &message.0, &message.1
         );
-        let instance: &FileBackedUserService = &*self.service.lock().unwrap();
+        let instance = self.service.lock().unwrap();
         let result = instance.set_sync(&message.0, &message.1);
         if result.is_err() {
             warn!(
@@ -1,10 +1,18 @@
-use crate::types::GerritTrigger;
-use reqwest::blocking::Response;
+use crate::{
+    cli::HookConfig,
+    service::{
+        TriggerSignature, TriggerSpool, SIGNATURE_HEADER, TIMESTAMP_HEADER, TRIGGER_ID_HEADER,
+    },
+    types::GerritTrigger,
+};
+use chrono::Utc;
+use reqwest::StatusCode;
 use serde::Serialize;
+use std::time::Duration;
 
 pub enum ErrorKind {
     NetworkError, // There was an issue making the request against the chtbtr backend
-    BackendError(Response), // The backend replied with a bad status code (!= 200)
+    BackendError(StatusCode, String), // The backend replied with this bad status (!= 200) and body
 }
 
 pub struct Error {
@@ -17,18 +25,47 @@ impl Error {
     }
 }
 
-fn fire_request<T: Serialize + ?Sized>(url: &str, params: &T) -> Result<(), Error> {
-    let response = reqwest::blocking::Client::new()
-        .post(url)
-        .json(params)
-        .send();
+fn fire_request<T: Serialize + ?Sized>(
+    config: &HookConfig,
+    path: &str,
+    id: &str,
+    params: &T,
+) -> Result<(), Error> {
+    let body = serde_json::to_vec(params).expect("Couldn't serialize trigger.");
+    let client = match reqwest::blocking::Client::builder()
+        .timeout(Duration::from_secs(config.timeout))
+        .build()
+    {
+        Ok(client) => client,
+        Err(_e) => {
+            return Err(Error {
+                kind: ErrorKind::NetworkError,
+            })
+        }
+    };
+    let mut request = client
+        .post(&format!("{}{}", config.server_url, path))
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(TRIGGER_ID_HEADER, id);
+    if let Some(secret) = &config.trigger_secret {
+        let timestamp = Utc::now().timestamp();
+        request = request
+            .header(TIMESTAMP_HEADER, timestamp.to_string())
+            .header(
+                SIGNATURE_HEADER,
+                TriggerSignature::sign(timestamp, &body, secret),
+            );
+    }
+
+    let response = request.body(body).send();
     match response {
         Ok(res) => {
             if res.status().is_success() {
                 Ok(())
             } else {
+                let status = res.status();
                 Err(Error {
-                    kind: ErrorKind::BackendError(res),
+                    kind: ErrorKind::BackendError(status, res.text().unwrap_or_default()),
                 })
             }
         }
@@ -38,18 +75,50 @@ fn fire_request<T: Serialize + ?Sized>(url: &str, params: &T) -> Result<(), Erro
     }
 }
 
-pub fn send_request(params: &GerritTrigger) -> Result<(), Error> {
-    let _fut = match params {
+/// Sends a trigger with the given id to the server configured in `config`,
+/// signed with the trigger secret if there is one.
+pub fn send_request(config: &HookConfig, id: &str, params: &GerritTrigger) -> Result<(), Error> {
+    match params {
         GerritTrigger::PatchStatusChanged(_) => {
-            fire_request("http://localhost:8088/trigger/comment_added", params)
+            fire_request(config, "/trigger/comment_added", id, params)
         }
         GerritTrigger::CommentAdded(_) => {
-            fire_request("http://localhost:8088/trigger/comment_added", params)
+            fire_request(config, "/trigger/comment_added", id, params)
         }
         GerritTrigger::ReviewerAdded(_) => {
-            fire_request("http://localhost:8088/trigger/reviewer_added", params)
+            fire_request(config, "/trigger/reviewer_added", id, params)
         }
+    }
+}
+
+/// Sends a trigger to the server and never fails, so Gerrit isn't held up.
+/// If the server can't be reached within the timeout or fails, the trigger
+/// is spooled for the server to deliver on its next start. Problems are only
+/// printed.
+pub fn deliver(config: &HookConfig, params: &GerritTrigger) {
+    let id = uuid::Uuid::new_v4().to_string();
+    let cause = match send_request(config, &id, params) {
+        Ok(()) => return,
+        Err(error) => match error.kind() {
+            ErrorKind::NetworkError => {
+                format!("Couldn't reach chtbtr server on {}.", config.server_url)
+            }
+            ErrorKind::BackendError(status, _) if status.is_server_error() => {
+                format!("Chtbtr server failed with {}.", status)
+            }
+            ErrorKind::BackendError(status, body) => {
+                // Sending the trigger again won't help, e.g. for a wrong secret.
+                eprintln!(
+                    "Chtbtr server rejected the trigger with {}: {}",
+                    status, body
+                );
+                return;
+            }
+        },
     };
 
-    Ok(())
+    match TriggerSpool::new(&config.spool_dir).push(&id, params) {
+        Ok(path) => eprintln!("{} Spooled trigger to {}.", cause, path.display()),
+        Err(e) => eprintln!("{} {}", cause, e),
+    }
 }
@@ -0,0 +1,746 @@
+extern crate chtbtr;
+extern crate clap;
+
+use chtbtr::{
+    cli::ConfigSources,
+    service::{
+        AuditLog, AuditQuery, DirectoryEntry, FileBackedUserService, SettingsError,
+        SqliteUserService, UserDirectory, UserService,
+    },
+    types::{
+        GerritUsername, OwnerSettings, PathToUserData, ProfileId, ResolutionStrategy,
+        ReviewerSettings, Settings, Storage, SyncRecord, Synchronization,
+    },
+};
+use clap::ArgMatches;
+use std::convert::TryFrom;
+use std::path::Path;
+
+mod cli {
+    use clap::{App, AppSettings, Arg, SubCommand};
+
+    fn username<'a>() -> Arg<'a, 'a> {
+        Arg::with_name("username")
+            .help("The Gerrit username, i.e. the name of the user's directory.")
+            .required(true)
+    }
+
+    pub fn create_cli<'a>() -> App<'a, 'a> {
+        App::new("chtbtr-admin")
+            .about(
+                r"Maintains the data directory of a chtbtr deployment.
+Works directly on the files. A running server reloads changed mappings, unless started with --reload-debounce=0.
+With --storage=sqlite only audit and migrate-to-sqlite are available.",
+            )
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .arg(
+                Arg::with_name("data_dir")
+                    .long("data-dir")
+                    .help("The directory where user data is stored.")
+                    .takes_value(true)
+                    .required(true),
+            )
+            .arg(
+                Arg::with_name("storage")
+                    .long("storage")
+                    .help("The storage of the server, 'files' or 'sqlite'. Also CHTBTR_STORAGE or storage in the config file. Default: files.")
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name("config")
+                    .long("config")
+                    .help("The config file of the server, to read the storage from. Also CHTBTR_CONFIG.")
+                    .takes_value(true),
+            )
+            .subcommand(
+                SubCommand::with_name("users")
+                    .about("Lists users, their ProfileId mapping and settings version."),
+            )
+            .subcommand(
+                SubCommand::with_name("set-mapping")
+                    .about("Maps a user to a ProfileId.")
+                    .arg(username())
+                    .arg(
+                        Arg::with_name("profile_id")
+                            .help("In the form 'PROFILE,n', or 'none' if the user can't be mapped.")
+                            .required(true),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("ambiguous")
+                    .about("Lists users with several matching profiles and their candidates."),
+            )
+            .subcommand(
+                SubCommand::with_name("pick")
+                    .about(
+                        "Maps a user with several matching profiles to one of the candidates. \
+Pending notifications are delivered once a running server reloads the mapping.",
+                    )
+                    .arg(username())
+                    .arg(
+                        Arg::with_name("profile_id")
+                            .help("In the form 'PROFILE,n'.")
+                            .required(true),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("clear-mapping")
+                    .about("Removes the mapping of a user, so it's resolved again.")
+                    .arg(username()),
+            )
+            .subcommand(
+                SubCommand::with_name("validate")
+                    .about("Validates every settings file. Exits with 1 if any is invalid."),
+            )
+            .subcommand(
+                SubCommand::with_name("migrate")
+                    .about("Migrates every settings file to the latest version. Keeps backups.")
+                    .arg(
+                        Arg::with_name("dry_run")
+                            .long("dry-run")
+                            .help("Only list the files that would be migrated."),
+                    ),
+            )
+            .subcommand(SubCommand::with_name("stats").about("Prints statistics about all users."))
+            .subcommand(
+                SubCommand::with_name("migrate-to-sqlite")
+                    .about(
+                        "Copies the settings and mappings of every user into the SQLite database \
+used with --storage=sqlite. Settings are copied as they are, even if invalid.",
+                    )
+                    .arg(
+                        Arg::with_name("database")
+                            .long("database")
+                            .help("The database to write to. Defaults to chtbtr.sqlite in the data directory.")
+                            .takes_value(true),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("import")
+                    .about(
+                        "Maps every user of a user directory (CSV, RON or JSON) to its ProfileId. \
+Users with an email are left to the server.",
+                    )
+                    .arg(
+                        Arg::with_name("file")
+                            .help("The user directory.")
+                            .required(true),
+                    )
+                    .arg(
+                        Arg::with_name("dry_run")
+                            .long("dry-run")
+                            .help("Only list the mappings that would be written."),
+                    )
+                    .arg(
+                        Arg::with_name("force")
+                            .long("force")
+                            .help("Overwrite mappings that differ from the directory."),
+                    ),
+            )
+            .subcommand(
+                SubCommand::with_name("audit")
+                    .about(
+                        "Lists the triggers the server received and what happened to the \
+notifications, e.g. why a user wasn't notified. Oldest first.",
+                    )
+                    .arg(
+                        Arg::with_name("user")
+                            .long("user")
+                            .help("Only triggers for this Gerrit user.")
+                            .takes_value(true),
+                    )
+                    .arg(
+                        Arg::with_name("change")
+                            .long("change")
+                            .help("Only triggers of this change number.")
+                            .takes_value(true),
+                    ),
+            )
+    }
+}
+
+fn describe_mapping(mapping: &Synchronization<ProfileId>) -> String {
+    match mapping {
+        Synchronization::Some(profile_id) => profile_id.to_string(),
+        Synchronization::None => String::from("unresolvable"),
+        Synchronization::NotMappedYet => String::from("not mapped yet"),
+        Synchronization::Ambiguous(candidates) => {
+            format!("ambiguous ({} candidates)", candidates.len())
+        }
+    }
+}
+
+fn describe_settings(settings: &Result<Option<Settings>, SettingsError>) -> String {
+    match settings {
+        Ok(Some(settings)) => String::from(settings.version()),
+        Ok(None) => String::from("no settings"),
+        Err(e) => e.to_string(),
+    }
+}
+
+fn parse_mapping(value: &str) -> Result<Synchronization<ProfileId>, String> {
+    if value.eq_ignore_ascii_case("none") {
+        return Ok(Synchronization::None);
+    }
+
+    ProfileId::try_from(value)
+        .map(Synchronization::Some)
+        .map_err(|_| format!("'{}' is neither 'PROFILE,n' nor 'none'.", value))
+}
+
+/// Counts per category, e.g. how many users have settings of version v2.
+#[derive(Debug, Default, PartialEq)]
+struct Statistics {
+    users: usize,
+    mapped: usize,
+    unresolvable: usize,
+    not_mapped_yet: usize,
+    ambiguous: usize,
+    settings_current: usize,
+    settings_outdated: usize,
+    settings_missing: usize,
+    settings_invalid: usize,
+    subscribe_comments: usize,
+    subscribe_verified: usize,
+    subscribe_ready_for_submit: usize,
+    subscribe_submitted: usize,
+    subscribe_reviews: usize,
+}
+
+impl Statistics {
+    fn add(
+        &mut self,
+        mapping: &Synchronization<ProfileId>,
+        settings: &Result<Option<Settings>, SettingsError>,
+    ) {
+        self.users += 1;
+        match mapping {
+            Synchronization::Some(_) => self.mapped += 1,
+            Synchronization::None => self.unresolvable += 1,
+            Synchronization::NotMappedYet => self.not_mapped_yet += 1,
+            Synchronization::Ambiguous(_) => self.ambiguous += 1,
+        }
+
+        let settings = match settings {
+            Ok(Some(settings)) => settings,
+            Ok(None) => {
+                self.settings_missing += 1;
+                return;
+            }
+            Err(_) => {
+                self.settings_invalid += 1;
+                return;
+            }
+        };
+
+        if settings.is_current() {
+            self.settings_current += 1;
+        } else {
+            self.settings_outdated += 1;
+        }
+
+        let as_owner: OwnerSettings = settings.clone().into();
+        let as_reviewer: ReviewerSettings = settings.clone().into();
+        self.subscribe_comments += as_owner.subscribe_comment as usize;
+        self.subscribe_verified += as_owner.subscribe_verified as usize;
+        self.subscribe_ready_for_submit += as_owner.subscribe_ready_for_submit as usize;
+        self.subscribe_submitted += as_owner.subscribe_submitted as usize;
+        self.subscribe_reviews += as_reviewer.subscribe as usize;
+    }
+
+    fn print(&self) {
+        println!("Users: {}", self.users);
+        println!(
+            "Mappings: {} mapped, {} unresolvable, {} not mapped yet, {} ambiguous",
+            self.mapped, self.unresolvable, self.not_mapped_yet, self.ambiguous
+        );
+        println!(
+            "Settings: {} current, {} outdated, {} missing, {} invalid",
+            self.settings_current,
+            self.settings_outdated,
+            self.settings_missing,
+            self.settings_invalid
+        );
+        println!(
+            "Subscriptions: {} comments, {} verified, {} ready for submit, {} submitted, {} reviews",
+            self.subscribe_comments,
+            self.subscribe_verified,
+            self.subscribe_ready_for_submit,
+            self.subscribe_submitted,
+            self.subscribe_reviews
+        );
+    }
+}
+
+fn users(service: &FileBackedUserService) -> Result<(), String> {
+    for user in service.list_users()? {
+        let record = service.read_sync(&user);
+        let resolved_by = record
+            .resolved_by
+            .map(|strategy| format!(" (by {:?})", strategy))
+            .unwrap_or_default();
+        println!(
+            "{}\t{}{}\t{}",
+            user,
+            describe_mapping(&record.mapping),
+            resolved_by,
+            describe_settings(&service.read_settings(&user))
+        );
+    }
+    Ok(())
+}
+
+fn set_mapping(service: &FileBackedUserService, matches: &ArgMatches) -> Result<(), String> {
+    let user = GerritUsername::from(matches.value_of("username").expect("username is not set!"));
+    let mapping = parse_mapping(
+        matches
+            .value_of("profile_id")
+            .expect("profile_id is not set!"),
+    )?;
+    service.set_sync(
+        &user,
+        &SyncRecord::new(mapping.clone(), Some(ResolutionStrategy::Manual)),
+    )?;
+    println!("{} is mapped to {}.", user, describe_mapping(&mapping));
+    Ok(())
+}
+
+fn ambiguous(service: &FileBackedUserService) -> Result<(), String> {
+    for user in service.list_users()? {
+        if let Synchronization::Ambiguous(candidates) = service.read_sync(&user).mapping {
+            println!("{}", user);
+            for candidate in candidates {
+                println!("\t{}\t{}", candidate.id, candidate.name);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn pick(service: &FileBackedUserService, matches: &ArgMatches) -> Result<(), String> {
+    let user = GerritUsername::from(matches.value_of("username").expect("username is not set!"));
+    let value = matches
+        .value_of("profile_id")
+        .expect("profile_id is not set!");
+    let profile_id =
+        ProfileId::try_from(value).map_err(|_| format!("'{}' is not 'PROFILE,n'.", value))?;
+    if !service.read_sync(&user).mapping.is_candidate(&profile_id) {
+        return Err(format!("{} is no candidate for '{}'.", profile_id, user));
+    }
+
+    service.set_sync(
+        &user,
+        &SyncRecord::new(
+            Synchronization::Some(profile_id.clone()),
+            Some(ResolutionStrategy::Manual),
+        ),
+    )?;
+    println!("{} is mapped to {}.", user, profile_id);
+    Ok(())
+}
+
+fn clear_mapping(service: &FileBackedUserService, matches: &ArgMatches) -> Result<(), String> {
+    let user = GerritUsername::from(matches.value_of("username").expect("username is not set!"));
+    service.clear_sync(&user)?;
+    println!("Removed mapping of {}.", user);
+    Ok(())
+}
+
+fn validate(service: &FileBackedUserService) -> Result<(), String> {
+    let mut invalid = 0;
+    for user in service.list_users()? {
+        if let Err(e) = service.read_settings(&user) {
+            println!("{}\t{}", user, e);
+            invalid += 1;
+        }
+    }
+
+    if invalid > 0 {
+        return Err(format!("{} settings files are invalid.", invalid));
+    }
+
+    println!("All settings files are valid.");
+    Ok(())
+}
+
+fn migrate(service: &FileBackedUserService, dry_run: bool) -> Result<(), String> {
+    let mut failed = 0;
+    for user in service.list_users()? {
+        match service.read_settings(&user) {
+            Ok(Some(settings)) if !settings.is_current() => {}
+            _ => continue,
+        }
+
+        if dry_run {
+            println!("{}\twould be migrated", user);
+            continue;
+        }
+
+        match service.migrate_settings(&user) {
+            Ok(Some(version)) => println!("{}\tmigrated from {}", user, version),
+            Ok(None) => {}
+            Err(e) => {
+                println!("{}\t{}", user, e);
+                failed += 1;
+            }
+        }
+    }
+
+    if failed > 0 {
+        return Err(format!("{} settings files couldn't be migrated.", failed));
+    }
+    Ok(())
+}
+
+fn stats(service: &FileBackedUserService) -> Result<(), String> {
+    let mut statistics = Statistics::default();
+    for user in service.list_users()? {
+        statistics.add(
+            &service.read_sync(&user).mapping,
+            &service.read_settings(&user),
+        );
+    }
+    statistics.print();
+    Ok(())
+}
+
+fn import(service: &FileBackedUserService, matches: &ArgMatches) -> Result<(), String> {
+    let directory = UserDirectory::load(Path::new(
+        matches.value_of("file").expect("file is not set!"),
+    ))?;
+    let dry_run = matches.is_present("dry_run");
+    let force = matches.is_present("force");
+
+    let (mut imported, mut conflicts, mut skipped) = (0, 0, 0);
+    for (user, entry) in directory.entries() {
+        let profile_id = match entry {
+            DirectoryEntry::ProfileId(profile_id) => profile_id,
+            DirectoryEntry::Email(email) => {
+                println!("{}\tskipped, the server searches Just for {}", user, email);
+                skipped += 1;
+                continue;
+            }
+        };
+
+        match service.read_sync(user).mapping {
+            Synchronization::Some(current) if &current == profile_id => continue,
+            Synchronization::Some(current) if !force => {
+                println!(
+                    "{}\tconflict, mapped to {} but the directory says {}",
+                    user, current, profile_id
+                );
+                conflicts += 1;
+                continue;
+            }
+            _ => {}
+        }
+
+        if dry_run {
+            println!("{}\twould be mapped to {}", user, profile_id);
+        } else {
+            service.set_sync(
+                user,
+                &SyncRecord::new(
+                    Synchronization::Some(profile_id.clone()),
+                    Some(ResolutionStrategy::Directory),
+                ),
+            )?;
+            println!("{}\tmapped to {}", user, profile_id);
+        }
+        imported += 1;
+    }
+
+    println!(
+        "{} imported, {} conflicts, {} skipped.",
+        imported, conflicts, skipped
+    );
+    if conflicts > 0 {
+        return Err(String::from(
+            "Some mappings differ from the directory. Use --force to overwrite them.",
+        ));
+    }
+    Ok(())
+}
+
+fn audit(service: &FileBackedUserService, matches: &ArgMatches) -> Result<(), String> {
+    let query = AuditQuery {
+        user: matches.value_of("user").map(GerritUsername::from),
+        change: matches.value_of("change").map(String::from),
+        ..AuditQuery::default()
+    };
+    let entries = AuditLog::new(&service.data_dir).query(&query)?;
+    if entries.is_empty() {
+        println!("No triggers found.");
+    }
+    for entry in entries {
+        println!(
+            "{}\t{}\t{} {}\t{}\t{}",
+            entry.time.to_rfc3339(),
+            entry.trigger,
+            entry.project,
+            entry.change,
+            entry.recipient,
+            entry.describe_outcome()
+        );
+    }
+    Ok(())
+}
+
+fn migrate_to_sqlite(service: &FileBackedUserService, matches: &ArgMatches) -> Result<(), String> {
+    let database = match matches.value_of("database") {
+        Some(path) => std::path::PathBuf::from(path),
+        None => PathToUserData::database(&service.data_dir)
+            .as_path()
+            .to_path_buf(),
+    };
+    let sqlite = SqliteUserService::open(&database)?;
+
+    let (mut settings, mut mappings) = (0, 0);
+    for user in service.list_users()? {
+        let path = PathToUserData::settings(&service.data_dir, &user);
+        match std::fs::read_to_string(&path) {
+            Ok(content) => {
+                sqlite
+                    .store_settings_content(&user, &content)
+                    .map_err(|e| e.to_string())?;
+                settings += 1;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => println!("{}\tcouldn't read settings: {}", user, e),
+        }
+
+        let record = service.read_sync(&user);
+        if record.mapping != Synchronization::NotMappedYet {
+            sqlite.set_sync(&user, &record)?;
+            mappings += 1;
+        }
+    }
+
+    println!(
+        "Copied {} settings and {} mappings to {}.",
+        settings,
+        mappings,
+        database.display()
+    );
+    Ok(())
+}
+
+fn main() {
+    let matches = cli::create_cli().get_matches();
+    let service = FileBackedUserService {
+        data_dir: String::from(matches.value_of("data_dir").expect("data-dir is not set!")),
+    };
+    let storage = ConfigSources::collect(&matches).and_then(|sources| sources.storage());
+
+    let result = match (storage, matches.subcommand()) {
+        (Err(e), _) => Err(e),
+        (Ok(Storage::Sqlite), (command, _)) if !works_with_sqlite(command) => Err(format!(
+            "The server stores user data in SQLite, {} only works on the files.",
+            command
+        )),
+        (_, subcommand) => run(&service, subcommand),
+    };
+
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Commands that don't touch user data in the files, so they are safe when
+/// the server stores it in SQLite.
+fn works_with_sqlite(command: &str) -> bool {
+    command == "audit" || command == "migrate-to-sqlite"
+}
+
+fn run(
+    service: &FileBackedUserService,
+    subcommand: (&str, Option<&ArgMatches>),
+) -> Result<(), String> {
+    match subcommand {
+        ("users", _) => users(service),
+        ("set-mapping", Some(matches)) => set_mapping(service, matches),
+        ("ambiguous", _) => ambiguous(service),
+        ("pick", Some(matches)) => pick(service, matches),
+        ("clear-mapping", Some(matches)) => clear_mapping(service, matches),
+        ("validate", _) => validate(service),
+        ("migrate", Some(matches)) => migrate(service, matches.is_present("dry_run")),
+        ("stats", _) => stats(service),
+        ("import", Some(matches)) => import(service, matches),
+        ("migrate-to-sqlite", Some(matches)) => migrate_to_sqlite(service, matches),
+        ("audit", Some(matches)) => audit(service, matches),
+        _ => Err(String::from("Unknown command.")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chtbtr::types::Candidate;
+    use std::{fs, path::PathBuf};
+
+    /// A fresh temporary data directory, with a copy of the users in
+    /// `fixture`, if given.
+    fn temp_data_dir(fixture: Option<&str>) -> PathBuf {
+        let target = std::env::temp_dir().join(format!("chtbtr-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&target).unwrap();
+        for user in fixture
+            .map(|dir| fs::read_dir(dir).unwrap())
+            .into_iter()
+            .flatten()
+        {
+            let user = user.unwrap().path();
+            let user_target = target.join(user.file_name().unwrap());
+            fs::create_dir_all(&user_target).unwrap();
+            for file in fs::read_dir(&user).unwrap() {
+                let file = file.unwrap().path();
+                fs::copy(&file, user_target.join(file.file_name().unwrap())).unwrap();
+            }
+        }
+        target
+    }
+
+    /// Runs a subcommand like the binary does, on the data directory of
+    /// `service`.
+    fn run_command(service: &FileBackedUserService, args: &[&str]) -> Result<(), String> {
+        let mut argv = vec!["chtbtr-admin", "--data-dir", &service.data_dir];
+        argv.extend_from_slice(args);
+        let matches = cli::create_cli().get_matches_from_safe(argv).unwrap();
+        run(service, matches.subcommand())
+    }
+
+    #[test]
+    fn parses_mapping() {
+        assert_eq!(
+            parse_mapping("PROFILE,1234"),
+            Ok(Synchronization::Some(ProfileId(1234)))
+        );
+        assert_eq!(parse_mapping("None"), Ok(Synchronization::None));
+        assert!(parse_mapping("PROFILE,abc").is_err());
+    }
+
+    #[test]
+    fn collects_statistics() {
+        let mut statistics = Statistics::default();
+        statistics.add(
+            &Synchronization::Some(ProfileId(1)),
+            &Ok(Some(chtbtr::default::default_settings())),
+        );
+        statistics.add(&Synchronization::None, &Ok(None));
+        statistics.add(&Synchronization::Ambiguous(vec![]), &Ok(None));
+        statistics.add(
+            &Synchronization::NotMappedYet,
+            &Err(SettingsError::Invalid(String::from("broken"))),
+        );
+
+        assert_eq!(
+            statistics,
+            Statistics {
+                users: 4,
+                mapped: 1,
+                unresolvable: 1,
+                not_mapped_yet: 1,
+                ambiguous: 1,
+                settings_current: 1,
+                settings_missing: 2,
+                settings_invalid: 1,
+                ..Statistics::default()
+            }
+        );
+    }
+
+    #[test]
+    fn overrides_and_clears_mappings() {
+        let data_dir = temp_data_dir(None);
+        let service = FileBackedUserService {
+            data_dir: String::from(data_dir.to_str().unwrap()),
+        };
+        let user = GerritUsername::from("user.a");
+
+        run_command(&service, &["set-mapping", "user.a", "PROFILE,7"]).unwrap();
+        let record = service.read_sync(&user);
+        assert_eq!(record.mapping, Synchronization::Some(ProfileId(7)));
+        assert_eq!(record.resolved_by, Some(ResolutionStrategy::Manual));
+
+        run_command(&service, &["set-mapping", "user.a", "none"]).unwrap();
+        assert_eq!(service.read_sync(&user).mapping, Synchronization::None);
+        assert!(run_command(&service, &["set-mapping", "user.a", "7"]).is_err());
+        assert_eq!(service.read_sync(&user).mapping, Synchronization::None);
+
+        run_command(&service, &["clear-mapping", "user.a"]).unwrap();
+        assert_eq!(
+            service.read_sync(&user).mapping,
+            Synchronization::NotMappedYet
+        );
+
+        fs::remove_dir_all(data_dir).unwrap();
+    }
+
+    #[test]
+    fn picks_only_candidates_of_ambiguous_mappings() {
+        let data_dir = temp_data_dir(None);
+        let service = FileBackedUserService {
+            data_dir: String::from(data_dir.to_str().unwrap()),
+        };
+        let user = GerritUsername::from("user.a");
+        let candidates = vec![
+            Candidate {
+                id: ProfileId(1),
+                name: String::from("User A"),
+            },
+            Candidate {
+                id: ProfileId(2),
+                name: String::from("User A."),
+            },
+        ];
+        service
+            .set_sync(
+                &user,
+                &SyncRecord::new(Synchronization::Ambiguous(candidates.clone()), None),
+            )
+            .unwrap();
+
+        assert_eq!(
+            run_command(&service, &["pick", "user.a", "PROFILE,3"]),
+            Err(String::from("PROFILE,3 is no candidate for 'user.a'."))
+        );
+        assert_eq!(
+            service.read_sync(&user).mapping,
+            Synchronization::Ambiguous(candidates)
+        );
+
+        run_command(&service, &["pick", "user.a", "PROFILE,2"]).unwrap();
+        let record = service.read_sync(&user);
+        assert_eq!(record.mapping, Synchronization::Some(ProfileId(2)));
+        assert_eq!(record.resolved_by, Some(ResolutionStrategy::Manual));
+
+        fs::remove_dir_all(data_dir).unwrap();
+    }
+
+    #[test]
+    fn migrates_settings_and_keeps_backups() {
+        let data_dir = temp_data_dir(Some("tests/user2/migrate_settings"));
+        let service = FileBackedUserService {
+            data_dir: String::from(data_dir.to_str().unwrap()),
+        };
+        let user = GerritUsername::from("user.v1");
+        let settings_file = data_dir.join("user.v1/settings.ron");
+        let original = fs::read_to_string(&settings_file).unwrap();
+
+        // A dry run doesn't touch the files.
+        run_command(&service, &["migrate", "--dry-run"]).unwrap();
+        assert_eq!(fs::read_to_string(&settings_file).unwrap(), original);
+        assert!(!data_dir.join("user.v1/settings.ron.v1.bak").exists());
+
+        run_command(&service, &["migrate"]).unwrap();
+        let settings = service.read_settings(&user).unwrap().unwrap();
+        assert!(settings.is_current());
+        assert_eq!(
+            fs::read_to_string(data_dir.join("user.v1/settings.ron.v1.bak")).unwrap(),
+            original
+        );
+        assert!(run_command(&service, &["validate"]).is_ok());
+
+        fs::remove_dir_all(data_dir).unwrap();
+    }
+}
@@ -5,15 +5,74 @@ extern crate chtbtr;
 use acteur::Acteur;
 use actix_web::{web, App, HttpServer};
 use clap::crate_version;
+use rustls::internal::pemfile::{certs, pkcs8_private_keys, rsa_private_keys};
+use rustls::{NoClientAuth, ServerConfig};
+use std::path::Path;
+use std::time::Duration;
 
 use actor::messages::SetAppState;
 use chtbtr::{
     actor,
     cli::parse_cli_args,
     controller,
-    types::{AppState, ConnectionParameters},
+    service::{FileBackedUserService, SqliteUserService, TriggerSpool, UserDirectory},
+    types::{AppState, ConnectionParameters, PathToUserData, Storage, Tenant},
 };
 
+/// Seconds between attempts to deliver spooled triggers that failed.
+const SPOOL_RETRY_INTERVAL: u64 = 60;
+
+/// Checks the user directory and the data directory of a tenant, and repairs
+/// files left behind by a crash.
+fn prepare_tenant(connection: &ConnectionParameters, tenant: &Tenant) -> Result<(), String> {
+    if let Some(path) = &tenant.user_directory {
+        let directory = UserDirectory::load(Path::new(path))?;
+        println!("... using {} users from {}.", directory.len(), path);
+    }
+
+    match connection.storage {
+        Storage::Files => {
+            let service = FileBackedUserService {
+                data_dir: tenant.data_dir.clone(),
+            };
+            for repair in service.repair()? {
+                println!("... repaired {}.", repair);
+            }
+        }
+        Storage::Sqlite => {
+            let path = PathToUserData::database(&tenant.data_dir);
+            SqliteUserService::open(path.as_path())?;
+            println!("... storing user data in {}.", path.as_path().display());
+        }
+    }
+    Ok(())
+}
+
+/// Loads the certificate chain and private key from PEM files. The key may be
+/// PKCS#8 or RSA.
+fn load_tls_config(cert: &str, key: &str) -> Result<ServerConfig, String> {
+    let read = |path: &str| {
+        std::fs::read(path).map_err(|e| format!("Couldn't read {}. Cause: {}.", path, e))
+    };
+
+    let chain = certs(&mut read(cert)?.as_slice())
+        .map_err(|_| format!("Couldn't parse certificates in {}.", cert))?;
+    let key_content = read(key)?;
+    let mut keys = pkcs8_private_keys(&mut key_content.as_slice()).unwrap_or_default();
+    if keys.is_empty() {
+        keys = rsa_private_keys(&mut key_content.as_slice()).unwrap_or_default();
+    }
+    if keys.is_empty() {
+        return Err(format!("Couldn't find a private key in {}.", key));
+    }
+
+    let mut config = ServerConfig::new(NoClientAuth::new());
+    config
+        .set_single_cert(chain, keys.remove(0))
+        .map_err(|e| format!("Invalid certificate or key. Cause: {}.", e))?;
+    Ok(config)
+}
+
 ///
 /// Start a Chtbtr server.
 ///
@@ -29,43 +88,171 @@ async fn main() -> std::io::Result<()> {
     env_logger::init();
 
     let connection: ConnectionParameters = parse_cli_args();
+    let tls = match (&connection.tls_cert, &connection.tls_key) {
+        (Some(cert), Some(key)) => match load_tls_config(cert, key) {
+            Ok(config) => Some(config),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        },
+        _ => None,
+    };
     println!(
         "Starting chtbtr {}
 ... for '{}'.
 ... using profile '{}' on '{}'.
-... listening on port 8088.",
+... listening on {}://{}:{}.",
         crate_version!(),
-        connection.gerrit_domain,
+        connection
+            .gerrit_instances
+            .iter()
+            .map(|instance| instance.base_url())
+            .collect::<Vec<String>>()
+            .join("', '"),
         connection.profile_id,
-        connection.domain
+        connection.domain,
+        if tls.is_some() { "https" } else { "http" },
+        connection.bind_address,
+        connection.port
     );
+    if connection.trigger_secret.is_some() {
+        println!("... only accepting signed triggers.");
+    }
+
+    for tenant in &connection.tenants {
+        println!(
+            "... serving tenant '{}' with profile '{}' on '{}'.",
+            tenant.id, tenant.profile_id, tenant.domain
+        );
+    }
+
+    let tenants = connection.all_tenants();
+    for tenant in &tenants {
+        if let Err(e) = prepare_tenant(&connection, tenant) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
 
     let sys = Acteur::new();
     sys.send_to_actor_sync::<actor::AppState, _>(0, SetAppState(connection.clone()));
 
+    if connection.chat_poll_interval > 0 {
+        println!(
+            "... polling for chat commands every {}s.",
+            connection.chat_poll_interval
+        );
+        for tenant in &tenants {
+            actix_rt::spawn(controller::chat_command::poll_chat_commands(
+                sys.clone(),
+                tenant.id.clone(),
+                Duration::from_secs(connection.chat_poll_interval),
+            ));
+        }
+    }
+
+    if connection.storage == Storage::Files && connection.reload_debounce > 0 {
+        for tenant in &tenants {
+            println!(
+                "... reloading changed mappings from {} after {}s.",
+                tenant.data_dir, connection.reload_debounce
+            );
+            controller::data_dir_watcher::watch_data_dir(
+                sys.clone(),
+                tenant.id.clone(),
+                tenant.data_dir.clone(),
+                Duration::from_secs(connection.reload_debounce),
+            );
+        }
+    }
+
+    let address = (connection.bind_address.clone(), connection.port);
+    let spool = connection.spool_dir.as_deref().map(TriggerSpool::new);
+    if let Some(spool) = &spool {
+        println!(
+            "... delivering triggers spooled to {}.",
+            spool.dir.display()
+        );
+    }
+
     let app_state = web::Data::new(AppState {
         acteur: sys.clone(),
         connection,
     });
+    let spool_state = app_state.clone();
 
     println!("\nWe have a liftoff! 🚀");
 
-    let result = HttpServer::new(move || {
-        App::new().app_data(app_state.clone()).service(
-            web::scope("/trigger")
-                .route(
-                    "/comment_added",
-                    web::post().to(controller::comment_controller),
-                )
-                .route(
-                    "/reviewer_added",
-                    web::post().to(controller::reviewer_controller),
-                ),
-        )
-    })
-    .bind("127.0.0.1:8088")?
-    .run()
-    .await;
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(app_state.clone())
+            .service(
+                web::scope("/trigger")
+                    .route(
+                        "/comment_added",
+                        web::post().to(controller::comment_controller),
+                    )
+                    .route(
+                        "/reviewer_added",
+                        web::post().to(controller::reviewer_controller),
+                    ),
+            )
+            .route("/health", web::get().to(controller::health::health))
+            .route("/ready", web::get().to(controller::health::ready))
+            .route("/metrics", web::get().to(controller::metrics::metrics))
+            .service(
+                web::resource("/settings")
+                    .route(web::get().to(controller::settings_page::show_settings))
+                    .route(web::post().to(controller::settings_page::update_settings)),
+            )
+            .service(
+                web::scope("/admin")
+                    .route("/users", web::get().to(controller::admin::list_users))
+                    .service(
+                        web::resource("/users/{username}/mapping")
+                            .route(web::get().to(controller::admin::show_mapping))
+                            .route(web::put().to(controller::admin::override_mapping)),
+                    )
+                    .route(
+                        "/users/{username}/resolve",
+                        web::post().to(controller::admin::resolve_mapping),
+                    )
+                    .route(
+                        "/users/{username}/pick",
+                        web::post().to(controller::admin::pick_profile),
+                    )
+                    .route(
+                        "/ambiguous",
+                        web::get().to(controller::admin::list_ambiguous),
+                    )
+                    .service(
+                        web::resource("/users/{username}/settings")
+                            .route(web::get().to(controller::admin::read_settings))
+                            .route(web::put().to(controller::admin::replace_settings)),
+                    )
+                    .route(
+                        "/settings/validate",
+                        web::post().to(controller::admin::validate_settings),
+                    )
+                    .route(
+                        "/users/{username}/explain",
+                        web::get().to(controller::admin::explain),
+                    ),
+            )
+    });
+    let server = match tls {
+        Some(config) => server.bind_rustls(address, config)?,
+        None => server.bind(address)?,
+    };
+    if let Some(spool) = spool {
+        actix_rt::spawn(controller::drain_spool(
+            spool,
+            spool_state,
+            Duration::from_secs(SPOOL_RETRY_INTERVAL),
+        ));
+    }
+    let result = server.run().await;
 
     println!("Server is done.");
 
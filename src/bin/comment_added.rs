@@ -2,7 +2,7 @@ extern crate chtbtr;
 extern crate clap;
 extern crate reqwest;
 
-use chtbtr::api::{send_request, ErrorKind};
+use chtbtr::{api::deliver, cli::hook_config};
 use clap::ArgMatches;
 
 mod cli {
@@ -63,11 +63,16 @@ mod cli {
                 "code-review-old",
                 "Code-Review-oldValue",
                 "The code review status before this one. Should only be present if status changed"
+            ).required(false),
+            arg_with_hyphen(
+                "comment",
+                "comment",
+                "The comment. Its first line names the patch set, e.g. 'Patch Set 3: Code-Review+1'."
             ).required(false)
         ];
 
         // We need to parse them so clap doesn't panic, but we don't want them.
-        let ignored_args = ["change", "branch", "topic", "commit", "comment"];
+        let ignored_args = ["change", "branch", "topic", "commit"];
 
         let mut app = App::new("comment-added").about(
             r"Binary to catch Gerrit's hooks plugin comment-added hook.
@@ -93,6 +98,29 @@ This binary is usually triggered by Gerrit and not called directly by a user.",
         }
     }
 
+    /// Gerrit starts comments with the patch set they were added to, e.g.
+    /// "Patch Set 3: Code-Review+1".
+    fn parse_patch_set(comment: &str) -> Option<u32> {
+        let rest = comment.trim_start().strip_prefix("Patch Set ")?;
+        let number: String = rest.chars().take_while(char::is_ascii_digit).collect();
+        number.parse().ok()
+    }
+
+    /// The comment without the patch set line Gerrit starts it with, `None` if
+    /// nothing else was written.
+    fn parse_comment_text(comment: &str) -> Option<String> {
+        let comment = comment.trim();
+        let text = if parse_patch_set(comment).is_some() {
+            comment
+                .split_once('\n')
+                .map(|(_, rest)| rest)
+                .unwrap_or_default()
+        } else {
+            comment
+        };
+        Some(text.trim().to_string()).filter(|text| !text.is_empty())
+    }
+
     fn parse_base_data(matches: &ArgMatches) -> BaseData {
         let change_url = String::from(
             matches
@@ -116,6 +144,8 @@ This binary is usually triggered by Gerrit and not called directly by a user.",
             change_owner_username,
             change_url,
             project: ProjectName::from(project.as_str()),
+            instance: None,
+            patch_set: matches.value_of("comment").and_then(parse_patch_set),
         }
     }
 
@@ -178,17 +208,17 @@ This binary is usually triggered by Gerrit and not called directly by a user.",
             base,
             author,
             author_username,
+            location: None,
+            comment: matches.value_of("comment").and_then(parse_comment_text),
         })
     }
 
     #[cfg(test)]
     mod test {
 
-        use super::create_cli;
+        use super::{create_cli, parse_comment_text, parse_patch_set};
         use crate::cli::parse_matches_into_struct;
-        use chtbtr::gerrit_triggers::GerritTrigger;
-        use chtbtr::patch_status::PatchStatus;
-        use chtbtr::types::{CodeReviewStatus, VerifiedStatus};
+        use chtbtr::types::{CodeReviewStatus, GerritTrigger, PatchStatus, VerifiedStatus};
         use clap::ArgMatches;
 
         fn base_args() -> Vec<&'static str> {
@@ -269,6 +299,33 @@ patchset."#,
             assert!(matches.is_present("verified"));
         }
 
+        #[test]
+        fn reads_patch_set_from_comment() {
+            let mut base_args = base_args();
+            base_args.extend(vec!["--comment", "Patch Set 3: Code-Review+1\n\n(1 comment)"]);
+            let matches: ArgMatches = create_cli().get_matches_from(base_args);
+
+            match parse_matches_into_struct(&matches) {
+                GerritTrigger::CommentAdded(data) => assert_eq!(data.base.patch_set, Some(3)),
+                _ => panic!("Returned wrong struct type."),
+            };
+            assert_eq!(parse_patch_set("Patch Set 12:"), Some(12));
+            assert_eq!(parse_patch_set("Looks good"), None);
+        }
+
+        #[test]
+        fn reads_comment_without_patch_set_line() {
+            assert_eq!(
+                parse_comment_text("Patch Set 3: Code-Review+1\n\nLooks good"),
+                Some(String::from("Looks good"))
+            );
+            assert_eq!(parse_comment_text("Patch Set 3: Code-Review+1"), None);
+            assert_eq!(
+                parse_comment_text("Rebased\npatchset."),
+                Some(String::from("Rebased\npatchset."))
+            );
+        }
+
         #[test]
         fn removes_quotes_from_author() {
             let base_args = base_args();
@@ -391,15 +448,15 @@ patchset."#,
 fn main() {
     let matches: ArgMatches = cli::create_cli().get_matches();
     let params = cli::parse_matches_into_struct(&matches);
-
-    send_request(&params).unwrap_or_else(|error| {
-        match error.kind() {
-            ErrorKind::BackendError(res) => {
-                println!("Request to trigger message failed. Response was: {:?}", res)
-            }
-            ErrorKind::NetworkError => {
-                println!("Couldn't send trigger request to chtbtr server on localhost:8088.")
-            }
-        };
-    })
+    match hook_config() {
+        Ok(config) => deliver(
+            &config,
+            &params.with_instance(config.gerrit_instance.clone()),
+        ),
+        Err(e) => {
+            // Fail, so Gerrit logs that the hook is misconfigured.
+            eprintln!("{} The trigger is lost.", e);
+            std::process::exit(1);
+        }
+    }
 }
@@ -2,8 +2,8 @@ extern crate chtbtr;
 extern crate clap;
 
 use chtbtr::{
-    api::{send_request, ErrorKind},
-    cli::reviewer_added_cli,
+    api::deliver,
+    cli::{hook_config, reviewer_added_cli},
     types::{GerritTrigger, GerritUsername, ReviewerAddedData},
 };
 use clap::ArgMatches;
@@ -31,18 +31,22 @@ fn parse_matches_into_struct(matches: &ArgMatches) -> GerritTrigger {
         reviewer_username: GerritUsername::from(reviewer_username),
         change_owner: String::from(change_owner),
         change_owner_username: GerritUsername::from(change_owner_username),
+        instance: None,
     })
 }
 
 fn main() {
     let matches: ArgMatches = reviewer_added_cli().get_matches();
     let trigger_parameters = parse_matches_into_struct(&matches);
-    send_request(&trigger_parameters).unwrap_or_else(|error| match error.kind() {
-        ErrorKind::NetworkError => {
-            panic!("Couldn't send trigger request to chtbtr server on localhost:8088.")
+    match hook_config() {
+        Ok(config) => deliver(
+            &config,
+            &trigger_parameters.with_instance(config.gerrit_instance.clone()),
+        ),
+        Err(e) => {
+            // Fail, so Gerrit logs that the hook is misconfigured.
+            eprintln!("{} The trigger is lost.", e);
+            std::process::exit(1);
         }
-        ErrorKind::BackendError(res) => {
-            panic!("Request to trigger message failed. Response was: {:?}", res)
-        }
-    });
+    }
 }
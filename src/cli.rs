@@ -2,8 +2,16 @@ extern crate clap;
 use clap::{App, Arg, ArgMatches};
 
 use crate::types::ConnectionParameters;
+use crate::types::GerritInstance;
+use crate::types::MessageFormat;
 use crate::types::ProfileId;
+use crate::types::Storage;
+use crate::types::{Tenant, TenantId};
+use serde::Deserialize;
+use std::collections::HashMap;
 use std::convert::TryFrom;
+use std::fs;
+use std::path::Path;
 
 pub fn ignore_arg<'a>(name: &'a str, app: App<'a, 'a>) -> App<'a, 'a> {
     app.arg(
@@ -100,91 +108,1055 @@ pub fn get_clap<'a>() -> App<'a, 'a> {
 This is the server part."
         )
         .after_help("
+Every option can also be set in the environment, e.g. CHTBTR_DATA_DIR for
+--data-dir, or in a TOML or RON config file given with --config, e.g.
+data_dir = \"/opt/chtbtr/data\". The command line takes precedence over the
+environment, the environment over the config file.
+
 EXAMPLES:
 
 ./chtbtr --chat-bot-profile-id \"PROFILE,1234\" \\
          --just-domain \"just.installation.social\" \\
          --gerrit-domain \"gerrit.installation.com\" \\
          --username \"user.name+chatbot@domain.com\" \\
-         --password \"mysecretpassword\" \\
-         --user-data \"/home/user/data_dir\" \\
-         --client-id \"myclientid\"")
-        .arg(Arg::with_name("profile_id")
+         --password-file \"/etc/chtbtr/password\" \\
+         --data-dir \"/home/user/data_dir\" \\
+         --client-id \"myclientid\"
+
+CHTBTR_PASSWORD_FILE=/etc/chtbtr/password ./chtbtr --config /etc/chtbtr/chtbtr.toml")
+        .arg(Arg::with_name("config")
+             .long("config")
+             .help("A TOML or RON file with the options, e.g. 'data_dir = \"/opt/chtbtr/data\"'. Also CHTBTR_CONFIG.")
+             .takes_value(true)
+             .display_order(0))
+        .arg(Arg::with_name("chat_bot_profile_id")
              .long("chat-bot-profile-id")
-             .help("The profile id the chatbot should use. In the form 'PROFILE,n'.")
+             .help("The profile id the chatbot should use. In the form 'PROFILE,n'. Required.")
              .takes_value(true)
-             .display_order(0)
-             .required(true),
-        )
-        .arg(Arg::with_name("domain")
+             .display_order(1))
+        .arg(Arg::with_name("just_domain")
              .long("just-domain")
-             .help("The domain to use. Example: 'just.installation.social'")
+             .help("The domain to use. Example: 'just.installation.social'. Required.")
              .takes_value(true)
-             .display_order(1)
-             .required(true)
-        )
+             .display_order(2))
         .arg(Arg::with_name("gerrit_domain")
              .long("gerrit-domain")
-             .help("Gerrit domain to forward notifications for. Is used to construct messages.")
+             .help("Gerrit domain to forward notifications for, optionally with the path Gerrit is served under, e.g. 'gerrit.example.com/r'. Is used to construct messages. Required, unless --gerrit-instances is set.")
              .takes_value(true)
-             .display_order(2)
-             .required(true)
-        )
+             .display_order(3))
+        .arg(Arg::with_name("gerrit_instances")
+             .long("gerrit-instances")
+             .help("Several Gerrit servers to forward notifications for, e.g. 'main=https://gerrit.example.com, legacy=http://old.example.com'. The first one is the default, usernames of the others are prefixed with their name, e.g. 'legacy~jdoe'.")
+             .takes_value(true)
+             .display_order(3))
         .arg(Arg::with_name("username")
              .long("username")
-             .help("The username used to retrieve an OAuth token. Usually the same account as the chatbot's profile.")
+             .help("The username used to retrieve an OAuth token. Usually the same account as the chatbot's profile. Required.")
              .takes_value(true)
-             .display_order(3)
-             .required(true))
+             .display_order(4))
         .arg(Arg::with_name("password")
              .long("password")
-             .help("Password used to retrieve an OAuth token.")
+             .help("Password used to retrieve an OAuth token. Visible to other users of the machine, prefer --password-file.")
+             .takes_value(true)
+             .display_order(5))
+        .arg(Arg::with_name("password_file")
+             .long("password-file")
+             .help("A file containing the password used to retrieve an OAuth token. Used if no password is set.")
              .takes_value(true)
-             .display_order(4)
-             .required(true))
+             .display_order(6))
         .arg(Arg::with_name("data_dir")
              .long("data-dir")
-             .help("The directory where user data is stored.")
+             .help("The directory where user data is stored. Required.")
              .takes_value(true)
-             .display_order(5)
-             .required(true))
+             .display_order(7))
         .arg(Arg::with_name("client_id")
              .long("client-id")
-             .help("The OAuth client id as configured in the backend.")
-             .takes_value(true)
-             .display_order(6)
-             .required(true))
-}
-
-fn validate_match(matches: &ArgMatches, field: &str) -> String {
-    let error: &str = &format!("{} is not not set. Clap is not properly configured?", field);
-    String::from(matches.value_of(field).expect(error))
-}
-
-pub fn parse_matches_into_connection_parameters(matches: &ArgMatches) -> ConnectionParameters {
-    let profile_id = validate_match(matches, "profile_id");
-    let profile_id =
-        ProfileId::try_from(profile_id.as_str()).expect("Couldn't parse ProfileId from CLI.");
-    let domain = validate_match(matches, "domain");
-    let gerrit_domain = validate_match(matches, "gerrit_domain");
-    let username = validate_match(matches, "username");
-    let password = validate_match(matches, "password");
-    let data_dir = validate_match(matches, "data_dir");
-    let client_id = validate_match(matches, "client_id");
-
-    ConnectionParameters {
-        profile_id,
-        domain,
-        gerrit_domain,
-        username,
-        password,
-        data_dir,
+             .help("The OAuth client id as configured in the backend. Required.")
+             .takes_value(true)
+             .display_order(8))
+        .arg(Arg::with_name("chat_poll_interval")
+             .long("chat-poll-interval")
+             .help("Seconds between polls for chat commands sent to the chatbot. 0 disables chat commands. Default: 0.")
+             .takes_value(true)
+             .display_order(9))
+        .arg(Arg::with_name("web_secret")
+             .long("web-secret")
+             .help("Secret used to sign links to the settings page. The settings page is disabled without it.")
+             .takes_value(true)
+             .display_order(10))
+        .arg(Arg::with_name("public_url")
+             .long("public-url")
+             .help("The URL users reach the server with. Used to construct links to the settings page. Default: http://127.0.0.1:8088.")
+             .takes_value(true)
+             .display_order(11))
+        .arg(Arg::with_name("admin_token")
+             .long("admin-token")
+             .help("Bearer token required to use the admin API under /admin. The admin API is disabled without it.")
+             .takes_value(true)
+             .display_order(12))
+        .arg(Arg::with_name("unresolved_ttl")
+             .long("unresolved-ttl")
+             .help("Hours until Chtbtr tries again to find the Just profile of a user it couldn't find. 0 never tries again. Default: 24.")
+             .takes_value(true)
+             .display_order(13))
+        .arg(Arg::with_name("user_directory")
+             .long("user-directory")
+             .help("A CSV, RON or JSON file mapping Gerrit usernames to a profile ('PROFILE,n') or an email. Takes precedence over searching Just.")
+             .takes_value(true)
+             .display_order(14))
+        .arg(Arg::with_name("storage")
+             .long("storage")
+             .help("How user data is stored in the data directory: 'files' (a directory per user) or 'sqlite' (the database chtbtr.sqlite). Default: files.")
+             .takes_value(true)
+             .possible_values(&["files", "sqlite"])
+             .display_order(15))
+        .arg(Arg::with_name("reload_debounce")
+             .long("reload-debounce")
+             .help("Seconds without changes before changed sync files in the data directory are reloaded. 0 disables reloading. Only for --storage=files. Default: 2.")
+             .takes_value(true)
+             .display_order(16))
+        .arg(Arg::with_name("bind_address")
+             .long("bind-address")
+             .help("The address the server listens on. Default: 127.0.0.1.")
+             .takes_value(true)
+             .display_order(17))
+        .arg(Arg::with_name("port")
+             .long("port")
+             .help("The port the server listens on. Default: 8088.")
+             .takes_value(true)
+             .display_order(18))
+        .arg(Arg::with_name("tls_cert")
+             .long("tls-cert")
+             .help("A PEM file with the certificate chain. Serves HTTPS together with --tls-key.")
+             .takes_value(true)
+             .display_order(19))
+        .arg(Arg::with_name("tls_key")
+             .long("tls-key")
+             .help("A PEM file with the private key (PKCS#8 or RSA) of the certificate.")
+             .takes_value(true)
+             .display_order(20))
+        .arg(Arg::with_name("trigger_secret")
+             .long("trigger-secret")
+             .help("Secret the hook binaries sign triggers with. Unsigned triggers are rejected if set.")
+             .takes_value(true)
+             .display_order(21))
+        .arg(Arg::with_name("spool_dir")
+             .long("spool-dir")
+             .help("Directory the hook binaries spool triggers to while the server is down. The server delivers them on startup.")
+             .takes_value(true)
+             .display_order(22))
+        .arg(Arg::with_name("tenants")
+             .long("tenants")
+             .help("Config files of further tenants, separated by commas. Each tenant has its own chatbot, Just domain and data directory. See README.")
+             .takes_value(true)
+             .display_order(23))
+        .arg(Arg::with_name("message_format")
+             .long("message-format")
+             .help("The richest format the Just installation shows messages in: 'text' or 'markdown'. Users preferring plain text get text. Default: text.")
+             .takes_value(true)
+             .possible_values(&["text", "markdown"])
+             .display_order(24))
+}
+
+/// Every option of the server and its default, if it isn't required. The key
+/// is the name in the config file, the option on the command line uses dashes
+/// instead of underscores (`--data-dir`), the environment variable is upper
+/// case with a `CHTBTR_` prefix (`CHTBTR_DATA_DIR`).
+const OPTIONS: &[(&str, Option<&str>)] = &[
+    ("chat_bot_profile_id", None),
+    ("just_domain", None),
+    ("gerrit_domain", None),
+    ("gerrit_instances", None),
+    ("username", None),
+    ("password", None),
+    ("password_file", None),
+    ("data_dir", None),
+    ("client_id", None),
+    ("chat_poll_interval", Some("0")),
+    ("web_secret", None),
+    ("public_url", Some("http://127.0.0.1:8088")),
+    ("admin_token", None),
+    ("unresolved_ttl", Some("24")),
+    ("user_directory", None),
+    ("storage", Some("files")),
+    ("reload_debounce", Some("2")),
+    ("bind_address", Some("127.0.0.1")),
+    ("port", Some("8088")),
+    ("tls_cert", None),
+    ("tls_key", None),
+    ("trigger_secret", None),
+    ("spool_dir", None),
+    ("tenants", None),
+    ("message_format", Some("text")),
+];
+
+/// Options in the config file of a tenant. `instances` lists the names of the
+/// Gerrit instances notifying its users.
+const TENANT_OPTIONS: &[(&str, Option<&str>)] = &[
+    ("chat_bot_profile_id", None),
+    ("just_domain", None),
+    ("username", None),
+    ("password", None),
+    ("password_file", None),
+    ("client_id", None),
+    ("data_dir", None),
+    ("user_directory", None),
+    ("message_format", None),
+    ("instances", None),
+];
+
+/// Options of the hook binaries. A config file may contain them, so the
+/// server and the hooks can share it.
+const HOOK_OPTIONS: &[(&str, Option<&str>)] = &[
+    ("server_url", Some("http://localhost:8088")),
+    ("trigger_secret", None),
+    ("spool_dir", None),
+    ("hook_timeout", Some("3")),
+    ("gerrit_instance", None),
+];
+
+fn env_var(key: &str) -> String {
+    format!("CHTBTR_{}", key.to_uppercase())
+}
+
+/// A value in a config file. Numbers and booleans may be written without
+/// quotes.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ConfigValue {
+    Text(String),
+    Bool(bool),
+    Number(i64),
+    // RON reports numbers without a type as floats
+    Float(f64),
+}
+
+/// Parses the content of a config file, `format` is `toml` or `ron`. Fails for
+/// keys that aren't options.
+pub fn parse_config_file(content: &str, format: &str) -> Result<HashMap<String, String>, String> {
+    parse_config(content, format, &[OPTIONS, HOOK_OPTIONS])
+}
+
+fn parse_config(
+    content: &str,
+    format: &str,
+    options: &[&[(&str, Option<&str>)]],
+) -> Result<HashMap<String, String>, String> {
+    let values: HashMap<String, ConfigValue> = match format {
+        "toml" => toml::from_str(content).map_err(|e| e.to_string())?,
+        "ron" => ron::de::from_str(content).map_err(|e| e.to_string())?,
+        _ => {
+            return Err(format!(
+                "Unknown format '{}'. Use a .toml or .ron file.",
+                format
+            ))
+        }
+    };
+
+    let mut unknown: Vec<&String> = values
+        .keys()
+        .filter(|key| {
+            options
+                .iter()
+                .flat_map(|options| options.iter())
+                .all(|(option, _)| option != &key.as_str())
+        })
+        .collect();
+    if !unknown.is_empty() {
+        unknown.sort();
+        return Err(format!("Unknown options {:?}.", unknown));
+    }
+
+    Ok(values
+        .into_iter()
+        .map(|(key, value)| match value {
+            ConfigValue::Text(text) => (key, text),
+            ConfigValue::Bool(value) => (key, value.to_string()),
+            ConfigValue::Number(number) => (key, number.to_string()),
+            ConfigValue::Float(number) => (key, number.to_string()),
+        })
+        .collect())
+}
+
+fn load_config_file(
+    path: &Path,
+    options: &[&[(&str, Option<&str>)]],
+) -> Result<HashMap<String, String>, String> {
+    let content = fs::read_to_string(path).map_err(|e| {
+        format!(
+            "Couldn't read config file {}. Cause: {}.",
+            path.display(),
+            e
+        )
+    })?;
+
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    parse_config(&content, &extension, options)
+        .map_err(|e| format!("Invalid config file {}: {}", path.display(), e))
+}
+
+/// The values of the server options from all sources. The command line takes
+/// precedence over the environment, the environment over the config file and
+/// the config file over the defaults.
+#[derive(Debug, Default)]
+pub struct ConfigSources {
+    // Options given on the command line, by key
+    pub cli: HashMap<String, String>,
+    // Environment variables, by name, e.g. CHTBTR_DATA_DIR
+    pub env: HashMap<String, String>,
+    // Options in the config file, by key
+    pub file: HashMap<String, String>,
+}
+
+impl ConfigSources {
+    /// Collects the options from the command line, the environment and the
+    /// config file, if one is given on the command line or in the environment.
+    pub fn collect(matches: &ArgMatches) -> Result<ConfigSources, String> {
+        let cli: HashMap<String, String> = OPTIONS
+            .iter()
+            .filter_map(|(key, _)| {
+                matches
+                    .value_of(key)
+                    .map(|value| (key.to_string(), value.to_string()))
+            })
+            .collect();
+        ConfigSources::load(cli, matches.value_of("config"))
+    }
+
+    /// Collects the options from the environment and the config file given
+    /// in `CHTBTR_CONFIG`, e.g. for the hook binaries.
+    pub fn from_environment() -> Result<ConfigSources, String> {
+        ConfigSources::load(HashMap::new(), None)
+    }
+
+    /// The storage the server uses, e.g. for chtbtr-admin, which only works
+    /// on files.
+    pub fn storage(&self) -> Result<Storage, String> {
+        let value = self.value("storage").expect("storage has a default.");
+        value
+            .parse()
+            .map_err(|e| format!("Invalid storage '{}': {}", value, e))
+    }
+
+    fn load(cli: HashMap<String, String>, config: Option<&str>) -> Result<ConfigSources, String> {
+        let env: HashMap<String, String> = std::env::vars()
+            .filter(|(name, _)| name.starts_with("CHTBTR_"))
+            .collect();
+
+        let config = config
+            .map(String::from)
+            .or_else(|| env.get(&env_var("config")).cloned());
+        let file = match config {
+            Some(path) => load_config_file(Path::new(&path), &[OPTIONS, HOOK_OPTIONS])?,
+            None => HashMap::new(),
+        };
+
+        Ok(ConfigSources { cli, env, file })
+    }
+
+    fn value(&self, key: &str) -> Option<String> {
+        self.cli
+            .get(key)
+            .or_else(|| self.env.get(&env_var(key)))
+            .or_else(|| self.file.get(key))
+            .cloned()
+            .or_else(|| {
+                OPTIONS
+                    .iter()
+                    .chain(HOOK_OPTIONS)
+                    .find(|(option, _)| option == &key)
+                    .and_then(|(_, default)| default.map(String::from))
+            })
+    }
+
+    fn parse<T, E: std::fmt::Display>(
+        &self,
+        key: &str,
+        parse: impl Fn(&str) -> Result<T, E>,
+        errors: &mut Vec<String>,
+    ) -> Option<T> {
+        match self.value(key) {
+            Some(value) => match parse(&value) {
+                Ok(value) => Some(value),
+                Err(e) => {
+                    errors.push(format!("Invalid {} '{}': {}", key, value, e));
+                    None
+                }
+            },
+            None => None,
+        }
+    }
+
+    fn required<T, E: std::fmt::Display>(
+        &self,
+        key: &str,
+        parse: impl Fn(&str) -> Result<T, E>,
+        errors: &mut Vec<String>,
+    ) -> Option<T> {
+        if self.value(key).is_none() {
+            errors.push(missing(key));
+        }
+        self.parse(key, parse, errors)
+    }
+
+    /// The password, or the content of the password file without the trailing
+    /// line break.
+    fn password(&self, errors: &mut Vec<String>) -> Option<String> {
+        if let Some(password) = self.value("password") {
+            return Some(password);
+        }
+
+        let path = match self.value("password_file") {
+            Some(path) => path,
+            None => {
+                errors.push(format!("{} Or set password_file.", missing("password")));
+                return None;
+            }
+        };
+        match fs::read_to_string(&path) {
+            Ok(content) => Some(content.trim_end_matches(&['\r', '\n'][..]).to_string()),
+            Err(e) => {
+                errors.push(format!(
+                    "Couldn't read password file {}. Cause: {}.",
+                    path, e
+                ));
+                None
+            }
+        }
+    }
+}
+
+fn missing(key: &str) -> String {
+    format!(
+        "Missing {}. Set --{}, {} or {} in the config file.",
+        key,
+        key.replace('_', "-"),
+        env_var(key),
+        key
+    )
+}
+
+fn parse_string(value: &str) -> Result<String, String> {
+    Ok(String::from(value))
+}
+
+/// Builds the `ConnectionParameters` from all sources. Fails with every
+/// missing or invalid value, not just the first.
+pub fn parse_connection_parameters(
+    sources: &ConfigSources,
+) -> Result<ConnectionParameters, Vec<String>> {
+    let mut errors = vec![];
+    let profile_id = sources.required(
+        "chat_bot_profile_id",
+        |value| ProfileId::try_from(value),
+        &mut errors,
+    );
+    let domain = sources.required("just_domain", parse_string, &mut errors);
+    let gerrit_instances = match (
+        sources.value("gerrit_domain"),
+        sources.value("gerrit_instances"),
+    ) {
+        (Some(domain), None) => Some(vec![GerritInstance::from_domain(&domain)]),
+        (None, Some(_)) => {
+            sources.parse("gerrit_instances", GerritInstance::parse_list, &mut errors)
+        }
+        (Some(_), Some(_)) => {
+            errors.push(String::from(
+                "Set gerrit_domain or gerrit_instances, not both.",
+            ));
+            None
+        }
+        (None, None) => {
+            errors.push(format!(
+                "{} Or set gerrit_instances.",
+                missing("gerrit_domain")
+            ));
+            None
+        }
+    };
+    let username = sources.required("username", parse_string, &mut errors);
+    let password = sources.password(&mut errors);
+    let data_dir = sources.required("data_dir", parse_string, &mut errors);
+    let client_id = sources.required("client_id", parse_string, &mut errors);
+    let storage = sources.parse("storage", str::parse::<Storage>, &mut errors);
+    let chat_poll_interval = sources.parse("chat_poll_interval", str::parse::<u64>, &mut errors);
+    let unresolved_ttl = sources.parse("unresolved_ttl", str::parse::<u64>, &mut errors);
+    let reload_debounce = sources.parse("reload_debounce", str::parse::<u64>, &mut errors);
+    let bind_address = sources.value("bind_address");
+    let port = sources.parse("port", str::parse::<u16>, &mut errors);
+    let tls_cert = sources.value("tls_cert");
+    let tls_key = sources.value("tls_key");
+    if tls_cert.is_some() != tls_key.is_some() {
+        errors.push(String::from(
+            "Set both tls_cert and tls_key to serve HTTPS, or none of them.",
+        ));
+    }
+    let trigger_secret = sources.value("trigger_secret");
+    let spool_dir = sources.value("spool_dir");
+    let tenants: Vec<Tenant> = sources
+        .value("tenants")
+        .map(|paths| {
+            paths
+                .split(',')
+                .map(str::trim)
+                .filter(|path| !path.is_empty())
+                .filter_map(|path| match parse_tenant(Path::new(path)) {
+                    Ok(tenant) => Some(tenant),
+                    Err(tenant_errors) => {
+                        errors.extend(tenant_errors);
+                        None
+                    }
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    if let (Some(data_dir), Some(instances)) = (&data_dir, &gerrit_instances) {
+        errors.extend(check_tenants(&tenants, data_dir, instances));
+    }
+    let web_secret = sources.value("web_secret");
+    let admin_token = sources.value("admin_token");
+    let user_directory = sources.value("user_directory");
+    let message_format = sources.parse("message_format", str::parse::<MessageFormat>, &mut errors);
+    let public_url = sources
+        .value("public_url")
+        .map(|url| url.trim_end_matches('/').to_string());
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    Ok(ConnectionParameters {
+        profile_id: profile_id.unwrap(),
+        domain: domain.unwrap(),
+        gerrit_instances: gerrit_instances.unwrap(),
+        username: username.unwrap(),
+        password: password.unwrap(),
+        data_dir: data_dir.unwrap(),
+        storage: storage.unwrap(),
         oauth_token: String::from("notset"),
-        client_id,
+        client_id: client_id.unwrap(),
+        chat_poll_interval: chat_poll_interval.unwrap(),
+        web_secret,
+        public_url: public_url.unwrap(),
+        admin_token,
+        unresolved_ttl: unresolved_ttl.unwrap(),
+        user_directory,
+        message_format: message_format.unwrap(),
+        reload_debounce: reload_debounce.unwrap(),
+        bind_address: bind_address.unwrap(),
+        port: port.unwrap(),
+        tls_cert,
+        tls_key,
+        trigger_secret,
+        spool_dir,
+        tenants,
+    })
+}
+
+/// Reads a tenant from its config file. The tenant is named after the file,
+/// e.g. `acme` for `/etc/chtbtr/tenants/acme.toml`.
+fn parse_tenant(path: &Path) -> Result<Tenant, Vec<String>> {
+    let id = TenantId(
+        path.file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default(),
+    );
+    let sources = ConfigSources {
+        file: load_config_file(path, &[TENANT_OPTIONS]).map_err(|e| vec![e])?,
+        ..ConfigSources::default()
+    };
+
+    let mut errors = vec![];
+    for key in &[
+        "chat_bot_profile_id",
+        "just_domain",
+        "username",
+        "client_id",
+        "data_dir",
+    ] {
+        if sources.value(key).is_none() {
+            errors.push(format!("Missing {} in {}.", key, path.display()));
+        }
+    }
+    let profile_id = sources.parse(
+        "chat_bot_profile_id",
+        |value| ProfileId::try_from(value),
+        &mut errors,
+    );
+    let password =
+        if sources.value("password").is_some() || sources.value("password_file").is_some() {
+            sources.password(&mut errors)
+        } else {
+            errors.push(format!(
+                "Missing password or password_file in {}.",
+                path.display()
+            ));
+            None
+        };
+    let message_format = sources.parse("message_format", str::parse::<MessageFormat>, &mut errors);
+    let gerrit_instances: Vec<String> = sources
+        .value("instances")
+        .map(|names| {
+            names
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default();
+
+    if !errors.is_empty() {
+        return Err(errors
+            .into_iter()
+            .map(|e| format!("Tenant {}: {}", id, e))
+            .collect());
+    }
+
+    Ok(Tenant {
+        id,
+        profile_id: profile_id.unwrap(),
+        domain: sources.value("just_domain").unwrap(),
+        username: sources.value("username").unwrap(),
+        password: password.unwrap(),
+        client_id: sources.value("client_id").unwrap(),
+        data_dir: sources.value("data_dir").unwrap(),
+        user_directory: sources.value("user_directory"),
+        message_format: message_format.unwrap(),
+        gerrit_instances,
+    })
+}
+
+/// Every tenant needs its own name and data directory. A Gerrit instance
+/// notifies the users of one tenant only.
+fn check_tenants(
+    tenants: &[Tenant],
+    default_data_dir: &str,
+    instances: &[GerritInstance],
+) -> Vec<String> {
+    let mut errors = vec![];
+    let mut ids = vec![TenantId::default_tenant()];
+    let mut data_dirs = vec![default_data_dir];
+    let mut claimed: Vec<&String> = vec![];
+    for tenant in tenants {
+        if ids.contains(&tenant.id) {
+            errors.push(format!("Tenant {} is configured twice.", tenant.id));
+        }
+        ids.push(tenant.id.clone());
+        if data_dirs.contains(&tenant.data_dir.as_str()) {
+            errors.push(format!(
+                "Tenant {}: The data directory {} is used by another tenant.",
+                tenant.id, tenant.data_dir
+            ));
+        }
+        data_dirs.push(&tenant.data_dir);
+        for name in &tenant.gerrit_instances {
+            if !instances.iter().any(|instance| &instance.name == name) {
+                errors.push(format!(
+                    "Tenant {}: Unknown Gerrit instance '{}'.",
+                    tenant.id, name
+                ));
+            } else if claimed.contains(&name) {
+                errors.push(format!(
+                    "Tenant {}: Gerrit instance '{}' belongs to another tenant.",
+                    tenant.id, name
+                ));
+            }
+            claimed.push(name);
+        }
+    }
+    errors
+}
+
+/// Where the hook binaries send triggers to and how they sign them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HookConfig {
+    // The URL of the server, e.g. http://localhost:8088
+    pub server_url: String,
+    // Signs triggers, if set. Has to match the server's trigger_secret
+    pub trigger_secret: Option<String>,
+    // Triggers the server didn't take are written here
+    pub spool_dir: String,
+    // Seconds until the hook gives up on the server
+    pub timeout: u64,
+    // The name of the Gerrit instance the hook runs on, None for the default
+    pub gerrit_instance: Option<String>,
+}
+
+impl HookConfig {
+    pub fn from_sources(sources: &ConfigSources) -> Result<HookConfig, String> {
+        let mut errors = vec![];
+        let timeout = sources.parse("hook_timeout", str::parse::<u64>, &mut errors);
+        let spool_dir = sources.value("spool_dir");
+        if spool_dir.is_none() {
+            errors.push(String::from(
+                "Missing spool_dir (CHTBTR_SPOOL_DIR), the hook keeps the triggers the server didn't take there.",
+            ));
+        }
+        if !errors.is_empty() {
+            return Err(errors.join(" "));
+        }
+
+        Ok(HookConfig {
+            server_url: sources
+                .value("server_url")
+                .expect("server_url has a default.")
+                .trim_end_matches('/')
+                .to_string(),
+            trigger_secret: sources.value("trigger_secret"),
+            spool_dir: spool_dir.expect("spool_dir is checked."),
+            timeout: timeout.expect("hook_timeout has a default."),
+            gerrit_instance: sources.value("gerrit_instance"),
+        })
     }
 }
 
+/// Reads the `HookConfig` from the environment, e.g. `CHTBTR_SERVER_URL`, and
+/// the config file given in `CHTBTR_CONFIG`.
+pub fn hook_config() -> Result<HookConfig, String> {
+    ConfigSources::from_environment().and_then(|sources| HookConfig::from_sources(&sources))
+}
+
+/// Parses the server options from the command line, the environment and the
+/// config file. Prints every problem and exits if any value is missing or
+/// invalid.
 pub fn parse_cli_args() -> ConnectionParameters {
     let cli_args: ArgMatches = get_clap().get_matches();
-    parse_matches_into_connection_parameters(&cli_args)
+    let result = ConfigSources::collect(&cli_args)
+        .map_err(|e| vec![e])
+        .and_then(|sources| parse_connection_parameters(&sources));
+
+    match result {
+        Ok(connection) => connection,
+        Err(errors) => {
+            for error in errors {
+                eprintln!("{}", error);
+            }
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_config_file, parse_connection_parameters, ConfigSources, HookConfig};
+    use crate::types::{GerritUsername, MessageFormat, ProfileId, Storage, TenantId};
+    use std::collections::HashMap;
+
+    fn values(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn required() -> HashMap<String, String> {
+        values(&[
+            ("chat_bot_profile_id", "PROFILE,1"),
+            ("just_domain", "just.example.com"),
+            ("gerrit_domain", "gerrit.example.com"),
+            ("username", "chatbot"),
+            ("password", "file secret"),
+            ("data_dir", "/opt/chtbtr/data"),
+            ("client_id", "client"),
+        ])
+    }
+
+    #[test]
+    fn uses_defaults() {
+        let sources = ConfigSources {
+            file: required(),
+            ..ConfigSources::default()
+        };
+
+        let connection = parse_connection_parameters(&sources).unwrap();
+
+        assert_eq!(connection.profile_id, ProfileId(1));
+        assert_eq!(connection.storage, Storage::Files);
+        assert_eq!(connection.chat_poll_interval, 0);
+        assert_eq!(connection.unresolved_ttl, 24);
+        assert_eq!(connection.reload_debounce, 2);
+        assert_eq!(connection.message_format, MessageFormat::PlainText);
+        assert_eq!(connection.public_url, "http://127.0.0.1:8088");
+        assert_eq!(connection.web_secret, None);
+        assert_eq!(connection.bind_address, "127.0.0.1");
+        assert_eq!(connection.port, 8088);
+        assert_eq!(connection.tls_cert, None);
+    }
+
+    #[test]
+    fn reads_storage_without_other_options() {
+        assert_eq!(ConfigSources::default().storage(), Ok(Storage::Files));
+        let sources = ConfigSources {
+            env: values(&[("CHTBTR_STORAGE", "sqlite")]),
+            ..ConfigSources::default()
+        };
+        assert_eq!(sources.storage(), Ok(Storage::Sqlite));
+        let sources = ConfigSources {
+            file: values(&[("storage", "cloud")]),
+            ..ConfigSources::default()
+        };
+        assert!(sources.storage().is_err());
+    }
+
+    #[test]
+    fn command_line_beats_environment_beats_config_file() {
+        let sources = ConfigSources {
+            cli: values(&[("data_dir", "/cli")]),
+            env: values(&[
+                ("CHTBTR_DATA_DIR", "/env"),
+                ("CHTBTR_PASSWORD", "env secret"),
+                ("CHTBTR_UNRESOLVED_TTL", "1"),
+            ]),
+            file: required(),
+        };
+
+        let connection = parse_connection_parameters(&sources).unwrap();
+
+        assert_eq!(connection.data_dir, "/cli");
+        assert_eq!(connection.password, "env secret");
+        assert_eq!(connection.unresolved_ttl, 1);
+        assert_eq!(connection.username, "chatbot");
+    }
+
+    #[test]
+    fn lists_every_missing_and_invalid_value() {
+        let sources = ConfigSources {
+            cli: values(&[("chat_bot_profile_id", "1"), ("storage", "cloud")]),
+            env: values(&[("CHTBTR_USERNAME", "chatbot")]),
+            ..ConfigSources::default()
+        };
+
+        let errors = parse_connection_parameters(&sources).unwrap_err();
+
+        assert_eq!(errors.len(), 7, "{:?}", errors);
+        assert!(errors[0].starts_with("Invalid chat_bot_profile_id '1'"));
+        assert!(errors.contains(&String::from(
+            "Missing just_domain. Set --just-domain, CHTBTR_JUST_DOMAIN or just_domain in the config file."
+        )));
+        assert!(errors.iter().any(|e| e.starts_with("Missing password.")));
+        assert!(errors
+            .iter()
+            .any(|e| e.starts_with("Invalid storage 'cloud'")));
+        assert!(!errors.iter().any(|e| e.contains("username")));
+    }
+
+    #[test]
+    fn reads_password_file() {
+        let path = std::env::temp_dir().join(format!("chtbtr-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "secret from file\n").unwrap();
+        let mut file = required();
+        file.remove("password");
+        let sources = ConfigSources {
+            env: values(&[("CHTBTR_PASSWORD_FILE", path.to_str().unwrap())]),
+            file,
+            ..ConfigSources::default()
+        };
+
+        let connection = parse_connection_parameters(&sources).unwrap();
+
+        assert_eq!(connection.password, "secret from file");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn requires_both_tls_files() {
+        let sources = ConfigSources {
+            cli: values(&[("tls_cert", "/etc/chtbtr/cert.pem")]),
+            file: required(),
+            ..ConfigSources::default()
+        };
+
+        assert_eq!(
+            parse_connection_parameters(&sources).unwrap_err(),
+            vec![String::from(
+                "Set both tls_cert and tls_key to serve HTTPS, or none of them."
+            )]
+        );
+    }
+
+    #[test]
+    fn reads_gerrit_instances() {
+        let mut file = required();
+        file.remove("gerrit_domain");
+        let sources = ConfigSources {
+            env: values(&[(
+                "CHTBTR_GERRIT_INSTANCES",
+                "main=https://gerrit.example.com, legacy=http://old.example.com",
+            )]),
+            file: file.clone(),
+            ..ConfigSources::default()
+        };
+
+        let instances = parse_connection_parameters(&sources)
+            .unwrap()
+            .gerrit_instances;
+        assert_eq!(instances.len(), 2);
+        assert_eq!(instances[1].namespace, Some(String::from("legacy")));
+
+        let single = parse_connection_parameters(&ConfigSources {
+            file: required(),
+            ..ConfigSources::default()
+        })
+        .unwrap()
+        .gerrit_instances;
+        assert_eq!(single[0].base_url(), "https://gerrit.example.com");
+        assert_eq!(single[0].namespace, None);
+
+        file.insert(String::from("gerrit_instances"), String::from("main"));
+        let errors = parse_connection_parameters(&ConfigSources {
+            file,
+            ..ConfigSources::default()
+        })
+        .unwrap_err();
+        assert!(errors[0].starts_with("Invalid gerrit_instances 'main'"));
+    }
+
+    #[test]
+    fn reads_tenants() {
+        let dir = std::env::temp_dir().join(format!("chtbtr-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let acme = dir.join("acme.toml");
+        std::fs::write(
+            &acme,
+            "chat_bot_profile_id = \"PROFILE,2\"\njust_domain = \"just.acme.com\"\n\
+             username = \"bot\"\npassword = \"acme secret\"\nclient_id = \"acme\"\n\
+             data_dir = \"/opt/chtbtr/acme\"\ninstances = \"legacy\"\n\
+             message_format = \"markdown\"\n",
+        )
+        .unwrap();
+        let broken = dir.join("broken.toml");
+        std::fs::write(
+            &broken,
+            "data_dir = \"/opt/chtbtr/data\"\ninstances = \"other\"\n",
+        )
+        .unwrap();
+
+        let mut file = required();
+        file.remove("gerrit_domain");
+        file.insert(
+            String::from("gerrit_instances"),
+            String::from("main=https://gerrit.example.com, legacy=https://old.example.com"),
+        );
+        file.insert(String::from("tenants"), acme.to_str().unwrap().to_string());
+        let connection = parse_connection_parameters(&ConfigSources {
+            file: file.clone(),
+            ..ConfigSources::default()
+        })
+        .unwrap();
+
+        assert_eq!(connection.tenants.len(), 1);
+        let tenant = &connection.tenants[0];
+        assert_eq!(tenant.id, TenantId::from("acme"));
+        assert_eq!(tenant.profile_id, ProfileId(2));
+        assert_eq!(tenant.password, "acme secret");
+        assert_eq!(tenant.message_format, MessageFormat::Markdown);
+        assert_eq!(
+            connection.tenant_for_instance(&connection.gerrit_instances[1]),
+            TenantId::from("acme")
+        );
+        assert_eq!(
+            connection.tenant_of(&GerritUsername::from("legacy~jdoe")),
+            TenantId::from("acme")
+        );
+        assert_eq!(
+            connection.tenant_of(&GerritUsername::from("jdoe")),
+            TenantId::default_tenant()
+        );
+
+        file.insert(
+            String::from("tenants"),
+            format!("{},{}", acme.display(), broken.display()),
+        );
+        let errors = parse_connection_parameters(&ConfigSources {
+            file,
+            ..ConfigSources::default()
+        })
+        .unwrap_err();
+        assert!(errors
+            .iter()
+            .any(|e| e.starts_with("Tenant broken: Missing just_domain")));
+        assert!(errors
+            .iter()
+            .any(|e| e.starts_with("Tenant broken: Missing password or password_file")));
+        assert_eq!(errors.len(), 5, "{:?}", errors);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reads_hook_config() {
+        let sources = ConfigSources {
+            env: values(&[("CHTBTR_SERVER_URL", "https://chtbtr.example.com/")]),
+            file: values(&[
+                ("trigger_secret", "secret"),
+                ("data_dir", "/opt"),
+                ("spool_dir", "/var/spool/chtbtr"),
+                ("gerrit_instance", "legacy"),
+            ]),
+            ..ConfigSources::default()
+        };
+
+        assert_eq!(
+            HookConfig::from_sources(&sources),
+            Ok(HookConfig {
+                server_url: String::from("https://chtbtr.example.com"),
+                trigger_secret: Some(String::from("secret")),
+                spool_dir: String::from("/var/spool/chtbtr"),
+                timeout: 3,
+                gerrit_instance: Some(String::from("legacy")),
+            })
+        );
+        assert_eq!(
+            HookConfig::from_sources(&ConfigSources {
+                env: values(&[("CHTBTR_SPOOL_DIR", "/var/spool/chtbtr")]),
+                ..ConfigSources::default()
+            })
+            .unwrap()
+            .server_url,
+            "http://localhost:8088"
+        );
+        assert!(HookConfig::from_sources(&ConfigSources::default())
+            .unwrap_err()
+            .contains("Missing spool_dir"));
+        assert!(HookConfig::from_sources(&ConfigSources {
+            env: values(&[("CHTBTR_HOOK_TIMEOUT", "soon")]),
+            ..ConfigSources::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn parses_config_files() {
+        let toml = "data_dir = \"/opt/chtbtr/data\"\nchat_poll_interval = 10\n";
+        let ron = "{\"data_dir\": \"/opt/chtbtr/data\", \"chat_poll_interval\": 10}";
+        let expected = values(&[
+            ("data_dir", "/opt/chtbtr/data"),
+            ("chat_poll_interval", "10"),
+        ]);
+
+        assert_eq!(parse_config_file(toml, "toml"), Ok(expected.clone()));
+        assert_eq!(parse_config_file(ron, "ron"), Ok(expected));
+        assert_eq!(
+            parse_config_file("datadir = \"/opt\"", "toml"),
+            Err(String::from("Unknown options [\"datadir\"]."))
+        );
+        assert!(parse_config_file("server_url = \"http://localhost:8088\"", "toml").is_ok());
+        assert!(parse_config_file("", "yaml").is_err());
+
+        // Booleans and negative numbers are passed on, so parsing the option
+        // reports them
+        let expected = values(&[("port", "-1"), ("reload_debounce", "true")]);
+        assert_eq!(
+            parse_config_file("port = -1\nreload_debounce = true\n", "toml"),
+            Ok(expected.clone())
+        );
+        assert_eq!(
+            parse_config_file("{\"port\": -1, \"reload_debounce\": true}", "ron"),
+            Ok(expected)
+        );
+    }
 }
@@ -0,0 +1,546 @@
+//! Admin API to inspect and fix user data without shell access. All endpoints
+//! require the header `Authorization: Bearer {admin token}`. Users of all
+//! tenants are listed, a user is found in the tenant of its Gerrit instance.
+//!
+//! * `GET /admin/users`: List users and their `ProfileId` mapping.
+//! * `GET|PUT /admin/users/{username}/mapping`: Show or override a mapping.
+//! * `POST /admin/users/{username}/resolve`: Resolve a missing mapping again.
+//! * `GET /admin/ambiguous`: List users with several matching profiles.
+//! * `POST /admin/users/{username}/pick`: Pick one of these profiles.
+//! * `GET|PUT /admin/users/{username}/settings`: Read or replace settings (RON).
+//! * `POST /admin/settings/validate`: Validate settings (RON) without saving.
+//! * `GET /admin/users/{username}/explain?change=n&limit=n`: Explain what
+//!   happened to the latest notifications for a user, of the change if given.
+
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    actor::{
+        messages::{
+            GetAmbiguousMappings, GetProfileIdMappings, OverrideProfileIdMapping, PickProfileId,
+            ReadSettings, ReresolveProfileId, SaveSettings,
+        },
+        ResolverClient, UserServiceClient,
+    },
+    controller::util::query_audit_log,
+    service::{constant_time_eq, AuditOutcome, SettingsError},
+    types::{AppState, Candidate, GerritUsername, ProfileId, Settings, Synchronization},
+};
+
+#[derive(Debug, Serialize)]
+pub struct UserEntry {
+    pub username: GerritUsername,
+    pub mapping: Synchronization<ProfileId>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MappingUpdate {
+    #[serde(flatten)]
+    pub entry: UserEntry,
+    // How many pending notifications were delivered
+    pub delivered: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AmbiguousEntry {
+    pub username: GerritUsername,
+    pub candidates: Vec<Candidate<ProfileId>>,
+    // How many notifications are kept until a profile is picked
+    pub pending: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PickRequest {
+    pub profile_id: ProfileId,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExplainQuery {
+    // The change number, all recent events if not set
+    pub change: Option<String>,
+    // How many of the newest events are explained, 100 if not set
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Explanation {
+    pub received: DateTime<Utc>,
+    // The type of the trigger, e.g. comment_added
+    pub event: String,
+    pub change: String,
+    pub author: GerritUsername,
+    // Whether the chat accepted the notification
+    pub notified: bool,
+    // What happened to the notification, e.g. the rule that suppressed it
+    pub outcome: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResolveRequest {
+    // The full name of the user, as known to Gerrit
+    pub name: String,
+}
+
+fn unauthorized() -> HttpResponse {
+    HttpResponse::Unauthorized()
+        .header(header::WWW_AUTHENTICATE, "Bearer")
+        .body("Missing or wrong admin token.")
+}
+
+/// Returns the response to send instead, if the admin API is disabled or the
+/// request isn't authorized.
+fn authorize(request: &HttpRequest, state: &AppState) -> Result<(), HttpResponse> {
+    let expected = match &state.connection.admin_token {
+        Some(token) => token,
+        None => return Err(HttpResponse::NotFound().finish()),
+    };
+
+    let token = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .ok_or_else(unauthorized)?;
+
+    if constant_time_eq(token.trim().as_bytes(), expected.as_bytes()) {
+        Ok(())
+    } else {
+        warn!(
+            "Rejected admin request to {} with wrong token.",
+            request.path()
+        );
+        Err(unauthorized())
+    }
+}
+
+/// Usernames are used as directory names in the data directory, so anything
+/// that could leave it is rejected.
+fn username(path: &web::Path<(String,)>) -> Result<GerritUsername, HttpResponse> {
+    let value = path.0.as_str();
+    if value.is_empty() || value == "." || value == ".." || value.contains(&['/', '\\'][..]) {
+        return Err(HttpResponse::BadRequest().body("Invalid username."));
+    }
+
+    Ok(GerritUsername::from(value))
+}
+
+fn internal_error(e: &str) -> HttpResponse {
+    error!("Admin request failed: {}", e);
+    HttpResponse::InternalServerError().body(e.to_string())
+}
+
+fn settings_error(e: SettingsError) -> HttpResponse {
+    match e {
+        SettingsError::Invalid(_) => HttpResponse::UnprocessableEntity().body(e.to_string()),
+        SettingsError::Io(_) => internal_error(&e.to_string()),
+    }
+}
+
+fn parse_settings(body: &str) -> Result<Settings, SettingsError> {
+    Ok(ron::de::from_str(body)?)
+}
+
+pub async fn list_users(request: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
+    if let Err(response) = authorize(&request, &state) {
+        return response;
+    }
+
+    let mut users: Vec<UserEntry> = vec![];
+    for tenant in state.connection.all_tenants() {
+        match state
+            .acteur
+            .call_actor::<ResolverClient, _>(tenant.id, GetProfileIdMappings)
+            .await
+        {
+            Ok(mappings) => users.extend(
+                mappings
+                    .into_iter()
+                    .map(|(username, mapping)| UserEntry { username, mapping }),
+            ),
+            Err(e) => return internal_error(e),
+        }
+    }
+    users.sort_by(|a, b| a.username.0.cmp(&b.username.0));
+    HttpResponse::Ok().json(users)
+}
+
+pub async fn show_mapping(
+    request: HttpRequest,
+    path: web::Path<(String,)>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    if let Err(response) = authorize(&request, &state) {
+        return response;
+    }
+
+    let username = match username(&path) {
+        Ok(username) => username,
+        Err(response) => return response,
+    };
+    match state
+        .acteur
+        .call_actor::<ResolverClient, _>(
+            state.connection.tenant_of(&username),
+            GetProfileIdMappings,
+        )
+        .await
+    {
+        Ok(mappings) => HttpResponse::Ok().json(UserEntry {
+            mapping: mappings
+                .get(&username)
+                .cloned()
+                .unwrap_or(Synchronization::NotMappedYet),
+            username,
+        }),
+        Err(e) => internal_error(e),
+    }
+}
+
+pub async fn override_mapping(
+    request: HttpRequest,
+    path: web::Path<(String,)>,
+    mapping: web::Json<Synchronization<ProfileId>>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    if let Err(response) = authorize(&request, &state) {
+        return response;
+    }
+
+    let username = match username(&path) {
+        Ok(username) => username,
+        Err(response) => return response,
+    };
+    let mapping = mapping.into_inner();
+    match state
+        .acteur
+        .call_actor::<ResolverClient, _>(
+            state.connection.tenant_of(&username),
+            OverrideProfileIdMapping(username.clone(), mapping.clone()),
+        )
+        .await
+    {
+        Ok(delivered) => HttpResponse::Ok().json(MappingUpdate {
+            entry: UserEntry { username, mapping },
+            delivered,
+        }),
+        Err(e) => internal_error(e),
+    }
+}
+
+pub async fn list_ambiguous(request: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
+    if let Err(response) = authorize(&request, &state) {
+        return response;
+    }
+
+    let mut entries: Vec<AmbiguousEntry> = vec![];
+    for tenant in state.connection.all_tenants() {
+        match state
+            .acteur
+            .call_actor::<ResolverClient, _>(tenant.id, GetAmbiguousMappings)
+            .await
+        {
+            Ok(ambiguous) => entries.extend(ambiguous.into_iter().map(
+                |(username, candidates, pending)| AmbiguousEntry {
+                    username,
+                    candidates,
+                    pending,
+                },
+            )),
+            Err(e) => return internal_error(e),
+        }
+    }
+    entries.sort_by(|a, b| a.username.0.cmp(&b.username.0));
+    HttpResponse::Ok().json(entries)
+}
+
+pub async fn pick_profile(
+    request: HttpRequest,
+    path: web::Path<(String,)>,
+    body: web::Json<PickRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    if let Err(response) = authorize(&request, &state) {
+        return response;
+    }
+
+    let username = match username(&path) {
+        Ok(username) => username,
+        Err(response) => return response,
+    };
+    let profile_id = body.into_inner().profile_id;
+    match state
+        .acteur
+        .call_actor::<ResolverClient, _>(
+            state.connection.tenant_of(&username),
+            PickProfileId(username.clone(), profile_id.clone()),
+        )
+        .await
+    {
+        Ok(Ok(delivered)) => HttpResponse::Ok().json(MappingUpdate {
+            entry: UserEntry {
+                username,
+                mapping: Synchronization::Some(profile_id),
+            },
+            delivered,
+        }),
+        Ok(Err(e)) => HttpResponse::Conflict().body(e),
+        Err(e) => internal_error(e),
+    }
+}
+
+pub async fn resolve_mapping(
+    request: HttpRequest,
+    path: web::Path<(String,)>,
+    body: web::Json<ResolveRequest>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    if let Err(response) = authorize(&request, &state) {
+        return response;
+    }
+
+    let username = match username(&path) {
+        Ok(username) => username,
+        Err(response) => return response,
+    };
+    let result = state
+        .acteur
+        .call_actor::<ResolverClient, _>(
+            state.connection.tenant_of(&username),
+            ReresolveProfileId(username.clone(), body.name.clone()),
+        )
+        .await;
+    match result {
+        Ok(Ok(mapping)) => HttpResponse::Ok().json(UserEntry { username, mapping }),
+        Ok(Err(e)) => HttpResponse::BadGateway().body(e),
+        Err(e) => internal_error(e),
+    }
+}
+
+pub async fn read_settings(
+    request: HttpRequest,
+    path: web::Path<(String,)>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    if let Err(response) = authorize(&request, &state) {
+        return response;
+    }
+
+    let username = match username(&path) {
+        Ok(username) => username,
+        Err(response) => return response,
+    };
+    let settings = match state
+        .acteur
+        .call_actor::<UserServiceClient, _>(
+            state.connection.tenant_of(&username),
+            ReadSettings(username.clone()),
+        )
+        .await
+    {
+        Ok(Ok(Some(settings))) => settings,
+        Ok(Ok(None)) => {
+            return HttpResponse::NotFound().body(format!("'{}' has no settings.", username))
+        }
+        Ok(Err(e)) => return settings_error(e),
+        Err(e) => return internal_error(e),
+    };
+
+    match ron::ser::to_string_pretty(&settings, PrettyConfig::default()) {
+        Ok(serialized) => HttpResponse::Ok()
+            .content_type("text/plain; charset=utf-8")
+            .body(serialized),
+        Err(e) => internal_error(&e.to_string()),
+    }
+}
+
+pub async fn replace_settings(
+    request: HttpRequest,
+    path: web::Path<(String,)>,
+    body: String,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    if let Err(response) = authorize(&request, &state) {
+        return response;
+    }
+
+    let settings = match parse_settings(&body) {
+        Ok(settings) => settings,
+        Err(e) => return settings_error(e),
+    };
+
+    let username = match username(&path) {
+        Ok(username) => username,
+        Err(response) => return response,
+    };
+    info!("Replacing settings of '{}' via admin API.", username);
+    match state
+        .acteur
+        .call_actor::<UserServiceClient, _>(
+            state.connection.tenant_of(&username),
+            SaveSettings(username, settings),
+        )
+        .await
+    {
+        Ok(Ok(())) => HttpResponse::NoContent().finish(),
+        Ok(Err(e)) => settings_error(e),
+        Err(e) => internal_error(e),
+    }
+}
+
+pub async fn validate_settings(
+    request: HttpRequest,
+    body: String,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    if let Err(response) = authorize(&request, &state) {
+        return response;
+    }
+
+    match parse_settings(&body) {
+        Ok(settings) if settings.is_current() => HttpResponse::Ok().body("Settings are valid."),
+        Ok(settings) => HttpResponse::Ok().body(format!(
+            "Settings are valid, but use the outdated version {}. They are migrated on the next load.",
+            settings.version()
+        )),
+        Err(e) => settings_error(e),
+    }
+}
+
+/// Explains what happened to the notifications for the user, as recorded in
+/// the audit log of its tenant, newest first.
+pub async fn explain(
+    request: HttpRequest,
+    path: web::Path<(String,)>,
+    query: web::Query<ExplainQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    if let Err(response) = authorize(&request, &state) {
+        return response;
+    }
+
+    let username = match username(&path) {
+        Ok(username) => username,
+        Err(response) => return response,
+    };
+    let limit = query.limit.unwrap_or(100);
+    let entries =
+        match query_audit_log(&state.connection, &username, query.change.clone(), limit).await {
+            Ok(entries) => entries,
+            Err(e) => return internal_error(&e),
+        };
+
+    let explanations: Vec<Explanation> = entries
+        .into_iter()
+        .rev()
+        .map(|entry| Explanation {
+            received: entry.time,
+            event: entry.trigger.clone(),
+            change: entry.change.clone(),
+            author: entry.author.clone(),
+            notified: matches!(entry.outcome, AuditOutcome::Delivered { .. }),
+            outcome: entry.describe_outcome(),
+        })
+        .collect();
+    HttpResponse::Ok().json(explanations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_settings, username, validate_settings};
+    use crate::cli::{parse_connection_parameters, ConfigSources};
+    use crate::default::DEFAULT_SETTINGS;
+    use crate::service::SettingsError;
+    use crate::types::AppState;
+    use acteur::Acteur;
+    use actix_web::{http::header, http::StatusCode, test, web, App};
+
+    fn state(admin_token: Option<&str>) -> web::Data<AppState> {
+        let sources = ConfigSources {
+            file: [
+                ("chat_bot_profile_id", "PROFILE,1"),
+                ("just_domain", "just.example.com"),
+                ("gerrit_domain", "gerrit.example.com"),
+                ("username", "chatbot"),
+                ("password", "secret"),
+                ("data_dir", "/opt/chtbtr/data"),
+                ("client_id", "client"),
+            ]
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect(),
+            ..ConfigSources::default()
+        };
+        let mut connection = parse_connection_parameters(&sources).unwrap();
+        connection.admin_token = admin_token.map(String::from);
+        web::Data::new(AppState {
+            acteur: Acteur::new(),
+            connection,
+        })
+    }
+
+    /// The status of a request to validate the default settings, with the
+    /// given `Authorization` header.
+    async fn status(admin_token: Option<&str>, authorization: Option<&str>) -> StatusCode {
+        let mut app = test::init_service(
+            App::new()
+                .app_data(state(admin_token))
+                .route("/validate", web::post().to(validate_settings)),
+        )
+        .await;
+        let mut request = test::TestRequest::post()
+            .uri("/validate")
+            .set_payload(DEFAULT_SETTINGS);
+        if let Some(authorization) = authorization {
+            request = request.header(header::AUTHORIZATION, authorization);
+        }
+        test::call_service(&mut app, request.to_request())
+            .await
+            .status()
+    }
+
+    #[actix_rt::test]
+    async fn authorizes_admin_requests() {
+        let token = Some("admin-token");
+
+        assert_eq!(status(token, None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            status(token, Some("Bearer wrong-token")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(token, Some("admin-token")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(None, Some("Bearer admin-token")).await,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            status(token, Some("Bearer admin-token")).await,
+            StatusCode::OK
+        );
+    }
+
+    #[test]
+    fn parses_default_settings() {
+        assert!(parse_settings(DEFAULT_SETTINGS).is_ok());
+    }
+
+    #[test]
+    fn reports_invalid_settings() {
+        match parse_settings("V2(as_owner: ())") {
+            Err(SettingsError::Invalid(_)) => {}
+            other => panic!("Expected invalid settings, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_usernames_leaving_data_dir() {
+        assert!(username(&web::Path::from((String::from(".."),))).is_err());
+        assert!(username(&web::Path::from((String::from("a\\b"),))).is_err());
+        assert!(username(&web::Path::from((String::from("fz.user"),))).is_ok());
+    }
+}
@@ -0,0 +1,370 @@
+use acteur::Acteur;
+use actix_rt::time::delay_for;
+use chrono::{Duration as ChronoDuration, Utc};
+use std::time::Duration;
+
+use crate::{
+    actor::{
+        messages::{
+            FetchChatMessages, GetAppState, LoadSettings, ResolveToGerritUsername, SendChatMessage,
+            UpdateSettings,
+        },
+        AppState, JustClient, ResolverClient, UserServiceClient,
+    },
+    controller::{error::ControllerError, util::query_audit_log},
+    service::{AuditEntry, LoginToken},
+    types::{
+        ChatCommand, GerritUsername, OwnerSettings, ProfileId, ReviewerSettings, RichMessage,
+        Settings, TenantId, Watch,
+    },
+};
+
+/// How many of the newest events of a change are explained.
+const EXPLAINED_EVENTS: usize = 20;
+
+const HELP: &str = "You can change your notification settings by sending me:
+• show settings
+• link (to edit your settings in the browser)
+• subscribe|unsubscribe comments|verified|ready for submit|submitted|reviews
+• ignore|unignore project <project>
+• ignore|unignore user <gerrit username>
+• watch|unwatch <project>[/+/<change number>]
+• mute 30m|2h|1d
+• unmute
+• language en|de
+• why <change number> (explains what happened to the notifications for the change)";
+
+const UNKNOWN_USER: &str =
+    "I don't know your Gerrit account yet. I'll learn it with the first notification I send you.";
+
+/// Polls the conversations of the chatbot of a tenant for new messages and
+/// handles them as chat commands. Runs until the process is stopped.
+///
+/// Messages sent before the poller started are ignored.
+pub async fn poll_chat_commands(acteur: Acteur, tenant: TenantId, interval: Duration) {
+    let mut since = Utc::now();
+    loop {
+        delay_for(interval).await;
+
+        let messages = match acteur
+            .call_actor::<JustClient, FetchChatMessages>(tenant.clone(), FetchChatMessages(since))
+            .await
+        {
+            Ok(Ok(messages)) => messages,
+            Ok(Err(e)) => {
+                warn!("Couldn't poll for chat commands. Cause: {}", e);
+                continue;
+            }
+            Err(e) => {
+                error!("Couldn't call JustClient to poll for chat commands: {}", e);
+                continue;
+            }
+        };
+
+        for message in messages {
+            since = since.max(message.create_date);
+            let author = message.author_id.to_profile_id();
+            if let Err(e) = handle_chat_command(&acteur, &tenant, &author, &message.text).await {
+                error!("Couldn't handle chat command from {}: {}", author, e);
+            }
+        }
+    }
+}
+
+/// Handles a message a user sent to the chatbot of a tenant and replies to it.
+pub async fn handle_chat_command(
+    acteur: &Acteur,
+    tenant: &TenantId,
+    author: &ProfileId,
+    text: &str,
+) -> Result<(), ControllerError> {
+    let reply = compose_reply(acteur, tenant, author, text).await?;
+    debug!("Replying to chat command '{}' from {}.", text, author);
+    acteur
+        .send_to_actor::<JustClient, SendChatMessage>(
+            tenant.clone(),
+            SendChatMessage(author.clone(), RichMessage::text(reply)),
+        )
+        .await;
+    Ok(())
+}
+
+async fn compose_reply(
+    acteur: &Acteur,
+    tenant: &TenantId,
+    author: &ProfileId,
+    text: &str,
+) -> Result<String, ControllerError> {
+    let username: Option<GerritUsername> = acteur
+        .call_actor::<ResolverClient, _>(tenant.clone(), ResolveToGerritUsername(author.clone()))
+        .await
+        .map_err(|e| ControllerError::Unspecified(e.to_string()))?;
+    let username = match username {
+        Some(username) => username,
+        None => return Ok(String::from(UNKNOWN_USER)),
+    };
+
+    let command: ChatCommand = match text.parse() {
+        Ok(command) => command,
+        Err(e) => return Ok(format!("{}\n\n{}", e, HELP)),
+    };
+
+    match command {
+        ChatCommand::Help => Ok(String::from(HELP)),
+        ChatCommand::ShowSettings => {
+            let (settings, _) = acteur
+                .call_actor::<UserServiceClient, _>(tenant.clone(), LoadSettings(username))
+                .await
+                .map_err(|e| ControllerError::Unspecified(e.to_string()))?;
+            Ok(describe_settings(&settings))
+        }
+        ChatCommand::SettingsLink => settings_link(acteur, username).await,
+        ChatCommand::Explain(change) => {
+            let connection = acteur
+                .call_actor::<AppState, _>(0, GetAppState)
+                .await
+                .map_err(|e| ControllerError::Unspecified(e.to_string()))?;
+            let entries = query_audit_log(
+                &connection,
+                &username,
+                Some(change.clone()),
+                EXPLAINED_EVENTS,
+            )
+            .await
+            .map_err(ControllerError::Unspecified)?;
+            Ok(describe_audit_entries(&change, &entries))
+        }
+        command => {
+            info!("{} changes settings with '{}'.", username, text);
+            let now = Utc::now();
+            let result = acteur
+                .call_actor::<UserServiceClient, _>(
+                    tenant.clone(),
+                    UpdateSettings(
+                        username,
+                        Box::new(move |settings| command.apply(settings, now)),
+                    ),
+                )
+                .await
+                .map_err(|e| ControllerError::Unspecified(e.to_string()))?;
+            match result {
+                Ok(settings) => Ok(format!("✅ Done!\n\n{}", describe_settings(&settings))),
+                Err(e) => Ok(format!("❌ Couldn't change your settings. {}", e)),
+            }
+        }
+    }
+}
+
+/// A link to the settings page, that is valid for one hour.
+async fn settings_link(
+    acteur: &Acteur,
+    username: GerritUsername,
+) -> Result<String, ControllerError> {
+    let connection = acteur
+        .call_actor::<AppState, _>(0, GetAppState)
+        .await
+        .map_err(|e| ControllerError::Unspecified(e.to_string()))?;
+    let secret = match connection.web_secret {
+        Some(secret) => secret,
+        None => {
+            return Ok(String::from(
+                "The settings page isn't enabled on this server.",
+            ))
+        }
+    };
+
+    let token = LoginToken {
+        username,
+        expires: Utc::now() + ChronoDuration::hours(1),
+    };
+    Ok(format!(
+        "Edit your settings here, the link is valid for one hour: {}/settings?token={}",
+        connection.public_url,
+        token.sign(&secret)
+    ))
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+fn list<T: ToString>(values: &[T]) -> String {
+    if values.is_empty() {
+        return String::from("none");
+    }
+
+    values
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// A human readable summary of the settings a user can change via chat.
+fn describe_settings(settings: &Settings) -> String {
+    let as_owner: OwnerSettings = settings.clone().into();
+    let as_reviewer: ReviewerSettings = settings.clone().into();
+    let projects = settings.ignored_projects();
+    let users = settings.ignored_users();
+
+    let mut description = format!(
+        "Your settings:
+• comments: {}
+• verified: {}
+• ready for submit: {}
+• submitted: {}
+• reviews: {}
+• ignored projects: {}
+• ignored users: {}",
+        on_off(as_owner.subscribe_comment),
+        on_off(as_owner.subscribe_verified),
+        on_off(as_owner.subscribe_ready_for_submit),
+        on_off(as_owner.subscribe_submitted),
+        on_off(as_reviewer.subscribe),
+        list(&projects),
+        list(&users),
+    );
+
+    description.push_str(&format!("\n• language: {}", settings.language()));
+
+    if let Settings::V2 {
+        watches, schedule, ..
+    } = settings
+    {
+        let watches: Vec<String> = watches.iter().map(Watch::to_string).collect();
+        description.push_str(&format!("\n• watched: {}", list(&watches)));
+        if let Some(quiet_hours) = &schedule.quiet_hours {
+            description.push_str(&format!(
+                "\n• quiet hours: {:02}:00-{:02}:00 UTC{}",
+                quiet_hours.from,
+                quiet_hours.to,
+                schedule.time_zone()
+            ));
+        }
+        if let Some(until) = &schedule.muted_until {
+            if schedule.is_muted(&Utc::now()) {
+                description.push_str(&format!(
+                    "\n• muted until: {}",
+                    until.format("%Y-%m-%d %H:%M UTC")
+                ));
+            }
+        }
+    }
+
+    description
+}
+
+/// Explains what happened to the notifications for the events of a change,
+/// newest first, as recorded in the audit log.
+fn describe_audit_entries(change: &str, entries: &[AuditEntry]) -> String {
+    if entries.is_empty() {
+        return format!(
+            "I haven't received any events of change {} that concern you.",
+            change
+        );
+    }
+
+    let events: Vec<String> = entries
+        .iter()
+        .rev()
+        .map(|entry| {
+            format!(
+                "• {} {} by {}: {} {}",
+                entry.time.format("%Y-%m-%d %H:%M UTC"),
+                entry.trigger,
+                entry.author,
+                entry.outcome_symbol(),
+                entry.describe_outcome()
+            )
+        })
+        .collect();
+    format!("Events of change {}:\n{}", change, events.join("\n"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{describe_audit_entries, describe_settings};
+    use crate::default::default_settings;
+    use crate::service::{AuditEntry, AuditOutcome};
+    use crate::types::{ChatCommand, GerritUsername, ProfileId, QuietHours, Settings};
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn describes_default_settings() {
+        let description = describe_settings(&default_settings());
+
+        assert!(description.contains("• comments: off"));
+        assert!(description.contains("• ignored projects: none"));
+        assert!(description.contains("• ignored users: tools.just, ec2.gerrit"));
+        assert!(description.contains("• watched: none"));
+        assert!(description.contains("• language: en"));
+        assert!(!description.contains("muted"));
+    }
+
+    #[test]
+    fn describes_changed_settings() {
+        let settings = default_settings();
+        let settings = "subscribe comments"
+            .parse::<ChatCommand>()
+            .unwrap()
+            .apply(settings, Utc::now());
+        let mut settings = "mute 2h"
+            .parse::<ChatCommand>()
+            .unwrap()
+            .apply(settings, Utc::now());
+
+        if let Settings::V2 { schedule, .. } = &mut settings {
+            schedule.quiet_hours = Some(QuietHours { from: 20, to: 8 });
+            schedule.utc_offset = 120;
+        }
+
+        let description = describe_settings(&settings);
+
+        assert!(description.contains("• comments: on"));
+        assert!(description.contains("• muted until: "));
+        assert!(description.contains("• quiet hours: 20:00-08:00 UTC+02:00"));
+    }
+
+    #[test]
+    fn describes_audit_entries() {
+        let entry = |outcome| AuditEntry {
+            time: Utc.ymd(2020, 5, 4).and_hms(9, 30, 0),
+            trigger: String::from("comment_added"),
+            instance: None,
+            project: String::from("project"),
+            change: String::from("1234"),
+            author: GerritUsername::from("max"),
+            recipient: GerritUsername::from("jdoe"),
+            outcome,
+        };
+        let entries = vec![
+            entry(AuditOutcome::Suppressed {
+                rule: String::from("OwnerIgnoresCommentsByUser"),
+                reason: String::from("jdoe ignores comments by max."),
+            }),
+            entry(AuditOutcome::DeliveryFailed {
+                profile_id: ProfileId(1),
+                error: String::from("timeout"),
+            }),
+            entry(AuditOutcome::Delivered {
+                profile_id: ProfileId(1),
+            }),
+        ];
+
+        let description = describe_audit_entries("1234", &entries);
+        let lines: Vec<&str> = description.lines().collect();
+
+        assert_eq!(lines[0], "Events of change 1234:");
+        assert_eq!(
+            lines[1],
+            "• 2020-05-04 09:30 UTC comment_added by max: ✅ delivered to PROFILE,1"
+        );
+        assert!(lines[2].ends_with(&format!("⚠️ {}", entries[1].describe_outcome())));
+        assert!(lines[3].ends_with("❌ suppressed: jdoe ignores comments by max."));
+        assert!(describe_audit_entries("1234", &[]).contains("haven't received any events"));
+    }
+}
@@ -1,33 +1,34 @@
 use actix_web::web;
 
 use crate::{
-    actor::{messages::SendChatMessage, JustClient},
     controller::error::ControllerError,
-    service::NotificationMessageComposer,
-    types::{AppState, CommentAddedData, GerritTrigger, OwnerSettings},
+    types::{AppState, CommentAddedData, GerritInstance, GerritTrigger},
 };
 
-use super::{notification_rules::comment_added::notification_wanted, util::extract_user_data};
+use super::{
+    notification_rules::check_notification,
+    util::{extract_user_data, message_composer, notify},
+};
 
 pub async fn comment_added_rewrite(
     trigger: &GerritTrigger,
     comment: &CommentAddedData,
+    instance: &GerritInstance,
     state: web::Data<AppState>,
 ) -> Result<(), ControllerError> {
     let acteur = &state.acteur;
+    let tenant = state.connection.tenant_for_instance(instance);
     let owner = &comment.base.change_owner;
     let username = &comment.base.change_owner_username;
 
-    let (profile_id, settings) = extract_user_data(acteur, owner, username).await?;
-    let settings: OwnerSettings = settings.into();
+    let (recipient, settings) = extract_user_data(acteur, &tenant, owner, username).await?;
+    let composer = message_composer(&state.connection, &tenant, instance, &settings);
 
-    notification_wanted(comment, &settings)?;
+    check_notification(trigger, &settings)?;
 
     debug!("Rule check for comment notification was passed.");
-    let message = NotificationMessageComposer::create().compose(trigger)?;
+    let message = composer.compose(trigger)?;
     debug!("Message is composed. Dispatching actor message to trigger chat message dispatch.");
-    acteur
-        .send_to_service::<JustClient, SendChatMessage>(SendChatMessage(profile_id, message))
-        .await;
+    notify(&state, &tenant, trigger, username, recipient, message).await;
     Ok(())
 }
@@ -0,0 +1,158 @@
+use acteur::Acteur;
+use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::channel;
+use std::time::Duration;
+
+use crate::{
+    actor::{messages::ReloadProfileIdMappings, ResolverClient},
+    types::{GerritUsername, TenantId},
+};
+
+/// A file of a user in the data directory that Chtbtr reads.
+#[derive(Clone, Debug, PartialEq)]
+enum UserFile {
+    Settings(GerritUsername),
+    Sync(GerritUsername),
+}
+
+/// Watches the data directory of a tenant and reloads the `ProfileId` mapping
+/// of users whose sync file changed on disk, e.g. by hand or via
+/// `chtbtr-admin`.
+/// Changes are collected until no file changed for the debounce time. Runs
+/// on its own thread until the process is stopped.
+///
+/// Settings aren't cached, changed settings are used from the next
+/// notification on.
+pub fn watch_data_dir(acteur: Acteur, tenant: TenantId, data_dir: String, debounce: Duration) {
+    std::thread::spawn(move || {
+        let (sender, receiver) = channel();
+        let mut watcher = match watcher(sender, debounce) {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                error!("Couldn't watch {} for changes. Cause: {}", data_dir, e);
+                return;
+            }
+        };
+        if let Err(e) = watcher.watch(&data_dir, RecursiveMode::Recursive) {
+            error!("Couldn't watch {} for changes. Cause: {}", data_dir, e);
+            return;
+        }
+
+        while let Ok(event) = receiver.recv() {
+            // The debounced events of a burst of changes arrive together.
+            let events = std::iter::once(event).chain(receiver.try_iter());
+            let mut users = HashSet::new();
+            for file in events.filter_map(|event| changed_file(Path::new(&data_dir), &event)) {
+                match file {
+                    UserFile::Sync(user) => {
+                        users.insert(user);
+                    }
+                    UserFile::Settings(user) => {
+                        info!("Settings of '{}' changed on disk.", user);
+                    }
+                }
+            }
+
+            if !users.is_empty() {
+                acteur.send_to_actor_sync::<ResolverClient, _>(
+                    tenant.clone(),
+                    ReloadProfileIdMappings(users.into_iter().collect()),
+                );
+            }
+        }
+
+        warn!("Stopped watching {} for changes.", data_dir);
+    });
+}
+
+/// The user file an event is about, if any. Files are written to a temporary
+/// file and renamed, so for renames the target counts.
+fn changed_file(data_dir: &Path, event: &DebouncedEvent) -> Option<UserFile> {
+    let path: &PathBuf = match event {
+        DebouncedEvent::Create(path)
+        | DebouncedEvent::Write(path)
+        | DebouncedEvent::Remove(path)
+        | DebouncedEvent::Rename(_, path) => path,
+        DebouncedEvent::Error(e, path) => {
+            warn!("Error watching {:?}. Cause: {}", path, e);
+            return None;
+        }
+        _ => return None,
+    };
+
+    let relative = path.strip_prefix(data_dir).ok()?;
+    let mut components = relative.iter();
+    let user = GerritUsername::from(components.next()?.to_str()?);
+    let file = components.next()?.to_str()?;
+    if components.next().is_some() {
+        return None;
+    }
+
+    match file {
+        "settings.ron" => Some(UserFile::Settings(user)),
+        "sync.ron" => Some(UserFile::Sync(user)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{changed_file, UserFile};
+    use crate::types::GerritUsername;
+    use notify::DebouncedEvent;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn finds_changed_user_files() {
+        let data_dir = Path::new("/var/chtbtr");
+        let user = GerritUsername::from("user.a");
+
+        assert_eq!(
+            changed_file(
+                data_dir,
+                &DebouncedEvent::Write(PathBuf::from("/var/chtbtr/user.a/settings.ron"))
+            ),
+            Some(UserFile::Settings(user.clone()))
+        );
+        assert_eq!(
+            changed_file(
+                data_dir,
+                &DebouncedEvent::Rename(
+                    PathBuf::from("/var/chtbtr/user.a/sync.ron.tmp"),
+                    PathBuf::from("/var/chtbtr/user.a/sync.ron")
+                )
+            ),
+            Some(UserFile::Sync(user.clone()))
+        );
+        assert_eq!(
+            changed_file(
+                data_dir,
+                &DebouncedEvent::Remove(PathBuf::from("/var/chtbtr/user.a/sync.ron"))
+            ),
+            Some(UserFile::Sync(user))
+        );
+    }
+
+    #[test]
+    fn ignores_other_files() {
+        let data_dir = Path::new("/var/chtbtr");
+
+        for path in &[
+            "/var/chtbtr/user.a/sync.ron.tmp",
+            "/var/chtbtr/user.a/.lock",
+            "/var/chtbtr/user.a/settings.ron.v1.bak",
+            "/var/chtbtr/chtbtr.sqlite",
+            "/var/chtbtr/user.a/nested/sync.ron",
+            "/tmp/user.a/sync.ron",
+        ] {
+            assert_eq!(
+                changed_file(data_dir, &DebouncedEvent::Write(PathBuf::from(path))),
+                None,
+                "{}",
+                path
+            );
+        }
+    }
+}
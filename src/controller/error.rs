@@ -24,11 +24,62 @@ pub enum NotificationRuleViolation {
     /// Which owner is not subscribed?
     OwnerNotSubscribedToVerfiedNotification(GerritUsername),
 
+    /// Which owner is not subscribed to which vote on which label?
+    OwnerNotSubscribedToVote(GerritUsername, String, i8),
+
     /// Which reviewer is not subscribed to notifications?
     ReviewerNotSubscribedToNotification(GerritUsername),
 
     /// Which reviewer ignores what change owner?
     ReviewerIgnoresReviewsByChangeOwner(GerritUsername, GerritUsername),
+
+    /// Which user turned off direct messages?
+    DirectMessagesTurnedOff(GerritUsername),
+
+    /// Which user muted notifications?
+    NotificationsMuted(GerritUsername),
+
+    /// Which user doesn't want notifications at this hour?
+    QuietHours(GerritUsername),
+
+    /// Which watcher of the change caused the event?
+    WatcherIsAuthor(GerritUsername),
+}
+
+impl NotificationRuleViolation {
+    /// The name of the rule, without the users it's about.
+    pub fn name(&self) -> &'static str {
+        match self {
+            NotificationRuleViolation::AuthorAndOwnerAreTheSame => "AuthorAndOwnerAreTheSame",
+            NotificationRuleViolation::NoPatchStatusSet => "NoPatchStatusSet",
+            NotificationRuleViolation::OwnerNotSubscribedToComments(_) => {
+                "OwnerNotSubscribedToComments"
+            }
+            NotificationRuleViolation::OwnerIgnoresCommentsByUser(..) => {
+                "OwnerIgnoresCommentsByUser"
+            }
+            NotificationRuleViolation::OwnerIgnoresCommentsForProject(..) => {
+                "OwnerIgnoresCommentsForProject"
+            }
+            NotificationRuleViolation::OwnerNotSubscribedToSubmitNotification(_) => {
+                "OwnerNotSubscribedToSubmitNotification"
+            }
+            NotificationRuleViolation::OwnerNotSubscribedToVerfiedNotification(_) => {
+                "OwnerNotSubscribedToVerfiedNotification"
+            }
+            NotificationRuleViolation::OwnerNotSubscribedToVote(..) => "OwnerNotSubscribedToVote",
+            NotificationRuleViolation::ReviewerNotSubscribedToNotification(_) => {
+                "ReviewerNotSubscribedToNotification"
+            }
+            NotificationRuleViolation::ReviewerIgnoresReviewsByChangeOwner(..) => {
+                "ReviewerIgnoresReviewsByChangeOwner"
+            }
+            NotificationRuleViolation::DirectMessagesTurnedOff(_) => "DirectMessagesTurnedOff",
+            NotificationRuleViolation::NotificationsMuted(_) => "NotificationsMuted",
+            NotificationRuleViolation::QuietHours(_) => "QuietHours",
+            NotificationRuleViolation::WatcherIsAuthor(_) => "WatcherIsAuthor",
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -84,20 +135,19 @@ impl fmt::Display for NotificationRuleViolation {
             NotificationRuleViolation::OwnerIgnoresCommentsForProject(owner, project) => format!("{} ignores comments for project {}.", owner, project),
             NotificationRuleViolation::OwnerNotSubscribedToSubmitNotification(owner) => format!("{} ignores submit notifications.", owner),
             NotificationRuleViolation::OwnerNotSubscribedToVerfiedNotification(owner) => format!("{} ignores verified notifications.", owner),
+            NotificationRuleViolation::OwnerNotSubscribedToVote(owner, label, value) => format!("{} ignores {} {:+} votes.", owner, label, value),
             NotificationRuleViolation::ReviewerNotSubscribedToNotification(reviewer) => format!("{} ignores notifications to reviews.", reviewer),
             NotificationRuleViolation::ReviewerIgnoresReviewsByChangeOwner(reviewer, owner) => format!("{} ignores reviews from {}.", reviewer, owner),
+            NotificationRuleViolation::DirectMessagesTurnedOff(user) => format!("{} turned off direct messages.", user),
+            NotificationRuleViolation::NotificationsMuted(user) => format!("{} muted notifications.", user),
+            NotificationRuleViolation::QuietHours(user) => format!("{} doesn't want notifications at this hour.", user),
+            NotificationRuleViolation::WatcherIsAuthor(watcher) => format!("{} watches the change, but is the author.", watcher),
         };
 
         write!(f, "{}", message)
     }
 }
 
-impl From<()> for ControllerError {
-    fn from(_err: ()) -> ControllerError {
-        ControllerError::Unspecified(String::from("Unknown error occurred."))
-    }
-}
-
 impl From<actix::prelude::MailboxError> for ControllerError {
     fn from(err: actix::prelude::MailboxError) -> ControllerError {
         ControllerError::Unspecified(format!(
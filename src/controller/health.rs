@@ -0,0 +1,138 @@
+//! Endpoints for monitoring, e.g. systemd or a load balancer.
+//!
+//! * `GET /health`: The server is running and handles requests.
+//! * `GET /ready`: The server can notify users. Checks that the application
+//!   state is set, that the data directories are readable and not read-only,
+//!   and that Just accepts the OAuth token of every chatbot. Replies with
+//!   `503 Service Unavailable` if a check fails.
+
+use actix_web::{web, HttpResponse};
+use serde::Serialize;
+use std::{fs, path::Path};
+
+use crate::{
+    actor::{
+        messages::{IsAppStateSet, ProbeJustApi},
+        AppState as AppStateActor, JustClient,
+    },
+    types::AppState,
+};
+
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub name: String,
+    pub ok: bool,
+    // Why the check failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Check {
+    fn new(name: String, result: Result<(), String>) -> Check {
+        Check {
+            name,
+            ok: result.is_ok(),
+            error: result.err(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    pub ready: bool,
+    pub checks: Vec<Check>,
+}
+
+pub async fn health() -> HttpResponse {
+    HttpResponse::Ok().body("OK")
+}
+
+pub async fn ready(state: web::Data<AppState>) -> HttpResponse {
+    let mut checks = vec![];
+
+    let app_state = state
+        .acteur
+        .call_actor::<AppStateActor, _>(0, IsAppStateSet)
+        .await;
+    checks.push(Check::new(
+        String::from("app_state"),
+        match app_state {
+            Ok(true) => Ok(()),
+            _ => Err(String::from("The application state isn't set.")),
+        },
+    ));
+
+    for tenant in state.connection.all_tenants() {
+        checks.push(Check::new(
+            format!("data_dir:{}", tenant.id),
+            check_data_dir(Path::new(&tenant.data_dir)),
+        ));
+
+        let probe = state
+            .acteur
+            .call_actor::<JustClient, _>(tenant.id.clone(), ProbeJustApi)
+            .await
+            .unwrap_or_else(|_| Err(String::from("The chatbot isn't running.")));
+        checks.push(Check::new(format!("just:{}", tenant.id), probe));
+    }
+
+    let readiness = Readiness {
+        ready: checks.iter().all(|check| check.ok),
+        checks,
+    };
+    if readiness.ready {
+        HttpResponse::Ok().json(readiness)
+    } else {
+        for check in readiness.checks.iter().filter(|check| !check.ok) {
+            warn!(
+                "Not ready, {} failed: {}",
+                check.name,
+                check.error.as_deref().unwrap_or_default()
+            );
+        }
+        HttpResponse::ServiceUnavailable().json(readiness)
+    }
+}
+
+/// Lists the directory and checks that it isn't read-only. Nothing is
+/// written, the probe runs often.
+fn check_data_dir(dir: &Path) -> Result<(), String> {
+    let metadata = fs::metadata(dir).map_err(|e| format!("Can't read {}: {}", dir.display(), e))?;
+    if !metadata.is_dir() {
+        return Err(format!("{} isn't a directory.", dir.display()));
+    }
+    fs::read_dir(dir).map_err(|e| format!("Can't read {}: {}", dir.display(), e))?;
+    if metadata.permissions().readonly() {
+        return Err(format!("{} is read-only.", dir.display()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::check_data_dir;
+
+    #[test]
+    fn checks_data_dir() {
+        let dir = std::env::temp_dir().join(format!("chtbtr-{}", uuid::Uuid::new_v4()));
+
+        assert!(check_data_dir(&dir).is_err());
+
+        std::fs::create_dir_all(&dir).unwrap();
+        assert_eq!(check_data_dir(&dir), Ok(()));
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+
+        let file = dir.join("settings.ron");
+        std::fs::write(&file, b"").unwrap();
+        assert!(check_data_dir(&file).is_err());
+
+        let original = std::fs::metadata(&dir).unwrap().permissions();
+        let mut permissions = original.clone();
+        permissions.set_readonly(true);
+        std::fs::set_permissions(&dir, permissions).unwrap();
+        assert!(check_data_dir(&dir).is_err());
+
+        std::fs::set_permissions(&dir, original).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
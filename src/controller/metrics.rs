@@ -0,0 +1,23 @@
+use actix_web::{web, HttpResponse};
+
+use crate::{
+    actor::{messages::GetMetrics, MetricsCollector},
+    types::AppState,
+};
+
+/// Shows the metrics in the Prometheus text format, see `Metrics`.
+pub async fn metrics(state: web::Data<AppState>) -> HttpResponse {
+    match state
+        .acteur
+        .call_service::<MetricsCollector, _>(GetMetrics)
+        .await
+    {
+        Ok(metrics) => HttpResponse::Ok()
+            .content_type("text/plain; version=0.0.4; charset=utf-8")
+            .body(metrics),
+        Err(_) => {
+            error!("Couldn't collect metrics.");
+            HttpResponse::InternalServerError().body("Couldn't collect metrics.")
+        }
+    }
+}
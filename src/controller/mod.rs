@@ -1,62 +1,361 @@
-use actix_web::{web, Responder};
-
-use std::ops::Deref;
+use actix_rt::time::delay_for;
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use std::time::Duration;
 
 use crate::{
+    actor::{
+        messages::{
+            RecordAudit, RecordHandledTrigger, RecordMetric, RecordTrigger, WasTriggerHandled,
+        },
+        AuditRecorder, MetricsCollector, TriggerHistory,
+    },
     controller::error::ControllerError,
-    types::{AppState, GerritTrigger},
+    service::{
+        AuditEntry, AuditLog, AuditOutcome, Metric, TriggerSignature, TriggerSpool,
+        SIGNATURE_HEADER, TIMESTAMP_HEADER, TRIGGER_ID_HEADER,
+    },
+    types::{AppState, GerritInstance, GerritTrigger},
 };
 
+pub mod admin;
+pub mod chat_command;
 mod comment_added;
+pub mod data_dir_watcher;
 mod error;
+pub mod health;
+pub mod metrics;
 mod notification_rules;
 mod patch_status;
 mod reviewer_added;
+pub mod settings_page;
 mod util;
+mod watch;
+
+/// Parses the trigger in the body of a request. If a trigger secret is
+/// configured, only triggers signed with it are accepted.
+fn parse_trigger(
+    request: &HttpRequest,
+    body: &[u8],
+    state: &AppState,
+) -> Result<GerritTrigger, HttpResponse> {
+    if let Some(secret) = &state.connection.trigger_secret {
+        let header = |name: &str| {
+            request
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default()
+        };
+        let verified = TriggerSignature::verify(
+            header(TIMESTAMP_HEADER),
+            header(SIGNATURE_HEADER),
+            body,
+            secret,
+            &Utc::now(),
+        );
+        if let Err(e) = verified {
+            warn!("Rejected trigger to {}: {}", request.path(), e);
+            return Err(HttpResponse::Unauthorized().body(e));
+        }
+    }
+
+    serde_json::from_slice(body)
+        .map_err(|e| HttpResponse::BadRequest().body(format!("Invalid trigger: {}", e)))
+}
+
+/// Remembers the id the hook gave the trigger of the request, once it's
+/// handled, see `TRIGGER_ID_HEADER`.
+async fn record_handled(
+    request: &HttpRequest,
+    state: &AppState,
+    result: &Result<(), ControllerError>,
+) {
+    if !is_handled(result) {
+        return;
+    }
+    let id = request
+        .headers()
+        .get(TRIGGER_ID_HEADER)
+        .and_then(|value| value.to_str().ok());
+    if let Some(id) = id {
+        state
+            .acteur
+            .send_to_service::<TriggerHistory, _>(RecordHandledTrigger(id.to_string()))
+            .await;
+    }
+}
 
 pub async fn comment_controller(
-    trigger: web::Json<GerritTrigger>,
+    request: HttpRequest,
+    body: web::Bytes,
     state: web::Data<AppState>,
-) -> impl Responder {
-    let result: Result<(), ControllerError> = match trigger.deref() {
-        GerritTrigger::CommentAdded(data) => {
-            comment_added::comment_added_rewrite(&trigger, data, state).await
-        }
-        GerritTrigger::PatchStatusChanged(data) => {
-            patch_status::patch_status_changed(&trigger, state, data).await
+) -> HttpResponse {
+    let trigger = match parse_trigger(&request, &body, &state) {
+        Ok(trigger) => trigger,
+        Err(response) => return response,
+    };
+    state
+        .acteur
+        .send_to_service::<TriggerHistory, _>(RecordTrigger(trigger.clone()))
+        .await;
+
+    let result: Result<(), ControllerError> = match &trigger {
+        GerritTrigger::CommentAdded(_) | GerritTrigger::PatchStatusChanged(_) => {
+            handle_trigger(&trigger, state.clone()).await
         }
         _ => Err(ControllerError::Unrecoverable(String::from(
             "Data doesn't fit endpoint",
         ))),
     };
+    record_handled(&request, &state, &result).await;
 
-    if result.is_err() {
-        let cause = result.err().unwrap();
-        error!("Error: {}", cause);
-        return format!("Error in comment_controller: {}", cause);
-    } else {
-        return String::from("Message send!");
+    match result {
+        Err(cause) => {
+            error!("Error: {}", cause);
+            HttpResponse::Ok().body(format!("Error in comment_controller: {}", cause))
+        }
+        Ok(()) => HttpResponse::Ok().body("Message send!"),
     }
 }
 
 pub async fn reviewer_controller(
-    trigger: web::Json<GerritTrigger>,
+    request: HttpRequest,
+    body: web::Bytes,
     state: web::Data<AppState>,
-) -> impl Responder {
-    let result: Result<(), ControllerError> = match trigger.deref() {
-        GerritTrigger::ReviewerAdded(data) => {
-            reviewer_added::reviewer_added(&trigger, state, data).await
-        }
+) -> HttpResponse {
+    let trigger = match parse_trigger(&request, &body, &state) {
+        Ok(trigger) => trigger,
+        Err(response) => return response,
+    };
+    state
+        .acteur
+        .send_to_service::<TriggerHistory, _>(RecordTrigger(trigger.clone()))
+        .await;
+
+    let result: Result<(), ControllerError> = match &trigger {
+        GerritTrigger::ReviewerAdded(_) => handle_trigger(&trigger, state.clone()).await,
         _ => Err(ControllerError::Unrecoverable(String::from(
             "Data doesn't fit endpoint",
         ))),
     };
+    record_handled(&request, &state, &result).await;
+
+    match result {
+        Err(cause) => {
+            info!("Error: {}", cause);
+            HttpResponse::Ok().body(format!("Error in reviewer_controller: {}", cause))
+        }
+        Ok(()) => HttpResponse::Ok().body("Message send!"),
+    }
+}
+
+/// Sends the notifications for a trigger. The usernames in the trigger are
+/// replaced with the usernames of its Gerrit instance first. The trigger and
+/// its outcome are counted, see `MetricsCollector`, and recorded in the audit
+/// log of the tenant, see `AuditLog`.
+async fn handle_trigger(
+    trigger: &GerritTrigger,
+    state: web::Data<AppState>,
+) -> Result<(), ControllerError> {
+    let acteur = state.acteur.clone();
+    acteur
+        .send_to_service::<MetricsCollector, _>(RecordMetric(Metric::TriggerReceived(
+            trigger.kind(),
+        )))
+        .await;
+
+    let instance = match state.connection.gerrit_instance(trigger.instance()) {
+        Some(instance) => instance.clone(),
+        None => {
+            let error = ControllerError::Unrecoverable(format!(
+                "Unknown Gerrit instance '{}'.",
+                trigger.instance().unwrap_or_default()
+            ));
+            // Without an instance there is no tenant, the log of the default
+            // tenant records the trigger.
+            acteur
+                .send_to_service::<AuditRecorder, _>(RecordAudit(
+                    AuditLog::new(&state.connection.data_dir),
+                    AuditEntry::new(
+                        trigger,
+                        AuditOutcome::Failed {
+                            error: error.to_string(),
+                        },
+                    ),
+                ))
+                .await;
+            return Err(error);
+        }
+    };
+    let trigger = &instance.qualify(trigger.clone());
+    let tenant = state.connection.tenant_for_instance(&instance);
+
+    let result = notify_for_trigger(trigger, &instance, state.clone()).await;
+    if let Err(error) = &result {
+        util::audit_error(&state, &tenant, trigger, trigger.recipient(), error).await;
+    }
+    watch::notify_watchers(trigger, &instance, &state).await;
+    result
+}
+
+async fn notify_for_trigger(
+    trigger: &GerritTrigger,
+    instance: &GerritInstance,
+    state: web::Data<AppState>,
+) -> Result<(), ControllerError> {
+    match trigger {
+        GerritTrigger::CommentAdded(data) => {
+            comment_added::comment_added_rewrite(trigger, data, instance, state).await
+        }
+        GerritTrigger::PatchStatusChanged(data) => {
+            patch_status::patch_status_changed(trigger, state, instance, data).await
+        }
+        GerritTrigger::ReviewerAdded(data) => {
+            reviewer_added::reviewer_added(trigger, state, instance, data).await
+        }
+    }
+}
+
+/// Handles the triggers the hook binaries spooled while the server was down,
+/// once on startup and then every `interval`, so triggers that failed are
+/// retried.
+pub async fn drain_spool(spool: TriggerSpool, state: web::Data<AppState>, interval: Duration) {
+    loop {
+        deliver_spooled_triggers(&spool, &state).await;
+        delay_for(interval).await;
+    }
+}
+
+/// What becomes of a spooled trigger after handling it failed.
+#[derive(Debug, PartialEq)]
+enum SpoolAction {
+    // The notifications were ruled out, the trigger is handled
+    Remove,
+    // Handling failed, but may work next time
+    Retry,
+    // Handling the trigger can't ever work, e.g. for an unknown Gerrit instance
+    Reject,
+}
+
+fn spool_action(error: &ControllerError) -> SpoolAction {
+    match error {
+        ControllerError::Unspecified(_) => SpoolAction::Retry,
+        ControllerError::Unrecoverable(_) => SpoolAction::Reject,
+        _ => SpoolAction::Remove,
+    }
+}
+
+/// Whether a trigger is done with. A trigger that failed, but may work next
+/// time, isn't, so a spooled copy of it is still delivered.
+fn is_handled(result: &Result<(), ControllerError>) -> bool {
+    match result {
+        Ok(()) => true,
+        Err(error) => spool_action(error) != SpoolAction::Retry,
+    }
+}
+
+/// Handles the spooled triggers, oldest first. A trigger is removed from the
+/// spool once it's handled, i.e. the notifications were sent or ruled out, or
+/// if the server handled it already, e.g. after the hook timed out.
+/// Triggers that can't be handled are rejected, see `TriggerSpool::reject`.
+/// Triggers that failed otherwise stay for the next attempt.
+async fn deliver_spooled_triggers(spool: &TriggerSpool, state: &web::Data<AppState>) {
+    let pending = spool.pending();
+    if !pending.is_empty() {
+        info!(
+            "Delivering {} spooled triggers from {}.",
+            pending.len(),
+            spool.dir.display()
+        );
+    }
+
+    for (path, trigger) in pending {
+        if let Some(id) = TriggerSpool::id(&path) {
+            let handled = state
+                .acteur
+                .call_service::<TriggerHistory, _>(WasTriggerHandled(id))
+                .await
+                .unwrap_or(false);
+            if handled {
+                info!("Spooled trigger {} was handled already.", path.display());
+                if let Err(e) = spool.remove(&path) {
+                    error!("{}", e);
+                }
+                continue;
+            }
+        }
+
+        if let Err(cause) = handle_trigger(&trigger, state.clone()).await {
+            match spool_action(&cause) {
+                SpoolAction::Retry => {
+                    warn!(
+                        "Keeping spooled trigger {} to retry. Cause: {}",
+                        path.display(),
+                        cause
+                    );
+                    continue;
+                }
+                SpoolAction::Reject => {
+                    error!(
+                        "Rejecting spooled trigger {}. Cause: {}",
+                        path.display(),
+                        cause
+                    );
+                    if let Err(e) = spool.reject(&path) {
+                        error!("{}", e);
+                    }
+                    continue;
+                }
+                SpoolAction::Remove => info!("Spooled trigger {}: {}", path.display(), cause),
+            }
+        }
+
+        state
+            .acteur
+            .send_to_service::<TriggerHistory, _>(RecordTrigger(trigger.clone()))
+            .await;
+        if let Err(e) = spool.remove(&path) {
+            error!("{}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{is_handled, spool_action, ControllerError, SpoolAction};
+    use crate::controller::error::NotificationRuleViolation;
+
+    #[test]
+    fn rejects_spooled_triggers_that_cant_be_handled() {
+        assert_eq!(
+            spool_action(&ControllerError::RuleViolation(
+                NotificationRuleViolation::NoPatchStatusSet
+            )),
+            SpoolAction::Remove
+        );
+        assert_eq!(
+            spool_action(&ControllerError::Unspecified(String::from(
+                "Couldn't load settings."
+            ))),
+            SpoolAction::Retry
+        );
+        assert_eq!(
+            spool_action(&ControllerError::Unrecoverable(String::from(
+                "Unknown Gerrit instance 'legacy'."
+            ))),
+            SpoolAction::Reject
+        );
+    }
 
-    if result.is_err() {
-        let cause = result.err().unwrap();
-        info!("Error: {}", cause);
-        return format!("Error in reviewer_controller: {}", cause);
-    } else {
-        return String::from("Message send!");
+    #[test]
+    fn only_records_triggers_that_wont_be_retried() {
+        assert!(is_handled(&Ok(())));
+        assert!(is_handled(&Err(ControllerError::Unrecoverable(
+            String::from("Unknown Gerrit instance 'legacy'.")
+        ))));
+        assert!(!is_handled(&Err(ControllerError::Unspecified(
+            String::from("Couldn't load settings.")
+        ))));
     }
 }
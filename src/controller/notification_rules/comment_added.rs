@@ -34,8 +34,7 @@ pub fn notification_wanted(comment: &CommentAddedData, settings: &OwnerSettings)
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::gerrit_triggers::BaseData;
-    use crate::user::types::{GerritUsername, ProjectName};
+    use crate::types::{BaseData, GerritUsername, ProjectName};
 
     fn create_comment_added_data() -> CommentAddedData {
         CommentAddedData {
@@ -44,9 +43,13 @@ mod test {
                 change_owner_username: GerritUsername::from("change.owner"),
                 change_url: String::from("change_url"),
                 project: ProjectName::from("project"),
+                instance: None,
+                patch_set: None,
             },
             author: String::from("Firstname Lastname"),
             author_username: GerritUsername::from("comment.author"),
+            location: None,
+            comment: None,
         }
     }
 
@@ -86,7 +89,9 @@ mod test {
         let result = notification_wanted(&comment, &settings);
         assert_eq!(
             result.err().unwrap(),
-            NotificationRuleViolation::OwnerNotSubscribedToComments
+            NotificationRuleViolation::OwnerNotSubscribedToComments(GerritUsername::from(
+                "change.owner"
+            ))
         );
     }
 
@@ -100,7 +105,10 @@ mod test {
         let result = notification_wanted(&comment, &settings);
         assert_eq!(
             result.err().unwrap(),
-            NotificationRuleViolation::OwnerIgnoresCommentsByUser
+            NotificationRuleViolation::OwnerIgnoresCommentsByUser(
+                GerritUsername::from("change.owner"),
+                GerritUsername::from("comment.author")
+            )
         );
     }
 
@@ -114,7 +122,10 @@ mod test {
         let result = notification_wanted(&comment, &settings);
         assert_eq!(
             result.err().unwrap(),
-            NotificationRuleViolation::OwnerIgnoresCommentsForProject
+            NotificationRuleViolation::OwnerIgnoresCommentsForProject(
+                GerritUsername::from("change.owner"),
+                ProjectName::from("project")
+            )
         );
     }
 }
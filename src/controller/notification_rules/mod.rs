@@ -1,3 +1,296 @@
+use acteur::Acteur;
+use chrono::{DateTime, Utc};
+
+use crate::{
+    actor::{messages::GetRecentTriggers, TriggerHistory},
+    controller::error::NotificationRuleViolation,
+    types::{change_number, ConnectionParameters, GerritTrigger, GerritUsername, Settings},
+};
+
 pub mod comment_added;
 pub mod patch_status;
 pub mod reviewer_added;
+pub mod watch;
+
+/// Checks whether the given user would be notified about the trigger with the
+/// given settings at the given time. Returns `None` if the trigger doesn't
+/// concern the user at all, i.e. the user is neither the change owner nor the
+/// added reviewer and doesn't watch the change.
+pub fn check_trigger(
+    trigger: &GerritTrigger,
+    user: &GerritUsername,
+    settings: &Settings,
+    now: &DateTime<Utc>,
+) -> Option<Result<(), NotificationRuleViolation>> {
+    let result = match trigger {
+        GerritTrigger::CommentAdded(data) if &data.base.change_owner_username == user => {
+            comment_added::notification_wanted(data, &settings.clone().into())
+        }
+        GerritTrigger::PatchStatusChanged(data) if &data.base.change_owner_username == user => {
+            patch_status::check_notification_settings(
+                &settings.clone().into(),
+                settings.labels(),
+                data,
+            )
+        }
+        GerritTrigger::ReviewerAdded(data) if &data.reviewer_username == user => {
+            reviewer_added::notification_wanted(
+                &data.change_owner_username,
+                user,
+                &settings.clone().into(),
+            )
+        }
+        GerritTrigger::CommentAdded(data) if watches(trigger, settings) => {
+            watch::notification_wanted(&data.author_username, None, user)
+        }
+        GerritTrigger::PatchStatusChanged(data) if watches(trigger, settings) => {
+            watch::notification_wanted(&data.author_username, Some(&data.patch_status), user)
+        }
+        _ => return None,
+    };
+
+    Some(
+        result
+            .and_then(|()| check_channel(user, settings))
+            .and_then(|()| check_schedule(user, settings, now)),
+    )
+}
+
+fn watches(trigger: &GerritTrigger, settings: &Settings) -> bool {
+    settings.watches(trigger.project(), change_number(trigger.change_url()))
+}
+
+/// Checks whether the recipient of the trigger wants to be notified about it
+/// now.
+pub fn check_notification(
+    trigger: &GerritTrigger,
+    settings: &Settings,
+) -> Result<(), NotificationRuleViolation> {
+    check_trigger(trigger, trigger.recipient(), settings, &Utc::now()).unwrap_or(Ok(()))
+}
+
+fn check_channel(
+    user: &GerritUsername,
+    settings: &Settings,
+) -> Result<(), NotificationRuleViolation> {
+    if !settings.direct_message() {
+        return Err(NotificationRuleViolation::DirectMessagesTurnedOff(
+            user.clone(),
+        ));
+    }
+
+    Ok(())
+}
+
+fn check_schedule(
+    user: &GerritUsername,
+    settings: &Settings,
+    now: &DateTime<Utc>,
+) -> Result<(), NotificationRuleViolation> {
+    let schedule = match settings.schedule() {
+        Some(schedule) => schedule,
+        None => return Ok(()),
+    };
+    if schedule.is_muted(now) {
+        return Err(NotificationRuleViolation::NotificationsMuted(user.clone()));
+    }
+    if !schedule.allows_notification_at(now) {
+        return Err(NotificationRuleViolation::QuietHours(user.clone()));
+    }
+
+    Ok(())
+}
+
+/// A recent trigger, described for the user, and whether the user is notified
+/// about it, see `check_triggers`.
+pub type Preview = Vec<(DateTime<Utc>, String, Result<(), NotificationRuleViolation>)>;
+
+pub fn describe_trigger(trigger: &GerritTrigger) -> String {
+    match trigger {
+        GerritTrigger::CommentAdded(data) => format!(
+            "{} commented on {}/+/{}",
+            data.author_username, data.base.project, data.base.change_url
+        ),
+        GerritTrigger::PatchStatusChanged(data) => format!(
+            "{} changed the status of {}/+/{}",
+            data.author_username, data.base.project, data.base.change_url
+        ),
+        GerritTrigger::ReviewerAdded(data) => format!(
+            "{} added you as reviewer on {}/+/{}",
+            data.change_owner_username, data.project, data.change_url
+        ),
+    }
+}
+
+/// Checks which of the triggers concern the user and whether they would notify
+/// the user with the given settings at the time they were received. The
+/// triggers keep their order.
+pub fn check_triggers(
+    triggers: Vec<(DateTime<Utc>, GerritTrigger)>,
+    user: &GerritUsername,
+    settings: &Settings,
+) -> Preview {
+    triggers
+        .into_iter()
+        .filter_map(|(received, trigger)| {
+            check_trigger(&trigger, user, settings, &received)
+                .map(|result| (received, describe_trigger(&trigger), result))
+        })
+        .collect()
+}
+
+/// Checks the triggers the server received recently, newest first, see
+/// `check_triggers`. The usernames of the triggers are qualified with their
+/// Gerrit instance first, like for notifications.
+pub async fn preview(
+    acteur: &Acteur,
+    connection: &ConnectionParameters,
+    user: &GerritUsername,
+    settings: &Settings,
+) -> Preview {
+    let triggers = acteur
+        .call_service::<TriggerHistory, _>(GetRecentTriggers)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|(received, trigger)| {
+            let trigger = connection
+                .gerrit_instance(trigger.instance())?
+                .qualify(trigger);
+            Some((received, trigger))
+        })
+        .collect();
+
+    check_triggers(triggers, user, settings)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{check_trigger, check_triggers};
+    use crate::controller::error::NotificationRuleViolation;
+    use crate::default::default_settings;
+    use crate::types::{
+        BaseData, ChatCommand, CommentAddedData, GerritTrigger, GerritUsername, ProjectName,
+        ReviewerAddedData, Settings, Watch,
+    };
+    use chrono::{Duration, Utc};
+
+    fn comment(change_url: &str, author: &str) -> GerritTrigger {
+        GerritTrigger::CommentAdded(CommentAddedData {
+            base: BaseData {
+                change_owner: String::from("Jane Doe"),
+                change_owner_username: GerritUsername::from("jdoe"),
+                change_url: String::from(change_url),
+                project: ProjectName::from("project"),
+                instance: None,
+                patch_set: None,
+            },
+            author: String::from("Author"),
+            author_username: GerritUsername::from(author),
+            location: None,
+            comment: None,
+        })
+    }
+
+    fn reviewer_added(change_url: &str) -> GerritTrigger {
+        GerritTrigger::ReviewerAdded(ReviewerAddedData {
+            change_owner: String::from("Jane Doe"),
+            change_owner_username: GerritUsername::from("jdoe"),
+            reviewer: String::from("Max Mustermann"),
+            reviewer_username: GerritUsername::from("max"),
+            change_url: String::from(change_url),
+            project: String::from("project"),
+            instance: None,
+        })
+    }
+
+    #[test]
+    fn checks_triggers_for_the_user() {
+        let now = Utc::now();
+        let triggers = vec![
+            (now, comment("https://gerrit/c/project/+/1234", "jdoe")),
+            (now, comment("https://gerrit/c/project/+/1235", "max")),
+            (now, reviewer_added("https://gerrit/c/project/+/1234")),
+        ];
+
+        let user = GerritUsername::from("jdoe");
+        let preview = check_triggers(triggers, &user, &default_settings());
+
+        assert_eq!(preview.len(), 2);
+        assert_eq!(
+            preview[0].2,
+            Err(NotificationRuleViolation::AuthorAndOwnerAreTheSame)
+        );
+        assert!(preview[0].1.starts_with("jdoe commented on project/+/"));
+        assert_eq!(
+            preview[1].2,
+            Err(NotificationRuleViolation::OwnerNotSubscribedToComments(
+                user
+            ))
+        );
+    }
+
+    #[test]
+    fn checks_triggers_of_watched_changes() {
+        let now = Utc::now();
+        let mut settings = default_settings();
+        if let Settings::V2 { watches, .. } = &mut settings {
+            watches.push(Watch::from("project/+/1234"));
+        }
+        let watcher = GerritUsername::from("max");
+
+        assert_eq!(
+            check_trigger(
+                &comment("https://gerrit/c/project/+/1234", "jdoe"),
+                &watcher,
+                &settings,
+                &now
+            ),
+            Some(Ok(()))
+        );
+        assert_eq!(
+            check_trigger(
+                &comment("https://gerrit/c/project/+/1234", "max"),
+                &watcher,
+                &settings,
+                &now
+            ),
+            Some(Err(NotificationRuleViolation::WatcherIsAuthor(
+                watcher.clone()
+            )))
+        );
+        assert_eq!(
+            check_trigger(
+                &comment("https://gerrit/c/project/+/1235", "jdoe"),
+                &watcher,
+                &settings,
+                &now
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn checks_schedule_of_the_user() {
+        let now = Utc::now();
+        let settings = ["subscribe comments", "mute 2h"]
+            .iter()
+            .fold(default_settings(), |settings, command| {
+                command.parse::<ChatCommand>().unwrap().apply(settings, now)
+            });
+        let trigger = comment("https://gerrit/c/project/+/1234", "max");
+        let user = GerritUsername::from("jdoe");
+
+        assert_eq!(
+            check_trigger(&trigger, &user, &settings, &now),
+            Some(Err(NotificationRuleViolation::NotificationsMuted(
+                user.clone()
+            )))
+        );
+        let later = now + Duration::hours(3);
+        assert_eq!(
+            check_trigger(&trigger, &user, &settings, &later),
+            Some(Ok(()))
+        );
+    }
+}
@@ -1,10 +1,42 @@
 use crate::{
     controller::error::NotificationRuleViolation,
-    types::{OwnerSettings, PatchStatus, PatchStatusChangedData, VerifiedStatus},
+    types::{
+        GerritUsername, LabelSubscription, OwnerSettings, PatchStatus, PatchStatusChangedData,
+        VerifiedStatus,
+    },
 };
 
+/// Checks a vote on a label. If the owner subscribed to the label, only the
+/// subscribed votes notify. Otherwise `unsubscribed` decides.
+fn check_vote(
+    owner: &GerritUsername,
+    labels: &[LabelSubscription],
+    label: &str,
+    value: i8,
+    unsubscribed: Result<(), NotificationRuleViolation>,
+) -> Result<(), NotificationRuleViolation> {
+    let mut subscriptions = labels
+        .iter()
+        .filter(|subscription| subscription.label.eq_ignore_ascii_case(label))
+        .peekable();
+    if subscriptions.peek().is_none() {
+        return unsubscribed;
+    }
+
+    if subscriptions.any(|subscription| subscription.matches(label, value)) {
+        Ok(())
+    } else {
+        Err(NotificationRuleViolation::OwnerNotSubscribedToVote(
+            owner.clone(),
+            label.to_string(),
+            value,
+        ))
+    }
+}
+
 pub fn check_notification_settings(
     settings: &OwnerSettings,
+    labels: &[LabelSubscription],
     data: &PatchStatusChangedData,
 ) -> Result<(), NotificationRuleViolation> {
     if data.author_username == data.base.change_owner_username {
@@ -13,13 +45,25 @@ pub fn check_notification_settings(
 
     let change_owner = data.base.change_owner_username.clone();
 
-    match data.patch_status {
+    match &data.patch_status {
         PatchStatus::Both(_, verified_status) | PatchStatus::Verified(verified_status) => {
-            if settings.subscribe_verified == false {
-                return Err(NotificationRuleViolation::OwnerNotSubscribedToVerfiedNotification(change_owner));
+            let subscribed = if settings.subscribe_verified {
+                Ok(())
+            } else {
+                Err(
+                    NotificationRuleViolation::OwnerNotSubscribedToVerfiedNotification(
+                        change_owner.clone(),
+                    ),
+                )
+            };
+            let value = verified_status.value();
+            check_vote(&change_owner, labels, "Verified", value, subscribed)?;
+            if let PatchStatus::Both(code_review_status, _) = &data.patch_status {
+                let value = code_review_status.value();
+                check_vote(&change_owner, labels, "Code-Review", value, Ok(()))?;
             }
 
-            if verified_status == VerifiedStatus::None {
+            if verified_status == &VerifiedStatus::None {
                 return Err(NotificationRuleViolation::NoPatchStatusSet);
             }
         }
@@ -28,8 +72,11 @@ pub fn check_notification_settings(
                 return Err(NotificationRuleViolation::OwnerNotSubscribedToSubmitNotification(change_owner));
             }
         }
-        // NOT Supported yet
-        PatchStatus::CodeReview(_) => {}
+        // Without a subscription to the label, every vote notifies
+        PatchStatus::CodeReview(code_review_status) => {
+            let value = code_review_status.value();
+            check_vote(&change_owner, labels, "Code-Review", value, Ok(()))?;
+        }
         PatchStatus::None => return Err(NotificationRuleViolation::NoPatchStatusSet),
     };
 
@@ -39,12 +86,7 @@ pub fn check_notification_settings(
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::gerrit_triggers::{BaseData, PatchStatusChangedData};
-    use crate::patch_status::PatchStatus;
-    use crate::types::{CodeReviewStatus, VerifiedStatus};
-    use crate::user::types::GerritUsername;
-    use crate::user::types::OwnerSettings;
-    use crate::user::types::ProjectName;
+    use crate::types::{BaseData, CodeReviewStatus, ProjectName};
 
     fn create_owner_settings() -> OwnerSettings {
         OwnerSettings {
@@ -65,6 +107,8 @@ mod test {
                 change_owner_username: GerritUsername::from("change.owner"),
                 change_url: String::from("change_url"),
                 project: ProjectName::from("project"),
+                instance: None,
+                patch_set: None,
             },
             author_username: GerritUsername::from("author.user"),
             patch_status: PatchStatus::None,
@@ -83,7 +127,7 @@ mod test {
             ..create_patch_status_changed_data()
         };
 
-        let result = check_notification_settings(&settings, &data);
+        let result = check_notification_settings(&settings, &[], &data);
         assert_eq!(true, result.is_ok());
     }
 
@@ -95,7 +139,7 @@ mod test {
             patch_status: PatchStatus::Verified(VerifiedStatus::PlusOne),
             ..create_patch_status_changed_data()
         };
-        let result = check_notification_settings(&settings, &data);
+        let result = check_notification_settings(&settings, &[], &data);
         assert_eq!(
             NotificationRuleViolation::AuthorAndOwnerAreTheSame,
             result.err().unwrap()
@@ -112,11 +156,13 @@ mod test {
             ..create_patch_status_changed_data()
         };
 
-        let result = check_notification_settings(&settings, &data);
+        let result = check_notification_settings(&settings, &[], &data);
 
         // THEN an error is generated
         assert_eq!(
-            NotificationRuleViolation::OwnerNotSubscribedToSubmitNotification,
+            NotificationRuleViolation::OwnerNotSubscribedToSubmitNotification(
+                GerritUsername::from("change.owner")
+            ),
             result.err().unwrap()
         );
     }
@@ -131,11 +177,13 @@ mod test {
             ..create_patch_status_changed_data()
         };
 
-        let result = check_notification_settings(&settings, &data);
+        let result = check_notification_settings(&settings, &[], &data);
 
         // THEN an error is generated.
         assert_eq!(
-            NotificationRuleViolation::OwnerNotSubscribedToVerfiedNotification,
+            NotificationRuleViolation::OwnerNotSubscribedToVerfiedNotification(
+                GerritUsername::from("change.owner")
+            ),
             result.err().unwrap()
         );
     }
@@ -150,11 +198,13 @@ mod test {
             ..create_patch_status_changed_data()
         };
 
-        let result = check_notification_settings(&settings, &data);
+        let result = check_notification_settings(&settings, &[], &data);
 
         // THEN an error is generated.
         assert_eq!(
-            NotificationRuleViolation::OwnerNotSubscribedToVerfiedNotification,
+            NotificationRuleViolation::OwnerNotSubscribedToVerfiedNotification(
+                GerritUsername::from("change.owner")
+            ),
             result.err().unwrap()
         );
     }
@@ -169,7 +219,7 @@ mod test {
             ..create_patch_status_changed_data()
         };
 
-        let result = check_notification_settings(&settings, &data);
+        let result = check_notification_settings(&settings, &[], &data);
 
         // THEN an error is generated.
         assert_eq!(
@@ -192,7 +242,7 @@ mod test {
             ..create_patch_status_changed_data()
         };
 
-        let result = check_notification_settings(&settings, &data);
+        let result = check_notification_settings(&settings, &[], &data);
 
         // THEN an error is generated
         assert_eq!(
@@ -200,4 +250,92 @@ mod test {
             result.err().unwrap()
         );
     }
+
+    #[test]
+    pub fn label_subscription_takes_precedence() {
+        // GIVEN we are not subscribed to verified, but to Verified -1 votes
+        let settings = create_owner_settings();
+        let labels = vec![LabelSubscription {
+            label: String::from("Verified"),
+            values: vec![-1],
+        }];
+        let data = |patch_status| PatchStatusChangedData {
+            patch_status,
+            ..create_patch_status_changed_data()
+        };
+
+        // THEN only the subscribed votes notify
+        let minus_one = data(PatchStatus::Verified(VerifiedStatus::MinusOne));
+        assert_eq!(
+            check_notification_settings(&settings, &labels, &minus_one),
+            Ok(())
+        );
+        let plus_one = data(PatchStatus::Verified(VerifiedStatus::PlusOne));
+        assert_eq!(
+            NotificationRuleViolation::OwnerNotSubscribedToVote(
+                GerritUsername::from("change.owner"),
+                String::from("Verified"),
+                1
+            ),
+            check_notification_settings(&settings, &labels, &plus_one)
+                .err()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    pub fn label_subscription_restricts_code_review_votes() {
+        let settings = create_owner_settings();
+        let labels = vec![LabelSubscription {
+            label: String::from("Code-Review"),
+            values: vec![-2, 2],
+        }];
+        let data = |status| PatchStatusChangedData {
+            patch_status: PatchStatus::CodeReview(status),
+            ..create_patch_status_changed_data()
+        };
+
+        let plus_two = data(CodeReviewStatus::PlusTwo);
+        assert_eq!(
+            check_notification_settings(&settings, &labels, &plus_two),
+            Ok(())
+        );
+        let plus_one = data(CodeReviewStatus::PlusOne);
+        assert!(check_notification_settings(&settings, &[], &plus_one).is_ok());
+        assert!(check_notification_settings(&settings, &labels, &plus_one).is_err());
+    }
+
+    #[test]
+    pub fn label_subscription_restricts_code_review_votes_when_both_changed() {
+        // GIVEN we are subscribed to verified and to Code-Review -2 and +2 votes
+        let settings = OwnerSettings {
+            subscribe_verified: true,
+            ..create_owner_settings()
+        };
+        let labels = vec![LabelSubscription {
+            label: String::from("Code-Review"),
+            values: vec![-2, 2],
+        }];
+        let data = |status| PatchStatusChangedData {
+            patch_status: PatchStatus::Both(status, VerifiedStatus::PlusOne),
+            ..create_patch_status_changed_data()
+        };
+
+        // THEN only the subscribed Code-Review votes notify
+        let plus_two = data(CodeReviewStatus::PlusTwo);
+        assert_eq!(
+            check_notification_settings(&settings, &labels, &plus_two),
+            Ok(())
+        );
+        let plus_one = data(CodeReviewStatus::PlusOne);
+        assert!(check_notification_settings(&settings, &[], &plus_one).is_ok());
+        assert_eq!(
+            check_notification_settings(&settings, &labels, &plus_one),
+            Err(NotificationRuleViolation::OwnerNotSubscribedToVote(
+                GerritUsername::from("change.owner"),
+                String::from("Code-Review"),
+                1
+            ))
+        );
+    }
 }
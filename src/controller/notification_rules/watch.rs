@@ -0,0 +1,56 @@
+use crate::{
+    controller::error::NotificationRuleViolation,
+    types::{GerritUsername, PatchStatus, VerifiedStatus},
+};
+
+type IResult = Result<(), NotificationRuleViolation>;
+
+/**
+ * Only performs checks to verify that a watcher, who is neither owner nor
+ * reviewer, should be notified about a comment or, if given, a patch status.
+ */
+pub fn notification_wanted(
+    author: &GerritUsername,
+    patch_status: Option<&PatchStatus>,
+    watcher: &GerritUsername,
+) -> IResult {
+    if author == watcher {
+        return Err(NotificationRuleViolation::WatcherIsAuthor(watcher.clone()));
+    }
+
+    match patch_status {
+        Some(PatchStatus::None) | Some(PatchStatus::Verified(VerifiedStatus::None)) => {
+            Err(NotificationRuleViolation::NoPatchStatusSet)
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    pub fn skip_when_watcher_is_author() {
+        let watcher = GerritUsername::from("watcher");
+        assert_eq!(
+            notification_wanted(&watcher, None, &watcher),
+            Err(NotificationRuleViolation::WatcherIsAuthor(watcher.clone()))
+        );
+    }
+
+    #[test]
+    pub fn notify_about_comments_and_votes() {
+        let author = GerritUsername::from("author");
+        let watcher = GerritUsername::from("watcher");
+        assert_eq!(notification_wanted(&author, None, &watcher), Ok(()));
+        assert_eq!(
+            notification_wanted(&author, Some(&PatchStatus::ReadyForSubmit), &watcher),
+            Ok(())
+        );
+        assert_eq!(
+            notification_wanted(&author, Some(&PatchStatus::None), &watcher),
+            Err(NotificationRuleViolation::NoPatchStatusSet)
+        );
+    }
+}
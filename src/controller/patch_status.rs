@@ -1,35 +1,34 @@
 use actix_web::web;
 
 use crate::{
-    actor::{messages::SendChatMessage, JustClient},
     controller::error::ControllerError,
-    service::NotificationMessageComposer,
-    types::{GerritTrigger, PatchStatusChangedData, AppState, OwnerSettings},
+    types::{GerritInstance, GerritTrigger, PatchStatusChangedData, AppState},
 };
 
 use super::{
-    notification_rules::patch_status::check_notification_settings, util::extract_user_data,
+    notification_rules::check_notification,
+    util::{extract_user_data, message_composer, notify},
 };
 
 pub async fn patch_status_changed(
     trigger: &GerritTrigger,
     state: web::Data<AppState>,
+    instance: &GerritInstance,
     data: &PatchStatusChangedData,
 ) -> Result<(), ControllerError> {
     let acteur = state.acteur.clone();
+    let tenant = state.connection.tenant_for_instance(instance);
     let owner = &data.base.change_owner;
     let username = &data.base.change_owner_username;
 
-    let (profile_id, settings) = extract_user_data(&acteur, owner, username).await?;
-    let settings: OwnerSettings = settings.into();
+    let (recipient, settings) = extract_user_data(&acteur, &tenant, owner, username).await?;
+    let composer = message_composer(&state.connection, &tenant, instance, &settings);
 
-    check_notification_settings(&settings, data)?;
+    check_notification(trigger, &settings)?;
 
-    let message = NotificationMessageComposer::create().compose(&trigger)?;
+    let message = composer.compose(trigger)?;
     debug!("Message is composed. Dispatching actor message to trigger chat message dispatch.");
-    acteur
-        .send_to_service::<JustClient, SendChatMessage>(SendChatMessage(profile_id, message))
-        .await;
+    notify(&state, &tenant, trigger, username, recipient, message).await;
 
     Ok(())
 }
@@ -1,36 +1,42 @@
 use actix_web::web;
 
-use super::{notification_rules::reviewer_added::notification_wanted, util::extract_user_data};
+use super::{
+    notification_rules::check_notification,
+    util::{extract_user_data, message_composer, notify},
+};
 use crate::{
-    actor::{messages::SendChatMessage, JustClient},
     controller::error::ControllerError,
-    types::{AppState, GerritTrigger, ReviewerAddedData, ReviewerSettings},
+    types::{AppState, GerritInstance, GerritTrigger, ReviewerAddedData},
 };
 
 pub async fn reviewer_added(
-    _: &GerritTrigger,
+    trigger: &GerritTrigger,
     state: web::Data<AppState>,
+    instance: &GerritInstance,
     data: &ReviewerAddedData,
 ) -> Result<(), ControllerError> {
     let acteur = state.acteur.clone();
-    let _domain = state.connection.gerrit_domain.clone();
-    let change_owner = &data.change_owner_username;
+    let tenant = state.connection.tenant_for_instance(instance);
     let reviewer_username = &data.reviewer_username;
     let reviewer = &data.reviewer;
 
-    let (reviewer_id, settings) = extract_user_data(&acteur, reviewer, reviewer_username).await?;
-    let settings: ReviewerSettings = settings.into();
+    let (recipient, settings) =
+        extract_user_data(&acteur, &tenant, reviewer, reviewer_username).await?;
+    let composer = message_composer(&state.connection, &tenant, instance, &settings);
 
-    notification_wanted(change_owner, reviewer_username, &settings)?;
+    check_notification(trigger, &settings)?;
 
-    let message = format!(
-        "You were added as reviewer. https://gerrit.just-ag.com/c/{}/+/{}",
-        data.project, data.change_url
-    );
+    let message = composer.compose(trigger)?;
 
     debug!("Message is composed. Dispatching actor message to trigger chat message dispatch.");
-    acteur
-        .send_to_service::<JustClient, SendChatMessage>(SendChatMessage(reviewer_id, message))
-        .await;
+    notify(
+        &state,
+        &tenant,
+        trigger,
+        reviewer_username,
+        recipient,
+        message,
+    )
+    .await;
     Ok(())
 }
@@ -0,0 +1,467 @@
+use actix_web::{web, HttpResponse};
+use chrono::Utc;
+use serde::Deserialize;
+
+use crate::{
+    actor::{
+        messages::{LoadSettings, UpdateSettings},
+        UserServiceClient,
+    },
+    controller::notification_rules::{preview, Preview},
+    service::LoginToken,
+    types::{AppState, GerritUsername, Language, OwnerSettings, ReviewerSettings, Settings, Watch},
+};
+
+#[derive(Debug, Deserialize)]
+pub struct TokenQuery {
+    token: String,
+}
+
+/// The form of the settings page. Checkboxes are only sent if they are checked,
+/// lists are sent as one entry per line.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct SettingsForm {
+    token: String,
+    action: String,
+    comments: Option<String>,
+    verified: Option<String>,
+    ready_for_submit: Option<String>,
+    submitted: Option<String>,
+    reviews: Option<String>,
+    owner_ignore_projects: String,
+    owner_ignore_users: String,
+    reviewer_ignore_projects: String,
+    reviewer_ignore_users: String,
+    watches: String,
+    language: Option<String>,
+    plain_text: Option<String>,
+}
+
+fn lines<'a, T: From<&'a str> + PartialEq>(value: &'a str) -> Vec<T> {
+    let mut result: Vec<T> = vec![];
+    for line in value.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        let line = T::from(line);
+        if !result.contains(&line) {
+            result.push(line);
+        }
+    }
+    result
+}
+
+impl SettingsForm {
+    /// Applies the form to the settings. Settings that aren't part of the form
+    /// are kept.
+    pub fn apply(&self, settings: Settings) -> Settings {
+        let mut settings = settings.migrate();
+        if let Settings::V2 {
+            as_reviewer,
+            as_owner,
+            watches,
+            channel,
+            ..
+        } = &mut settings
+        {
+            as_owner.subscribe_comment = self.comments.is_some();
+            as_owner.subscribe_verified = self.verified.is_some();
+            as_owner.subscribe_ready_for_submit = self.ready_for_submit.is_some();
+            as_owner.subscribe_submitted = self.submitted.is_some();
+            as_reviewer.subscribe = self.reviews.is_some();
+
+            as_owner.ignore_projects = lines(&self.owner_ignore_projects);
+            as_owner.ignore_by_username = lines(&self.owner_ignore_users);
+            as_reviewer.ignore_projects = lines(&self.reviewer_ignore_projects);
+            as_reviewer.ignore_by_username = lines(&self.reviewer_ignore_users);
+            *watches = lines(&self.watches);
+            if let Some(language) = self.language.as_ref().and_then(|l| l.parse().ok()) {
+                channel.language = language;
+            }
+            channel.plain_text = self.plain_text.is_some();
+        }
+
+        settings
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+fn html(status: &mut actix_web::dev::HttpResponseBuilder, body: String) -> HttpResponse {
+    status.content_type("text/html; charset=utf-8").body(body)
+}
+
+fn error_page(status: &mut actix_web::dev::HttpResponseBuilder, message: &str) -> HttpResponse {
+    html(
+        status,
+        format!(
+            "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>chtbtr</title></head>\
+             <body><p>{}</p></body></html>",
+            escape(message)
+        ),
+    )
+}
+
+/// Verifies the token of a request. Returns the response to send instead, if
+/// the page is disabled or the token is invalid.
+fn authenticate(state: &AppState, token: &str) -> Result<GerritUsername, HttpResponse> {
+    let secret = match &state.connection.web_secret {
+        Some(secret) => secret,
+        None => {
+            return Err(error_page(
+                &mut HttpResponse::NotFound(),
+                "The settings page isn't enabled on this server.",
+            ))
+        }
+    };
+
+    LoginToken::verify(token, secret, &Utc::now())
+        .map(|token| token.username)
+        .map_err(|e| error_page(&mut HttpResponse::Forbidden(), &e))
+}
+
+fn checkbox(name: &str, label: &str, checked: bool) -> String {
+    format!(
+        "<label><input type=\"checkbox\" name=\"{}\"{}> {}</label><br>",
+        name,
+        if checked { " checked" } else { "" },
+        label
+    )
+}
+
+fn textarea<T: ToString>(name: &str, label: &str, values: &[T]) -> String {
+    let values: Vec<String> = values.iter().map(|v| escape(&v.to_string())).collect();
+    format!(
+        "<label>{}<br><textarea name=\"{}\" rows=\"4\" cols=\"40\">{}</textarea></label><br>",
+        label,
+        name,
+        values.join("\n")
+    )
+}
+
+fn language_select(selected: Language) -> String {
+    let options: Vec<String> = Language::ALL
+        .iter()
+        .map(|language| {
+            format!(
+                "<option value=\"{}\"{}>{}</option>",
+                language.code(),
+                if *language == selected {
+                    " selected"
+                } else {
+                    ""
+                },
+                match language {
+                    Language::English => "English",
+                    Language::German => "Deutsch",
+                }
+            )
+        })
+        .collect();
+    format!(
+        "<label>Language of notifications <select name=\"language\">{}</select></label><br>",
+        options.join("")
+    )
+}
+
+fn render(
+    user: &GerritUsername,
+    token: &str,
+    settings: &Settings,
+    preview: &Preview,
+    notice: Option<&str>,
+) -> String {
+    let as_owner: OwnerSettings = settings.clone().into();
+    let as_reviewer: ReviewerSettings = settings.clone().into();
+    let watches: Vec<Watch> = match settings {
+        Settings::V2 { watches, .. } => watches.clone(),
+        _ => vec![],
+    };
+
+    let preview = if preview.is_empty() {
+        String::from("<p>No recent events concern you.</p>")
+    } else {
+        let rows: Vec<String> = preview
+            .iter()
+            .map(|(received, description, result)| {
+                let outcome = match result {
+                    Ok(()) => String::from("✅ notified"),
+                    Err(reason) => format!("❌ {}", reason),
+                };
+                format!(
+                    "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                    received.format("%Y-%m-%d %H:%M"),
+                    escape(description),
+                    escape(&outcome)
+                )
+            })
+            .collect();
+        format!("<table>{}</table>", rows.join(""))
+    };
+
+    format!(
+        "<!DOCTYPE html>
+<html>
+<head><meta charset=\"utf-8\"><title>chtbtr settings for {user}</title></head>
+<body>
+<h1>Settings for {user}</h1>
+{notice}
+<form method=\"post\" action=\"settings\">
+<input type=\"hidden\" name=\"token\" value=\"{token}\">
+<h2>Notify me about</h2>
+{comments}
+{verified}
+{ready_for_submit}
+{submitted}
+{reviews}
+<h2>Ignore on my changes</h2>
+{owner_ignore_projects}
+{owner_ignore_users}
+<h2>Ignore as reviewer</h2>
+{reviewer_ignore_projects}
+{reviewer_ignore_users}
+<h2>Watch</h2>
+{watches}
+<h2>Messages</h2>
+{language}
+{plain_text}
+<button type=\"submit\" name=\"action\" value=\"preview\">Preview</button>
+<button type=\"submit\" name=\"action\" value=\"save\">Save</button>
+</form>
+<h2>Recent events</h2>
+{preview}
+</body>
+</html>",
+        user = escape(&user.0),
+        notice = notice
+            .map(|n| format!("<p><strong>{}</strong></p>", escape(n)))
+            .unwrap_or_default(),
+        token = escape(token),
+        comments = checkbox(
+            "comments",
+            "Comments on my changes",
+            as_owner.subscribe_comment
+        ),
+        verified = checkbox(
+            "verified",
+            "Verified votes on my changes",
+            as_owner.subscribe_verified
+        ),
+        ready_for_submit = checkbox(
+            "ready_for_submit",
+            "My changes are ready for submit",
+            as_owner.subscribe_ready_for_submit
+        ),
+        submitted = checkbox(
+            "submitted",
+            "My changes are submitted",
+            as_owner.subscribe_submitted
+        ),
+        reviews = checkbox("reviews", "I am added as reviewer", as_reviewer.subscribe),
+        owner_ignore_projects = textarea(
+            "owner_ignore_projects",
+            "Projects, one per line",
+            &as_owner.ignore_projects
+        ),
+        owner_ignore_users = textarea(
+            "owner_ignore_users",
+            "Gerrit users, one per line",
+            &as_owner.ignore_by_username
+        ),
+        reviewer_ignore_projects = textarea(
+            "reviewer_ignore_projects",
+            "Projects, one per line",
+            &as_reviewer.ignore_projects
+        ),
+        reviewer_ignore_users = textarea(
+            "reviewer_ignore_users",
+            "Gerrit users, one per line",
+            &as_reviewer.ignore_by_username
+        ),
+        watches = textarea(
+            "watches",
+            "Projects or changes like project/+/1234, one per line",
+            &watches
+        ),
+        language = language_select(settings.language()),
+        plain_text = checkbox(
+            "plain_text",
+            "Plain text only, without details like the project",
+            settings.prefers_plain_text()
+        ),
+        preview = preview,
+    )
+}
+
+/// Shows the settings page of the user the token was issued for.
+pub async fn show_settings(
+    query: web::Query<TokenQuery>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let user = match authenticate(&state, &query.token) {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let result = state
+        .acteur
+        .call_actor::<UserServiceClient, _>(
+            state.connection.tenant_of(&user),
+            LoadSettings(user.clone()),
+        )
+        .await;
+    let (settings, error) = match result {
+        Ok(result) => result,
+        Err(e) => {
+            error!("Couldn't load settings for settings page: {}", e);
+            return error_page(
+                &mut HttpResponse::InternalServerError(),
+                "Couldn't load your settings.",
+            );
+        }
+    };
+
+    let preview = preview(&state.acteur, &state.connection, &user, &settings).await;
+    let notice = error.map(|e| e.to_string());
+    html(
+        &mut HttpResponse::Ok(),
+        render(&user, &query.token, &settings, &preview, notice.as_deref()),
+    )
+}
+
+/// Previews or saves the settings sent with the form. Saving fails, if the
+/// stored settings can't be loaded, so they aren't replaced.
+pub async fn update_settings(
+    form: web::Form<SettingsForm>,
+    state: web::Data<AppState>,
+) -> HttpResponse {
+    let user = match authenticate(&state, &form.token) {
+        Ok(user) => user,
+        Err(response) => return response,
+    };
+
+    let notice = if form.action == "save" {
+        info!("{} saves settings via the settings page.", user);
+        let apply = form.clone();
+        let result = state
+            .acteur
+            .call_actor::<UserServiceClient, _>(
+                state.connection.tenant_of(&user),
+                UpdateSettings(
+                    user.clone(),
+                    Box::new(move |settings| apply.apply(settings)),
+                ),
+            )
+            .await;
+        match result {
+            Ok(Ok(_)) => String::from("Your settings are saved."),
+            Ok(Err(e)) => format!("Couldn't save your settings. {}", e),
+            Err(e) => format!("Couldn't save your settings. {}", e),
+        }
+    } else {
+        String::from("This is a preview, your settings aren't saved yet.")
+    };
+
+    let result = state
+        .acteur
+        .call_actor::<UserServiceClient, _>(
+            state.connection.tenant_of(&user),
+            LoadSettings(user.clone()),
+        )
+        .await;
+    let (settings, error) = match result {
+        Ok((settings, error)) => (form.apply(settings), error),
+        Err(e) => {
+            error!("Couldn't load settings for settings page: {}", e);
+            return error_page(
+                &mut HttpResponse::InternalServerError(),
+                "Couldn't load your settings.",
+            );
+        }
+    };
+    let notice = match error {
+        Some(e) => format!("{} {}", notice, e),
+        None => notice,
+    };
+
+    let preview = preview(&state.acteur, &state.connection, &user, &settings).await;
+    html(
+        &mut HttpResponse::Ok(),
+        render(&user, &form.token, &settings, &preview, Some(&notice)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape, render, SettingsForm};
+    use crate::default::default_settings;
+    use crate::types::{
+        GerritUsername, Language, OwnerSettings, ProjectName, ReviewerSettings, Settings, Watch,
+    };
+
+    #[test]
+    fn applies_form_to_settings() {
+        let form = SettingsForm {
+            comments: Some(String::from("on")),
+            reviews: Some(String::from("on")),
+            reviewer_ignore_projects: String::from("foo\r\n\r\n bar \nfoo"),
+            owner_ignore_users: String::from("tools.just"),
+            watches: String::from("juco\njuco/+/1234"),
+            language: Some(String::from("de")),
+            ..SettingsForm::default()
+        };
+
+        let settings = form.apply(default_settings());
+        let as_owner: OwnerSettings = settings.clone().into();
+        let as_reviewer: ReviewerSettings = settings.clone().into();
+
+        assert!(as_owner.subscribe_comment);
+        assert!(!as_owner.subscribe_verified);
+        assert!(as_reviewer.subscribe);
+        assert_eq!(settings.language(), Language::German);
+        assert_eq!(
+            as_reviewer.ignore_projects,
+            vec![ProjectName::from("foo"), ProjectName::from("bar")]
+        );
+        assert!(as_owner.ignore_projects.is_empty());
+        assert_eq!(
+            as_owner.ignore_by_username,
+            vec![GerritUsername::from("tools.just")]
+        );
+        assert!(as_reviewer.ignore_by_username.is_empty());
+        match settings {
+            Settings::V2 { watches, .. } => {
+                assert_eq!(
+                    watches,
+                    vec![Watch::from("juco"), Watch::from("juco/+/1234")]
+                )
+            }
+            _ => panic!("Settings weren't migrated."),
+        }
+    }
+
+    #[test]
+    fn escapes_user_input() {
+        let form = SettingsForm {
+            owner_ignore_projects: String::from("<script>"),
+            ..SettingsForm::default()
+        };
+        let settings = form.apply(default_settings());
+
+        let page = render(
+            &GerritUsername::from("a&b"),
+            "token",
+            &settings,
+            &vec![],
+            None,
+        );
+
+        assert!(page.contains("&lt;script&gt;"));
+        assert!(page.contains("Settings for a&amp;b"));
+        assert_eq!(escape("\"'"), "&quot;&#39;");
+    }
+}
@@ -1,25 +1,183 @@
 use acteur::Acteur;
+use actix_web::{error::BlockingError, web};
 
 use super::error::ControllerError;
 use crate::{
-    actor::{messages::GetUserData, ControllerClient},
-    types::{GerritUsername, ProfileId, Settings},
+    actor::{
+        messages::{
+            DeliverNotification, GetUserData, QueueNotification, RecordAudit, RecordMetric,
+        },
+        AuditRecorder, ControllerClient, JustClient, MetricsCollector, ResolverClient,
+    },
+    service::{
+        AuditEntry, AuditLog, AuditOutcome, AuditQuery, MessageTemplates, Metric,
+        NotificationMessageComposer,
+    },
+    types::{
+        AppState, ConnectionParameters, GerritInstance, GerritTrigger, GerritUsername, ProfileId,
+        RichMessage, Settings, Synchronization, TenantId,
+    },
 };
 
+/// Who a notification is sent to.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Recipient {
+    Profile(ProfileId),
+    // The mapping of the user is ambiguous, the notification is kept until an
+    // admin picks the right profile.
+    Pending(GerritUsername),
+}
+
+/// Sends the notification to the user via the chatbot of the tenant, or keeps
+/// it if the user has no profile yet. The outcome is recorded in the audit log.
+pub async fn notify(
+    state: &AppState,
+    tenant: &TenantId,
+    trigger: &GerritTrigger,
+    user: &GerritUsername,
+    recipient: Recipient,
+    message: RichMessage,
+) {
+    let acteur = &state.acteur;
+    let log = audit_log(&state.connection, tenant);
+    let entry = AuditEntry::new(trigger, AuditOutcome::Queued).with_recipient(user.clone());
+    match recipient {
+        Recipient::Profile(profile_id) => {
+            // The chatbot records the entry and counts the notification once
+            // it knows if the message was delivered
+            acteur
+                .send_to_actor::<JustClient, DeliverNotification>(
+                    tenant.clone(),
+                    DeliverNotification(profile_id, message, log, entry),
+                )
+                .await;
+        }
+        Recipient::Pending(username) => {
+            acteur
+                .send_to_actor::<ResolverClient, QueueNotification>(
+                    tenant.clone(),
+                    QueueNotification(username, message, entry.clone()),
+                )
+                .await;
+            acteur
+                .send_to_service::<AuditRecorder, _>(RecordAudit(log, entry))
+                .await;
+            acteur
+                .send_to_service::<MetricsCollector, _>(RecordMetric(Metric::NotificationQueued))
+                .await;
+        }
+    }
+}
+
+/// Records why the notification of the user about the trigger wasn't sent.
+/// Suppressed notifications are counted, see `MetricsCollector`.
+pub async fn audit_error(
+    state: &AppState,
+    tenant: &TenantId,
+    trigger: &GerritTrigger,
+    user: &GerritUsername,
+    error: &ControllerError,
+) {
+    let acteur = &state.acteur;
+    let outcome = match error {
+        ControllerError::RuleViolation(violation) => {
+            acteur
+                .send_to_service::<MetricsCollector, _>(RecordMetric(
+                    Metric::NotificationSuppressed(violation.name()),
+                ))
+                .await;
+            AuditOutcome::Suppressed {
+                rule: violation.name().to_string(),
+                reason: violation.to_string(),
+            }
+        }
+        error => AuditOutcome::Failed {
+            error: error.to_string(),
+        },
+    };
+    acteur
+        .send_to_service::<AuditRecorder, _>(RecordAudit(
+            audit_log(&state.connection, tenant),
+            AuditEntry::new(trigger, outcome).with_recipient(user.clone()),
+        ))
+        .await;
+}
+
+/// The audit log in the data directory of the tenant.
+pub fn audit_log(connection: &ConnectionParameters, tenant: &TenantId) -> AuditLog {
+    let data_dir = connection
+        .tenant(tenant)
+        .map(|tenant| tenant.data_dir)
+        .unwrap_or_else(|| connection.data_dir.clone());
+    AuditLog::new(&data_dir)
+}
+
+/// The entries of the audit log of the tenant about the user and optionally a
+/// change of the user's Gerrit instance, at most `limit`. The logs are read on
+/// the thread pool, they may be large.
+pub async fn query_audit_log(
+    connection: &ConnectionParameters,
+    username: &GerritUsername,
+    change: Option<String>,
+    limit: usize,
+) -> Result<Vec<AuditEntry>, String> {
+    let log = audit_log(connection, &connection.tenant_of(username));
+    let query = AuditQuery {
+        user: Some(username.clone()),
+        instance: connection.instance_of(username).cloned(),
+        change,
+        limit: Some(limit),
+    };
+    web::block(move || log.query(&query))
+        .await
+        .map_err(|e| match e {
+            BlockingError::Error(e) => e,
+            BlockingError::Canceled => String::from("Couldn't query the audit log."),
+        })
+}
+
+/// Composes notifications in the language and format the recipient chose,
+/// using the templates in the data directory of the tenant.
+pub fn message_composer(
+    connection: &ConnectionParameters,
+    tenant: &TenantId,
+    instance: &GerritInstance,
+    settings: &Settings,
+) -> NotificationMessageComposer {
+    let templates = connection
+        .tenant(tenant)
+        .map(|tenant| MessageTemplates::load(&tenant.data_dir))
+        .unwrap_or_default();
+    NotificationMessageComposer::create(instance.base_url())
+        .with_templates(templates, settings.language())
+        .prefer_plain_text(settings.prefers_plain_text())
+}
+
 // TODO Fix error handling.
 pub async fn extract_user_data(
     acteur: &Acteur,
+    tenant: &TenantId,
     change_owner: &str,
     change_owner_username: &GerritUsername,
-) -> Result<(ProfileId, Settings), ControllerError> {
-    let get_user_data = GetUserData(change_owner_username.clone(), change_owner.to_string());
+) -> Result<(Recipient, Settings), ControllerError> {
+    let get_user_data = GetUserData(
+        change_owner_username.clone(),
+        change_owner.to_string(),
+        tenant.clone(),
+    );
 
-    let (profile_id, settings): (Option<ProfileId>, Option<Settings>) = acteur
+    let (mapping, settings): (Synchronization<ProfileId>, Option<Settings>) = acteur
         .call_service::<ControllerClient, _>(get_user_data)
         .await
         .expect("Error when calling ControllerClient actor to receive user data.");
 
-    if profile_id.is_none() {
+    let recipient = match mapping {
+        Synchronization::Some(profile_id) => Some(Recipient::Profile(profile_id)),
+        Synchronization::Ambiguous(_) => Some(Recipient::Pending(change_owner_username.clone())),
+        Synchronization::None | Synchronization::NotMappedYet => None,
+    };
+
+    if recipient.is_none() {
         warn!(
             "ControllerClient couldn't find a ProfileId for gerrit user '{}'. CommentAddedController is dropping comment notification.",
             change_owner_username
@@ -38,5 +196,5 @@ pub async fn extract_user_data(
         ));
     }
 
-    Ok((profile_id.unwrap(), settings.unwrap()))
+    Ok((recipient.unwrap(), settings.unwrap()))
 }
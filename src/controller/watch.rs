@@ -0,0 +1,100 @@
+use actix_web::web;
+use chrono::Utc;
+
+use crate::{
+    actor::{
+        messages::{GetProfileIdMappings, GetWatchers, LoadSettings},
+        ResolverClient, UserServiceClient,
+    },
+    controller::error::ControllerError,
+    types::{
+        change_number, AppState, GerritInstance, GerritTrigger, GerritUsername, Synchronization,
+        TenantId,
+    },
+};
+
+use super::{
+    notification_rules::check_trigger,
+    util::{audit_error, message_composer, notify, Recipient},
+};
+
+/// Notifies the users who watch the change of a comment or vote, besides its
+/// owner. Every watcher is checked and audited on their own, so a watcher
+/// can't keep the trigger from being handled.
+pub async fn notify_watchers(
+    trigger: &GerritTrigger,
+    instance: &GerritInstance,
+    state: &web::Data<AppState>,
+) {
+    if let GerritTrigger::ReviewerAdded(_) = trigger {
+        return;
+    }
+
+    let acteur = &state.acteur;
+    let tenant = state.connection.tenant_for_instance(instance);
+    let watchers = acteur
+        .call_actor::<UserServiceClient, _>(
+            tenant.clone(),
+            GetWatchers(
+                trigger.project().to_string(),
+                change_number(trigger.change_url()).to_string(),
+            ),
+        )
+        .await
+        .unwrap_or_default();
+    let watchers: Vec<GerritUsername> = watchers
+        .into_iter()
+        .filter(|watcher| watcher != trigger.recipient())
+        .collect();
+    if watchers.is_empty() {
+        return;
+    }
+
+    // Watchers are only notified via known mappings. Unlike owners, the
+    // trigger has no name to resolve them with.
+    let mappings = acteur
+        .call_actor::<ResolverClient, _>(tenant.clone(), GetProfileIdMappings)
+        .await
+        .unwrap_or_default();
+
+    for watcher in watchers {
+        let recipient = match mappings.get(&watcher) {
+            Some(Synchronization::Some(profile_id)) => Some(Recipient::Profile(profile_id.clone())),
+            Some(Synchronization::Ambiguous(_)) => Some(Recipient::Pending(watcher.clone())),
+            _ => None,
+        };
+        let result = notify_watcher(trigger, instance, &tenant, &watcher, recipient, state).await;
+        if let Err(error) = result {
+            info!("Watcher {} isn't notified: {}", watcher, error);
+            audit_error(state, &tenant, trigger, &watcher, &error).await;
+        }
+    }
+}
+
+async fn notify_watcher(
+    trigger: &GerritTrigger,
+    instance: &GerritInstance,
+    tenant: &TenantId,
+    watcher: &GerritUsername,
+    recipient: Option<Recipient>,
+    state: &web::Data<AppState>,
+) -> Result<(), ControllerError> {
+    let recipient = recipient.ok_or_else(|| {
+        ControllerError::UserMappingError(format!("No profile id known for {}.", watcher))
+    })?;
+    let (settings, _) = state
+        .acteur
+        .call_actor::<UserServiceClient, _>(tenant.clone(), LoadSettings(watcher.clone()))
+        .await
+        .map_err(|_| ControllerError::Unspecified(String::from("Couldn't load settings.")))?;
+
+    match check_trigger(trigger, watcher, &settings, &Utc::now()) {
+        Some(result) => result?,
+        None => return Ok(()),
+    }
+
+    let message =
+        message_composer(&state.connection, tenant, instance, &settings).compose(trigger)?;
+    notify(state, tenant, trigger, watcher, recipient, message).await;
+    Ok(())
+}
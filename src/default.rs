@@ -1,4 +1,6 @@
-pub const DEFAULT_SETTINGS: &'static str = r#"V1 (
+use crate::types::Settings;
+
+pub const DEFAULT_SETTINGS: &str = r#"V2 (
     /*
      * All settings below apply to you, only when you are a reviewer of a given
      * patch.
@@ -73,5 +75,57 @@ pub const DEFAULT_SETTINGS: &'static str = r#"V1 (
          */
         //ignore_projects: [("my-project"), ("another-project")]
         ignore_projects: [],
-    )
+    ),
+
+    /*
+     * Be notified about votes on Code-Review or Verified on patches you own.
+     * A subscription to a label replaces subscribe_verified for it, and only
+     * the listed values notify. An empty list of values notifies about every
+     * vote on the label.
+     */
+    //labels: [(label: "Code-Review", values: [-2, 2])]
+    labels: [],
+
+    /*
+     * Be notified about new comments and votes on every patch in these
+     * projects, or on a single change of a project, even if you are neither
+     * owner nor reviewer.
+     */
+    //watches: [(project: ("my-project")), (project: ("tools"), change: Some("1234"))]
+    watches: [],
+
+    /*
+     * Defines when notifications may be delivered. Quiet hours may wrap around
+     * midnight. They are in UTC, unless utc_offset gives your offset to UTC
+     * in minutes, e.g. 60 for UTC+01:00 or -300 for UTC-05:00. Daylight saving
+     * time isn't applied, change the offset instead.
+     */
+    schedule: (
+        //quiet_hours: Some((from: 20, to: 8)),
+        quiet_hours: None,
+        utc_offset: 0,
+        //muted_until: Some("2020-12-24T18:00:00Z"),
+        muted_until: None,
+    ),
+
+    /*
+     * Defines how notifications are delivered to you.
+     */
+    channel: (
+        // Deliver notifications as direct chat message from the chatbot. The
+        // chatbot has no other way, false turns off all notifications.
+        direct_message: true,
+
+        // Prefer plain text, even if the chat supports richer formatting.
+        plain_text: false,
+
+        // The language of notifications: English or German.
+        language: English,
+    ),
 )"#;
+
+/// Parses `DEFAULT_SETTINGS`. Used as fallback when the settings of a user
+/// can't be loaded.
+pub fn default_settings() -> Settings {
+    ron::de::from_str(DEFAULT_SETTINGS).expect("DEFAULT_SETTINGS are invalid. Fix me.")
+}
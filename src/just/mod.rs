@@ -21,11 +21,10 @@ pub mod utils {
     }
 
     pub fn remove_email_from_owner(change_owner: &str) -> &str {
-        let start_of_email = change_owner
-            .find('<')
-            .expect("Expect to find symbol '<' in change owner.");
-
-        &change_owner[0..start_of_email].trim()
+        match change_owner.find('<') {
+            Some(start_of_email) => change_owner[0..start_of_email].trim(),
+            None => change_owner.trim(),
+        }
     }
 
     #[cfg(test)]
@@ -40,6 +39,9 @@ pub mod utils {
 
             let result = remove_email_from_owner("First Middle Last <first.last@something.com>");
             assert_eq!("First Middle Last", result);
+
+            let result = remove_email_from_owner("First Last");
+            assert_eq!("First Last", result);
         }
 
         #[test]
@@ -2,7 +2,7 @@ use chrono::prelude::*;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::types::{ConversationId, ProfileId};
+use crate::types::{ConversationId, MessageFormat, ProfileId, RichMessage};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub enum ChatType {
@@ -14,6 +14,8 @@ pub enum ChatType {
 pub enum ChatMessageType {
     #[serde(rename = "TEXT")]
     Text,
+    #[serde(rename = "MARKDOWN")]
+    Markdown,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -58,4 +60,26 @@ impl ChatMessage {
             text: String::from(text),
         }
     }
+
+    /// A message in the given format. Messages without details are sent as
+    /// text, they look the same in every format.
+    pub fn create_formatted(
+        author_id: ProfileId,
+        chat_id: ConversationId,
+        message: &RichMessage,
+        format: MessageFormat,
+    ) -> ChatMessage {
+        let format = if message.is_plain() {
+            MessageFormat::PlainText
+        } else {
+            format
+        };
+        ChatMessage {
+            r#type: match format {
+                MessageFormat::PlainText => ChatMessageType::Text,
+                MessageFormat::Markdown => ChatMessageType::Markdown,
+            },
+            ..ChatMessage::create(author_id, chat_id, &message.render(format))
+        }
+    }
 }
@@ -1,6 +1,7 @@
 // Gather all response related structs, that are used to map JSON responses to objects.
 use crate::types::ConversationId;
 use crate::types::ProfileId;
+use chrono::{DateTime, Utc};
 use serde::Deserialize;
 use std::convert::TryFrom;
 
@@ -10,7 +11,7 @@ pub struct AccesTokenResponse {
 }
 
 // Used as intermediate representation
-#[derive(Deserialize, Debug)]
+#[derive(Clone, Deserialize, Debug)]
 pub struct JustUserProfileString(String);
 
 impl JustUserProfileString {
@@ -26,7 +27,8 @@ pub struct JustUserProfile {
     pub id: JustUserProfileString,
     //image_id: String,
     //modify_date: u32,
-    //name: String,
+    #[serde(default)]
+    pub name: String,
     //state: String,
 }
 
@@ -39,3 +41,29 @@ pub struct UserSearchResult {
 pub struct ChatCreationResult {
     pub id: ConversationId,
 }
+
+#[derive(Deserialize)]
+pub struct ChatSummary {
+    pub id: ConversationId,
+}
+
+#[derive(Deserialize)]
+pub struct ChatListResult {
+    pub items: Vec<ChatSummary>,
+}
+
+/// A chat message as returned when listing the messages of a chat.
+#[derive(Clone, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ReceivedChatMessage {
+    pub chat_id: ConversationId,
+    pub author_id: JustUserProfileString,
+    pub create_date: DateTime<Utc>,
+    #[serde(default)]
+    pub text: String,
+}
+
+#[derive(Deserialize)]
+pub struct ChatMessageListResult {
+    pub items: Vec<ReceivedChatMessage>,
+}
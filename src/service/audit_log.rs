@@ -0,0 +1,419 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::types::{
+    change_number, GerritInstance, GerritTrigger, GerritUsername, PathToUserData, ProfileId,
+};
+
+/// The log is rotated once it is larger, in bytes.
+const MAX_SIZE: u64 = 10 * 1024 * 1024;
+
+/// How many rotated logs are kept. Older ones are removed.
+const KEEP_ROTATED: usize = 5;
+
+/// What happened to the notification for a trigger.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum AuditOutcome {
+    /// The chatbot sent the notification.
+    Delivered { profile_id: ProfileId },
+    /// The chatbot couldn't send the notification.
+    DeliveryFailed {
+        profile_id: ProfileId,
+        error: String,
+    },
+    /// The mapping of the recipient is ambiguous, the notification is kept
+    /// until an admin picks a profile.
+    Queued,
+    /// The settings of the recipient ruled the notification out.
+    Suppressed { rule: String, reason: String },
+    /// The notification couldn't be composed, e.g. because the recipient has
+    /// no profile.
+    Failed { error: String },
+}
+
+/// A trigger, who it was for and what happened.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub time: DateTime<Utc>,
+    // The type of the trigger, e.g. comment_added
+    pub trigger: String,
+    // The Gerrit instance, missing for the default instance
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instance: Option<String>,
+    pub project: String,
+    pub change: String,
+    pub author: GerritUsername,
+    pub recipient: GerritUsername,
+    #[serde(flatten)]
+    pub outcome: AuditOutcome,
+}
+
+impl AuditEntry {
+    pub fn new(trigger: &GerritTrigger, outcome: AuditOutcome) -> AuditEntry {
+        AuditEntry {
+            time: Utc::now(),
+            trigger: trigger.kind().to_string(),
+            instance: trigger.instance().map(str::to_string),
+            project: trigger.project().to_string(),
+            change: change_number(trigger.change_url()).to_string(),
+            author: trigger.author().clone(),
+            recipient: trigger.recipient().clone(),
+            outcome,
+        }
+    }
+
+    pub fn with_outcome(self, outcome: AuditOutcome) -> AuditEntry {
+        AuditEntry { outcome, ..self }
+    }
+
+    /// The entry for someone else than the recipient of the trigger, e.g. a
+    /// watcher of the change.
+    pub fn with_recipient(self, recipient: GerritUsername) -> AuditEntry {
+        AuditEntry { recipient, ..self }
+    }
+
+    /// Whether the entry is about a trigger of the instance. Triggers of the
+    /// default instance may name no instance.
+    pub fn is_of(&self, instance: &GerritInstance) -> bool {
+        match &self.instance {
+            Some(name) => name == &instance.name,
+            None => instance.namespace.is_none(),
+        }
+    }
+
+    /// A symbol for the outcome, to go with `describe_outcome` in chat
+    /// messages.
+    pub fn outcome_symbol(&self) -> &'static str {
+        match &self.outcome {
+            AuditOutcome::Delivered { .. } => "✅",
+            AuditOutcome::DeliveryFailed { .. } | AuditOutcome::Failed { .. } => "⚠️",
+            AuditOutcome::Queued => "⏳",
+            AuditOutcome::Suppressed { .. } => "❌",
+        }
+    }
+
+    /// The outcome in words, e.g. for `chtbtr-admin audit`.
+    pub fn describe_outcome(&self) -> String {
+        match &self.outcome {
+            AuditOutcome::Delivered { profile_id } => format!("delivered to {}", profile_id),
+            AuditOutcome::DeliveryFailed { profile_id, error } => {
+                format!("delivery to {} failed: {}", profile_id, error)
+            }
+            AuditOutcome::Queued => String::from("queued until the mapping is fixed"),
+            AuditOutcome::Suppressed { reason, .. } => format!("suppressed: {}", reason),
+            AuditOutcome::Failed { error } => format!("failed: {}", error),
+        }
+    }
+}
+
+/// Which entries `AuditLog::query` finds. Unset fields match every entry.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AuditQuery {
+    // The recipient of the notifications
+    pub user: Option<GerritUsername>,
+    // The Gerrit instance, change numbers are only unique per instance
+    pub instance: Option<GerritInstance>,
+    pub change: Option<String>,
+    // How many of the newest entries are found, all if not set
+    pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+    pub fn matches(&self, entry: &AuditEntry) -> bool {
+        let user_matches = match &self.user {
+            Some(user) => &entry.recipient == user,
+            None => true,
+        };
+        let instance_matches = match &self.instance {
+            Some(instance) => entry.is_of(instance),
+            None => true,
+        };
+        let change_matches = match &self.change {
+            Some(change) => &entry.change == change,
+            None => true,
+        };
+        user_matches && instance_matches && change_matches
+    }
+}
+
+/// An append-only log of `AuditEntry`s as JSON lines in the data directory.
+/// The log is rotated once it exceeds `MAX_SIZE`, the last `KEEP_ROTATED`
+/// logs are kept.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AuditLog {
+    path: PathBuf,
+    max_size: u64,
+}
+
+impl AuditLog {
+    pub fn new(data_dir: &str) -> AuditLog {
+        AuditLog {
+            path: PathToUserData::audit_log(data_dir).as_path().to_path_buf(),
+            max_size: MAX_SIZE,
+        }
+    }
+
+    pub fn append(&self, entry: &AuditEntry) -> Result<(), String> {
+        let size = fs::metadata(&self.path).map(|m| m.len()).unwrap_or(0);
+        if size > self.max_size {
+            self.rotate()?;
+        }
+
+        let mut line = serde_json::to_string(entry).map_err(|e| e.to_string())?;
+        line.push('\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .map_err(|e| {
+                format!(
+                    "Couldn't write audit log {}. Cause: {}",
+                    self.path.display(),
+                    e
+                )
+            })
+    }
+
+    /// Moves `audit.jsonl` to `audit.jsonl.1`, `audit.jsonl.1` to
+    /// `audit.jsonl.2` and so on. The oldest log is removed.
+    fn rotate(&self) -> Result<(), String> {
+        for index in (1..=KEEP_ROTATED).rev() {
+            let from = match index {
+                1 => self.path.clone(),
+                _ => self.rotated(index - 1),
+            };
+            match fs::rename(&from, self.rotated(index)) {
+                Err(e) if e.kind() != ErrorKind::NotFound => {
+                    return Err(format!(
+                        "Couldn't rotate audit log {}. Cause: {}",
+                        from.display(),
+                        e
+                    ))
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn rotated(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        PathBuf::from(path)
+    }
+
+    /// The log and the rotated logs, newest first.
+    fn logs(&self) -> impl Iterator<Item = PathBuf> + '_ {
+        std::iter::once(self.path.clone()).chain((1..=KEEP_ROTATED).map(move |i| self.rotated(i)))
+    }
+
+    /// All entries, oldest first. Invalid lines are skipped.
+    pub fn entries(&self) -> Result<Vec<AuditEntry>, String> {
+        self.query(&AuditQuery::default())
+    }
+
+    /// The newest entries the query matches, oldest first. The logs are read
+    /// newest first, older logs aren't read once the limit is reached.
+    pub fn query(&self, query: &AuditQuery) -> Result<Vec<AuditEntry>, String> {
+        let mut found = VecDeque::new();
+        for path in self.logs() {
+            let wanted = match query.limit {
+                Some(limit) if found.len() >= limit => break,
+                Some(limit) => limit - found.len(),
+                None => usize::MAX,
+            };
+            let mut older = VecDeque::new();
+            read_entries(&path, |entry| {
+                if query.matches(&entry) {
+                    if older.len() == wanted {
+                        older.pop_front();
+                    }
+                    older.push_back(entry);
+                }
+            })?;
+            older.append(&mut found);
+            found = older;
+        }
+        Ok(found.into())
+    }
+}
+
+/// Passes the entries of a log to `found` line by line, so the log isn't read
+/// into memory at once.
+fn read_entries(path: &Path, mut found: impl FnMut(AuditEntry)) -> Result<(), String> {
+    let error =
+        |e: std::io::Error| format!("Couldn't read audit log {}. Cause: {}", path.display(), e);
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(error(e)),
+    };
+
+    for line in BufReader::new(file).lines() {
+        let line = line.map_err(error)?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(entry) => found(entry),
+            Err(e) => warn!("Skipping invalid line in {}: {}", path.display(), e),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AuditEntry, AuditLog, AuditOutcome, AuditQuery, KEEP_ROTATED};
+    use crate::types::{GerritInstance, GerritUsername, ProfileId};
+    use chrono::Utc;
+
+    fn entry(recipient: &str, change: &str, outcome: AuditOutcome) -> AuditEntry {
+        AuditEntry {
+            time: Utc::now(),
+            trigger: String::from("comment_added"),
+            instance: None,
+            project: String::from("my_project"),
+            change: String::from(change),
+            author: GerritUsername::from("author"),
+            recipient: GerritUsername::from(recipient),
+            outcome,
+        }
+    }
+
+    fn temp_dir() -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("chtbtr-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn appends_and_queries_entries() {
+        let dir = temp_dir();
+        let log = AuditLog::new(dir.to_str().unwrap());
+        let suppressed = AuditOutcome::Suppressed {
+            rule: String::from("AuthorAndOwnerAreTheSame"),
+            reason: String::from("Author and owner are the same."),
+        };
+        log.append(&entry("jdoe", "1234", suppressed.clone()))
+            .unwrap();
+        log.append(&entry(
+            "jdoe",
+            "1235",
+            AuditOutcome::Delivered {
+                profile_id: ProfileId(1),
+            },
+        ))
+        .unwrap();
+        log.append(&entry("other", "1234", AuditOutcome::Queued))
+            .unwrap();
+
+        let change = |change: &str| AuditQuery {
+            change: Some(String::from(change)),
+            ..AuditQuery::default()
+        };
+        let found = log
+            .query(&AuditQuery {
+                user: Some(GerritUsername::from("jdoe")),
+                ..change("1234")
+            })
+            .unwrap();
+
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].outcome, suppressed);
+        assert_eq!(log.query(&change("1234")).unwrap().len(), 2);
+        assert_eq!(log.entries().unwrap().len(), 3);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn queries_changes_of_an_instance() {
+        let dir = temp_dir();
+        let log = AuditLog::new(dir.to_str().unwrap());
+        let mut legacy = entry("jdoe", "1234", AuditOutcome::Queued);
+        legacy.instance = Some(String::from("legacy"));
+        log.append(&legacy).unwrap();
+        log.append(&entry("jdoe", "1234", AuditOutcome::Queued))
+            .unwrap();
+
+        let instances =
+            GerritInstance::parse_list("main=https://gerrit.example.com, legacy=http://old")
+                .unwrap();
+        let query = |instance: &GerritInstance| AuditQuery {
+            instance: Some(instance.clone()),
+            change: Some(String::from("1234")),
+            ..AuditQuery::default()
+        };
+
+        assert_eq!(log.query(&query(&instances[0])).unwrap().len(), 1);
+        assert_eq!(log.query(&query(&instances[1])).unwrap(), vec![legacy]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn queries_the_newest_entries_first() {
+        let dir = temp_dir();
+        let log = AuditLog {
+            max_size: 1,
+            ..AuditLog::new(dir.to_str().unwrap())
+        };
+        for change in 0..4 {
+            log.append(&entry("jdoe", &change.to_string(), AuditOutcome::Queued))
+                .unwrap();
+        }
+        // The oldest log can't be read, but the newest entries are found
+        // without it.
+        std::fs::write(log.rotated(3), [0xff, 0xfe]).unwrap();
+
+        let changes: Vec<String> = log
+            .query(&AuditQuery {
+                limit: Some(2),
+                ..AuditQuery::default()
+            })
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.change)
+            .collect();
+
+        assert_eq!(changes, vec!["2", "3"]);
+        assert!(log.entries().is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rotates_logs() {
+        let dir = temp_dir();
+        let log = AuditLog {
+            max_size: 1,
+            ..AuditLog::new(dir.to_str().unwrap())
+        };
+        for change in 0..(KEEP_ROTATED + 3) {
+            log.append(&entry("jdoe", &change.to_string(), AuditOutcome::Queued))
+                .unwrap();
+        }
+
+        let changes: Vec<String> = log
+            .entries()
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.change)
+            .collect();
+
+        assert_eq!(changes.len(), KEEP_ROTATED + 1);
+        assert_eq!(changes.last().unwrap(), &(KEEP_ROTATED + 2).to_string());
+        assert_eq!(changes[0], "2");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
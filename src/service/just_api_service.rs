@@ -4,15 +4,22 @@ use std::collections::HashMap;
 use crate::{
     just::requests::{Chat, ChatMessage},
     just::responses::{AccesTokenResponse, ChatCreationResult, JustUserProfile, UserSearchResult},
-    just::utils::{user_firstname, user_lastname},
-    types::{ConnectionParameters, ConversationId, ProfileId},
+    types::{ConnectionParameters, ConversationId, GerritAccount, ProfileId},
 };
 
-#[derive(Deserialize)]
+#[derive(Debug, Deserialize)]
 pub struct JustError {
     pub message: String,
 }
 
+impl From<reqwest::Error> for JustError {
+    fn from(error: reqwest::Error) -> Self {
+        JustError {
+            message: format!("Request to Just failed. Cause: {}", error),
+        }
+    }
+}
+
 #[async_trait::async_trait]
 pub trait JustApiService {
     async fn send_message<'a, 'b>(
@@ -171,13 +178,9 @@ impl JustApiService for JustApiServiceImpl {
     // The result/error message should be valid until the change_owner that called the function
     // is no longer valid.
     async fn search_user(&self, change_owner: &str) -> Result<Option<ProfileId>, String> {
-        let search = [
-            change_owner,
-            user_firstname(change_owner),
-            user_lastname(change_owner),
-        ];
-        for s in search.iter() {
-            let request_result = self.request_users(s).await;
+        let account = GerritAccount::from(change_owner);
+        for (_, s) in account.search_terms() {
+            let request_result = self.request_users(&s).await;
             if request_result.is_err() {
                 error!("API request failed.");
                 return Err(String::from("Error when searching for user."));
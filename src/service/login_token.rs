@@ -0,0 +1,133 @@
+use chrono::{DateTime, TimeZone, Utc};
+use hmac_sha256::HMAC;
+
+use crate::types::GerritUsername;
+
+/// Grants access to the settings of a user via the web UI until it expires.
+///
+/// The token is signed with a secret only the server knows, so it can be sent
+/// as part of a link in a chat message. A token looks like
+/// `{hex username}.{expiry timestamp}.{hex signature}`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoginToken {
+    pub username: GerritUsername,
+    pub expires: DateTime<Utc>,
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub(crate) fn from_hex(value: &str) -> Option<Vec<u8>> {
+    value
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| match pair {
+            [_, _] => u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok(),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Compares both values in constant time, so secrets like signatures or
+/// tokens can't be guessed byte by byte.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+impl LoginToken {
+    fn payload(&self) -> String {
+        format!(
+            "{}.{}",
+            to_hex(self.username.0.as_bytes()),
+            self.expires.timestamp()
+        )
+    }
+
+    pub fn sign(&self, secret: &str) -> String {
+        let payload = self.payload();
+        let signature = HMAC::mac(payload.as_bytes(), secret.as_bytes());
+        format!("{}.{}", payload, to_hex(&signature))
+    }
+
+    /// Returns the token if the signature is valid and it isn't expired yet.
+    pub fn verify(token: &str, secret: &str, now: &DateTime<Utc>) -> Result<LoginToken, String> {
+        let invalid = || String::from("The link is invalid.");
+        let parts: Vec<&str> = token.split('.').collect();
+        if parts.len() != 3 {
+            return Err(invalid());
+        }
+
+        let payload = format!("{}.{}", parts[0], parts[1]);
+        let expected = HMAC::mac(payload.as_bytes(), secret.as_bytes());
+        let signature = from_hex(parts[2]).ok_or_else(invalid)?;
+        if !constant_time_eq(&expected, &signature) {
+            return Err(invalid());
+        }
+
+        let username = from_hex(parts[0])
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .ok_or_else(invalid)?;
+        let expires: i64 = parts[1].parse().map_err(|_| invalid())?;
+        let token = LoginToken {
+            username: GerritUsername(username),
+            expires: Utc.timestamp(expires, 0),
+        };
+
+        if &token.expires < now {
+            return Err(String::from(
+                "The link is expired. Send me 'link' to get a new one.",
+            ));
+        }
+
+        Ok(token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LoginToken;
+    use crate::types::GerritUsername;
+    use chrono::{Duration, TimeZone, Utc};
+
+    fn create_token() -> LoginToken {
+        LoginToken {
+            username: GerritUsername::from("fz.user"),
+            expires: Utc.ymd(2020, 7, 1).and_hms(12, 0, 0),
+        }
+    }
+
+    #[test]
+    fn verifies_signed_token() {
+        let token = create_token();
+        let signed = token.sign("secret");
+        let now = token.expires - Duration::minutes(5);
+
+        assert_eq!(LoginToken::verify(&signed, "secret", &now), Ok(token));
+    }
+
+    #[test]
+    fn rejects_other_secret_and_tampering() {
+        let token = create_token();
+        let signed = token.sign("secret");
+        let now = token.expires - Duration::minutes(5);
+        let tampered = LoginToken {
+            username: GerritUsername::from("someone.else"),
+            ..token.clone()
+        }
+        .sign("other secret");
+
+        assert!(LoginToken::verify(&signed, "other secret", &now).is_err());
+        assert!(LoginToken::verify(&tampered, "secret", &now).is_err());
+        assert!(LoginToken::verify("garbage", "secret", &now).is_err());
+    }
+
+    #[test]
+    fn rejects_expired_token() {
+        let token = create_token();
+        let signed = token.sign("secret");
+        let now = token.expires + Duration::seconds(1);
+
+        assert!(LoginToken::verify(&signed, "secret", &now).is_err());
+    }
+}
@@ -0,0 +1,296 @@
+use std::collections::HashMap;
+use std::fs;
+
+use crate::types::{Language, PathToUserData};
+
+/// The events a notification is sent for. Every event has a template per
+/// language.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MessageEvent {
+    CommentAdded,
+    Verified,
+    ReadyForSubmit,
+    ReviewerAdded,
+}
+
+/// Variables of every event, describing the change.
+const BASE_VARIABLES: &[&str] = &[
+    "project",
+    "change",
+    "change_url",
+    "patch_set",
+    "change_owner",
+    "change_owner_username",
+    "instance",
+];
+
+impl MessageEvent {
+    pub const ALL: [MessageEvent; 4] = [
+        MessageEvent::CommentAdded,
+        MessageEvent::Verified,
+        MessageEvent::ReadyForSubmit,
+        MessageEvent::ReviewerAdded,
+    ];
+
+    /// The key of the event in the templates file, e.g. `comment_added`.
+    pub fn key(&self) -> &'static str {
+        match self {
+            MessageEvent::CommentAdded => "comment_added",
+            MessageEvent::Verified => "verified",
+            MessageEvent::ReadyForSubmit => "ready_for_submit",
+            MessageEvent::ReviewerAdded => "reviewer_added",
+        }
+    }
+
+    /// The variables a template of the event may use, besides
+    /// `BASE_VARIABLES`.
+    fn event_variables(&self) -> &'static [&'static str] {
+        match self {
+            MessageEvent::CommentAdded => &["author", "author_username", "file", "line"],
+            MessageEvent::Verified => &["verified", "emoji", "author_username"],
+            MessageEvent::ReadyForSubmit => &["author_username"],
+            MessageEvent::ReviewerAdded => &["reviewer", "reviewer_username"],
+        }
+    }
+
+    /// Every variable a template of the event may use.
+    pub fn variables(&self) -> Vec<&'static str> {
+        BASE_VARIABLES
+            .iter()
+            .chain(self.event_variables())
+            .copied()
+            .collect()
+    }
+
+    fn built_in(&self, language: Language) -> &'static str {
+        match (language, self) {
+            (Language::English, MessageEvent::CommentAdded) => {
+                "Comment was added by {author_username}. 💬 {change_url}"
+            }
+            (Language::English, MessageEvent::Verified) => {
+                "{verified} Verified for your patch {emoji} {change_url}."
+            }
+            (Language::English, MessageEvent::ReadyForSubmit) => {
+                "☑️ A patch is ready to submit! ✨ {change_url}"
+            }
+            (Language::English, MessageEvent::ReviewerAdded) => {
+                "You were added as reviewer. {change_url}"
+            }
+            (Language::German, MessageEvent::CommentAdded) => {
+                "{author_username} hat einen Kommentar geschrieben. 💬 {change_url}"
+            }
+            (Language::German, MessageEvent::Verified) => {
+                "{verified} Verified für deinen Patch {emoji} {change_url}."
+            }
+            (Language::German, MessageEvent::ReadyForSubmit) => {
+                "☑️ Ein Patch kann submitted werden! ✨ {change_url}"
+            }
+            (Language::German, MessageEvent::ReviewerAdded) => {
+                "Du wurdest als Reviewer hinzugefügt. {change_url}"
+            }
+        }
+    }
+}
+
+/// Replaces the variables in a template, e.g. `{project}`, with their values.
+/// `{{` and `}}` are written as `{` and `}`. Fails for variables without a
+/// value and for unclosed braces.
+pub fn render(template: &str, variables: &[(&str, String)]) -> Result<String, String> {
+    let mut result = String::with_capacity(template.len());
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                result.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                result.push('}');
+            }
+            '{' => {
+                let mut name = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => name.push(c),
+                        None => return Err(format!("Unclosed variable '{{{}'.", name)),
+                    }
+                }
+                let value = variables
+                    .iter()
+                    .find(|(variable, _)| *variable == name.trim())
+                    .map(|(_, value)| value)
+                    .ok_or_else(|| format!("Unknown variable '{{{}}}'.", name))?;
+                result.push_str(value);
+            }
+            '}' => return Err(String::from("Unexpected '}', write '}}' instead.")),
+            c => result.push(c),
+        }
+    }
+    Ok(result)
+}
+
+/// The texts of notifications. Built-in templates exist for every language and
+/// event, the templates file in the data directory may replace them, e.g.
+///
+/// ```text
+/// {
+///     "de": {
+///         "comment_added": "Neuer Kommentar von {author} 💬 {change_url}",
+///     },
+/// }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct MessageTemplates {
+    // Templates of the templates file, by language and event
+    custom: HashMap<(Language, MessageEvent), String>,
+}
+
+impl MessageTemplates {
+    /// Parses the content of a templates file. Fails for unknown languages,
+    /// events and variables.
+    pub fn parse(content: &str) -> Result<MessageTemplates, String> {
+        let file: HashMap<String, HashMap<String, String>> =
+            ron::de::from_str(content).map_err(|e| e.to_string())?;
+
+        let mut custom = HashMap::new();
+        for (code, templates) in file {
+            let language: Language = code.parse()?;
+            for (key, template) in templates {
+                let event = MessageEvent::ALL
+                    .iter()
+                    .find(|event| event.key() == key)
+                    .copied()
+                    .ok_or_else(|| format!("Unknown event '{}'.", key))?;
+                let placeholders: Vec<(&str, String)> = event
+                    .variables()
+                    .into_iter()
+                    .map(|variable| (variable, String::new()))
+                    .collect();
+                render(&template, &placeholders)
+                    .map_err(|e| format!("Invalid template {}.{}: {}", code, key, e))?;
+                custom.insert((language, event), template);
+            }
+        }
+        Ok(MessageTemplates { custom })
+    }
+
+    /// Loads the templates file of a data directory. Without a valid file, the
+    /// built-in templates are used.
+    pub fn load(data_dir: &str) -> MessageTemplates {
+        let path = PathToUserData::templates(data_dir);
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return MessageTemplates::default()
+            }
+            Err(e) => {
+                warn!(
+                    "Couldn't read {}. Using built-in templates. Cause: {}",
+                    path.as_path().display(),
+                    e
+                );
+                return MessageTemplates::default();
+            }
+        };
+
+        MessageTemplates::parse(&content).unwrap_or_else(|e| {
+            warn!(
+                "Invalid templates in {}. Using built-in templates. Cause: {}",
+                path.as_path().display(),
+                e
+            );
+            MessageTemplates::default()
+        })
+    }
+
+    pub fn template(&self, language: Language, event: MessageEvent) -> &str {
+        self.custom
+            .get(&(language, event))
+            .map(String::as_str)
+            .unwrap_or_else(|| event.built_in(language))
+    }
+
+    pub fn render(
+        &self,
+        language: Language,
+        event: MessageEvent,
+        variables: &[(&str, String)],
+    ) -> Result<String, String> {
+        render(self.template(language, event), variables)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{render, MessageEvent, MessageTemplates};
+    use crate::types::Language;
+
+    fn variables(event: MessageEvent) -> Vec<(&'static str, String)> {
+        event
+            .variables()
+            .into_iter()
+            .map(|variable| (variable, format!("<{}>", variable)))
+            .collect()
+    }
+
+    #[test]
+    fn renders_every_built_in_template() {
+        let templates = MessageTemplates::default();
+        for language in Language::ALL.iter() {
+            for event in MessageEvent::ALL.iter() {
+                let message = templates
+                    .render(*language, *event, &variables(*event))
+                    .unwrap_or_else(|e| panic!("{} {:?}: {}", language, event, e));
+                assert!(message.contains("<change_url>"), "{}", message);
+                assert!(!message.contains('{'), "{}", message);
+            }
+        }
+    }
+
+    #[test]
+    fn replaces_variables() {
+        let values = vec![("name", String::from("chtbtr"))];
+        assert_eq!(
+            render("Hi {name}, {{literal}}", &values),
+            Ok(String::from("Hi chtbtr, {literal}"))
+        );
+        assert!(render("Hi {unknown}", &values).is_err());
+        assert!(render("Hi {name", &values).is_err());
+        assert!(render("Hi name}", &values).is_err());
+    }
+
+    #[test]
+    fn templates_file_replaces_built_in_templates() {
+        let templates = MessageTemplates::parse(
+            r#"{"de": {"reviewer_added": "Review von {reviewer_username}: {change_url}"}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            templates.render(
+                Language::German,
+                MessageEvent::ReviewerAdded,
+                &variables(MessageEvent::ReviewerAdded)
+            ),
+            Ok(String::from("Review von <reviewer_username>: <change_url>"))
+        );
+        assert_eq!(
+            templates.template(Language::English, MessageEvent::ReviewerAdded),
+            "You were added as reviewer. {change_url}"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_templates_files() {
+        for content in &[
+            r#"{"fr": {"verified": "{change_url}"}}"#,
+            r#"{"en": {"merged": "{change_url}"}}"#,
+            r#"{"en": {"verified": "{reviewer}"}}"#,
+            r#"{"en": "#,
+        ] {
+            assert!(MessageTemplates::parse(content).is_err(), "{}", content);
+        }
+    }
+}
@@ -0,0 +1,341 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+/// Upper bounds of the buckets of the Just API latency histograms, in seconds.
+const LATENCY_BUCKETS: [f64; 9] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// Something that happened, counted by `Metrics`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Metric {
+    /// A trigger of the given type, e.g. `comment_added`, was received.
+    TriggerReceived(&'static str),
+    /// The chat backend accepted a notification.
+    NotificationSent,
+    /// The chat backend didn't accept a notification.
+    NotificationFailed,
+    /// A notification is kept until the mapping of the recipient is fixed.
+    NotificationQueued,
+    /// A notification wasn't sent due to the settings, given the name of the
+    /// `NotificationRuleViolation`.
+    NotificationSuppressed(&'static str),
+    ResolverCacheHit,
+    ResolverCacheMiss,
+    /// A request to the Just API, e.g. `send_chat_message`, how long it took
+    /// and whether it failed.
+    JustRequest {
+        operation: &'static str,
+        duration: Duration,
+        failed: bool,
+    },
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Histogram {
+    // Observations per bucket, not cumulative
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        if let Some(index) = LATENCY_BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[index] += 1;
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+/// Counts what the server does since it started. Rendered in the Prometheus
+/// text format for `/metrics`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Metrics {
+    triggers: BTreeMap<&'static str, u64>,
+    notifications_sent: u64,
+    notifications_failed: u64,
+    notifications_queued: u64,
+    suppressed: BTreeMap<&'static str, u64>,
+    cache_hits: u64,
+    cache_misses: u64,
+    just_latency: BTreeMap<&'static str, Histogram>,
+    just_errors: BTreeMap<&'static str, u64>,
+}
+
+impl Metrics {
+    pub fn record(&mut self, metric: Metric) {
+        match metric {
+            Metric::TriggerReceived(kind) => *self.triggers.entry(kind).or_default() += 1,
+            Metric::NotificationSent => self.notifications_sent += 1,
+            Metric::NotificationFailed => self.notifications_failed += 1,
+            Metric::NotificationQueued => self.notifications_queued += 1,
+            Metric::NotificationSuppressed(reason) => {
+                *self.suppressed.entry(reason).or_default() += 1
+            }
+            Metric::ResolverCacheHit => self.cache_hits += 1,
+            Metric::ResolverCacheMiss => self.cache_misses += 1,
+            Metric::JustRequest {
+                operation,
+                duration,
+                failed,
+            } => {
+                self.just_latency
+                    .entry(operation)
+                    .or_default()
+                    .observe(duration.as_secs_f64());
+                let errors = self.just_errors.entry(operation).or_default();
+                if failed {
+                    *errors += 1;
+                }
+            }
+        }
+    }
+
+    /// The metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "chtbtr_triggers_received_total",
+            "counter",
+            "Triggers received from Gerrit, by type.",
+        );
+        for (kind, count) in &self.triggers {
+            sample(
+                &mut out,
+                "chtbtr_triggers_received_total",
+                &[("type", kind)],
+                count,
+            );
+        }
+
+        header(
+            &mut out,
+            "chtbtr_notifications_sent_total",
+            "counter",
+            "Notifications sent to a chat.",
+        );
+        sample(
+            &mut out,
+            "chtbtr_notifications_sent_total",
+            &[],
+            self.notifications_sent,
+        );
+
+        header(
+            &mut out,
+            "chtbtr_notifications_failed_total",
+            "counter",
+            "Notifications the chat didn't accept.",
+        );
+        sample(
+            &mut out,
+            "chtbtr_notifications_failed_total",
+            &[],
+            self.notifications_failed,
+        );
+
+        header(
+            &mut out,
+            "chtbtr_notifications_queued_total",
+            "counter",
+            "Notifications kept for users with an ambiguous mapping.",
+        );
+        sample(
+            &mut out,
+            "chtbtr_notifications_queued_total",
+            &[],
+            self.notifications_queued,
+        );
+
+        header(
+            &mut out,
+            "chtbtr_notifications_suppressed_total",
+            "counter",
+            "Notifications not sent due to settings, by rule.",
+        );
+        for (reason, count) in &self.suppressed {
+            sample(
+                &mut out,
+                "chtbtr_notifications_suppressed_total",
+                &[("reason", reason)],
+                count,
+            );
+        }
+
+        header(
+            &mut out,
+            "chtbtr_resolver_cache_hits_total",
+            "counter",
+            "Users resolved from the cache.",
+        );
+        sample(
+            &mut out,
+            "chtbtr_resolver_cache_hits_total",
+            &[],
+            self.cache_hits,
+        );
+        header(
+            &mut out,
+            "chtbtr_resolver_cache_misses_total",
+            "counter",
+            "Users resolved using the Just API.",
+        );
+        sample(
+            &mut out,
+            "chtbtr_resolver_cache_misses_total",
+            &[],
+            self.cache_misses,
+        );
+
+        let name = "chtbtr_just_api_request_duration_seconds";
+        header(
+            &mut out,
+            name,
+            "histogram",
+            "Duration of requests to the Just API, by operation.",
+        );
+        for (operation, histogram) in &self.just_latency {
+            let mut cumulative = 0;
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets.iter()) {
+                cumulative += count;
+                let bound = bound.to_string();
+                sample(
+                    &mut out,
+                    &format!("{}_bucket", name),
+                    &[("operation", operation), ("le", &bound)],
+                    cumulative,
+                );
+            }
+            sample(
+                &mut out,
+                &format!("{}_bucket", name),
+                &[("operation", operation), ("le", "+Inf")],
+                histogram.count,
+            );
+            sample(
+                &mut out,
+                &format!("{}_sum", name),
+                &[("operation", operation)],
+                histogram.sum,
+            );
+            sample(
+                &mut out,
+                &format!("{}_count", name),
+                &[("operation", operation)],
+                histogram.count,
+            );
+        }
+
+        header(
+            &mut out,
+            "chtbtr_just_api_errors_total",
+            "counter",
+            "Failed requests to the Just API, by operation.",
+        );
+        for (operation, count) in &self.just_errors {
+            sample(
+                &mut out,
+                "chtbtr_just_api_errors_total",
+                &[("operation", operation)],
+                count,
+            );
+        }
+
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn sample<V: std::fmt::Display>(out: &mut String, name: &str, labels: &[(&str, &str)], value: V) {
+    let labels: Vec<String> = labels
+        .iter()
+        .map(|(label, value)| format!("{}=\"{}\"", label, escape_label(value)))
+        .collect();
+    if labels.is_empty() {
+        let _ = writeln!(out, "{} {}", name, value);
+    } else {
+        let _ = writeln!(out, "{}{{{}}} {}", name, labels.join(","), value);
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Metric, Metrics};
+    use std::time::Duration;
+
+    #[test]
+    fn counts_by_label() {
+        let mut metrics = Metrics::default();
+        metrics.record(Metric::TriggerReceived("comment_added"));
+        metrics.record(Metric::TriggerReceived("comment_added"));
+        metrics.record(Metric::TriggerReceived("reviewer_added"));
+        metrics.record(Metric::NotificationSuppressed("AuthorAndOwnerAreTheSame"));
+        metrics.record(Metric::NotificationSent);
+        metrics.record(Metric::ResolverCacheMiss);
+
+        let text = metrics.render();
+
+        assert!(text.contains("chtbtr_triggers_received_total{type=\"comment_added\"} 2\n"));
+        assert!(text.contains("chtbtr_triggers_received_total{type=\"reviewer_added\"} 1\n"));
+        assert!(text.contains(
+            "chtbtr_notifications_suppressed_total{reason=\"AuthorAndOwnerAreTheSame\"} 1\n"
+        ));
+        assert!(text.contains("chtbtr_notifications_sent_total 1\n"));
+        assert!(text.contains("chtbtr_notifications_failed_total 0\n"));
+        assert!(text.contains("chtbtr_notifications_queued_total 0\n"));
+        assert!(text.contains("chtbtr_resolver_cache_hits_total 0\n"));
+        assert!(text.contains("chtbtr_resolver_cache_misses_total 1\n"));
+        assert!(text.contains("# TYPE chtbtr_triggers_received_total counter\n"));
+    }
+
+    #[test]
+    fn renders_cumulative_histogram() {
+        let mut metrics = Metrics::default();
+        for (millis, failed) in &[(30, false), (200, false), (60_000, true)] {
+            metrics.record(Metric::JustRequest {
+                operation: "send_chat_message",
+                duration: Duration::from_millis(*millis),
+                failed: *failed,
+            });
+        }
+
+        let text = metrics.render();
+        let name = "chtbtr_just_api_request_duration_seconds";
+
+        assert!(text.contains(&format!(
+            "{}_bucket{{operation=\"send_chat_message\",le=\"0.05\"}} 1\n",
+            name
+        )));
+        assert!(text.contains(&format!(
+            "{}_bucket{{operation=\"send_chat_message\",le=\"0.25\"}} 2\n",
+            name
+        )));
+        assert!(text.contains(&format!(
+            "{}_bucket{{operation=\"send_chat_message\",le=\"30\"}} 2\n",
+            name
+        )));
+        assert!(text.contains(&format!(
+            "{}_bucket{{operation=\"send_chat_message\",le=\"+Inf\"}} 3\n",
+            name
+        )));
+        assert!(text.contains(&format!(
+            "{}_count{{operation=\"send_chat_message\"}} 3\n",
+            name
+        )));
+        assert!(text.contains("chtbtr_just_api_errors_total{operation=\"send_chat_message\"} 1\n"));
+    }
+}
@@ -1,11 +1,29 @@
+mod audit_log;
 mod just_api_service;
+mod login_token;
+mod message_templates;
+mod metrics;
 mod notification_message_composer;
+mod pending_notifications;
 mod resolver_service;
+mod sqlite_user_service;
+mod trigger_signature;
+mod trigger_spool;
+mod user_directory;
 mod user_service;
 
 pub use self::{
+    audit_log::{AuditEntry, AuditLog, AuditOutcome, AuditQuery},
     just_api_service::{JustApiService, JustApiServiceImpl, JustError},
+    login_token::{constant_time_eq, LoginToken},
+    message_templates::{MessageEvent, MessageTemplates},
+    metrics::{Metric, Metrics},
     notification_message_composer::NotificationMessageComposer,
-    resolver_service::{ProfileIdResolver, ResolverService},
-    user_service::{FileBackedUserService, UserService},
+    pending_notifications::{PendingNotification, PendingNotifications},
+    resolver_service::{DirectoryResolver, ProfileIdResolver, ResolverService},
+    sqlite_user_service::SqliteUserService,
+    trigger_signature::{TriggerSignature, SIGNATURE_HEADER, TIMESTAMP_HEADER},
+    trigger_spool::{TriggerSpool, TRIGGER_ID_HEADER},
+    user_directory::{DirectoryEntry, UserDirectory},
+    user_service::{FileBackedUserService, SettingsError, UserService},
 };
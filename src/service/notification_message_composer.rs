@@ -1,21 +1,168 @@
-use crate::types::{BaseData, CommentAddedData, GerritTrigger, PatchStatus, VerifiedStatus};
+use super::message_templates::{MessageEvent, MessageTemplates};
+use crate::types::{
+    change_number, BaseData, CommentAddedData, CommentLocation, GerritTrigger, GerritUsername,
+    Language, PatchStatus, ProjectName, ReviewerAddedData, RichMessage, VerifiedStatus,
+};
+
+type Variables = Vec<(&'static str, String)>;
+
+/// Longer comments are cut in notifications, the link leads to the rest.
+const MAX_QUOTE_LENGTH: usize = 500;
+
+/// The details of a notification, see `NotificationMessageComposer::label`.
+#[derive(Clone, Copy)]
+enum Label {
+    Project,
+    PatchSet,
+    Author,
+    File,
+    Votes,
+    Reviewer,
+    OpenChange,
+}
+
+/// Percent-encodes the characters of a project or file path that have a
+/// meaning in URLs. Slashes are kept, like Gerrit does.
+fn encode_path(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                encoded.push(byte as char)
+            }
+            byte => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Reviewer triggers describe the change with the fields of `BaseData`.
+fn reviewer_added_base(data: &ReviewerAddedData) -> BaseData {
+    BaseData {
+        change_owner: data.change_owner.clone(),
+        change_owner_username: data.change_owner_username.clone(),
+        change_url: data.change_url.clone(),
+        project: ProjectName::from(data.project.as_str()),
+        instance: data.instance.clone(),
+        patch_set: None,
+    }
+}
+
+fn shorten(comment: &str) -> String {
+    match comment.char_indices().nth(MAX_QUOTE_LENGTH) {
+        Some((index, _)) => format!("{}…", &comment[..index]),
+        None => comment.to_string(),
+    }
+}
 
 pub struct NotificationMessageComposer {
+    // The URL of the Gerrit instance, e.g. https://gerrit.example.com
+    gerrit_url: String,
+    templates: MessageTemplates,
+    language: Language,
+    // Leaves out the details, for users preferring plain text
+    plain_text: bool,
 }
 
 impl NotificationMessageComposer {
-    pub fn create() -> NotificationMessageComposer {
-        NotificationMessageComposer { }
+    /// A composer writing the built-in English messages.
+    pub fn create(gerrit_url: String) -> NotificationMessageComposer {
+        NotificationMessageComposer {
+            gerrit_url,
+            templates: MessageTemplates::default(),
+            language: Language::default(),
+            plain_text: false,
+        }
+    }
+
+    /// Writes the messages in the language of the recipient, using the
+    /// templates of the tenant.
+    pub fn with_templates(
+        self,
+        templates: MessageTemplates,
+        language: Language,
+    ) -> NotificationMessageComposer {
+        NotificationMessageComposer {
+            templates,
+            language,
+            ..self
+        }
+    }
+
+    /// Leaves out details like the project and the votes, if the recipient
+    /// prefers plain text. The quoted comment is kept.
+    pub fn prefer_plain_text(self, plain_text: bool) -> NotificationMessageComposer {
+        NotificationMessageComposer { plain_text, ..self }
+    }
+
+    /// The canonical URL of the change, e.g.
+    /// `https://gerrit.example.com/c/prj/+/2/3/src/main.rs#12`. It points to
+    /// the patch set and the commented file and line, if they are known.
+    fn generate_patch_url(&self, base: &BaseData, location: Option<&CommentLocation>) -> String {
+        let mut url = format!(
+            "{}/c/{}/+/{}",
+            self.gerrit_url,
+            encode_path(&base.project.0),
+            change_number(&base.change_url)
+        );
+        // Gerrit only links files of a patch set
+        if let Some(patch_set) = base.patch_set {
+            url.push_str(&format!("/{}", patch_set));
+            if let Some(location) = location {
+                url.push('/');
+                url.push_str(&encode_path(location.file.trim_start_matches('/')));
+                if let Some(line) = location.line {
+                    url.push_str(&format!("#{}", line));
+                }
+            }
+        }
+        url
+    }
+
+    fn base_variables(&self, base: &BaseData, location: Option<&CommentLocation>) -> Variables {
+        vec![
+            ("project", base.project.to_string()),
+            ("change", change_number(&base.change_url).to_string()),
+            ("change_url", self.generate_patch_url(base, location)),
+            (
+                "patch_set",
+                base.patch_set.map(|p| p.to_string()).unwrap_or_default(),
+            ),
+            ("change_owner", base.change_owner.clone()),
+            (
+                "change_owner_username",
+                base.change_owner_username.to_string(),
+            ),
+            ("instance", base.instance.clone().unwrap_or_default()),
+        ]
     }
 
-    fn generate_patch_url<'a>(&self, base: &'a BaseData) -> String {
-        base.change_url.clone()
+    fn render(&self, event: MessageEvent, variables: Variables) -> Result<String, String> {
+        self.templates
+            .render(self.language, event, &variables)
+            .map_err(|e| {
+                let error = format!(
+                    "Can't render the {} template in {}: {}",
+                    event.key(),
+                    self.language,
+                    e
+                );
+                error!("{}", error);
+                error
+            })
     }
 
-    fn compose_verified_message(&self, verified: &VerifiedStatus, data: &BaseData) -> String {
+    fn compose_verified_message(
+        &self,
+        verified: &VerifiedStatus,
+        author_username: &GerritUsername,
+        data: &BaseData,
+    ) -> Result<String, String> {
         // Feels wrong, remove &Verified...
         if &VerifiedStatus::None == verified {
-            return String::from("Crazy logic error. Don't tell anyone about MetallicSheep.");
+            return Ok(String::from(
+                "Crazy logic error. Don't tell anyone about MetallicSheep.",
+            ));
         }
 
         let emoji = match verified {
@@ -24,51 +171,156 @@ impl NotificationMessageComposer {
             VerifiedStatus::None => "",
         };
 
-        format!(
-            "{} Verified for your patch {} {}.",
-            verified,
-            emoji,
-            self.generate_patch_url(&data),
-        )
+        let mut variables = self.base_variables(data, None);
+        variables.push(("verified", verified.to_string()));
+        variables.push(("emoji", emoji.to_string()));
+        variables.push(("author_username", author_username.to_string()));
+        self.render(MessageEvent::Verified, variables)
     }
 
-    fn compose_ready_for_submit_message(&self, data: &BaseData) -> String {
-        format!(
-            "☑️ A patch is ready to submit! ✨ {}", // Sparkles + Checkbox
-            self.generate_patch_url(data)
-        )
+    fn compose_ready_for_submit_message(
+        &self,
+        author_username: &GerritUsername,
+        data: &BaseData,
+    ) -> Result<String, String> {
+        let mut variables = self.base_variables(data, None);
+        variables.push(("author_username", author_username.to_string()));
+        self.render(MessageEvent::ReadyForSubmit, variables)
+    }
+
+    fn compose_comment_added_message(&self, data: &CommentAddedData) -> Result<String, String> {
+        let location = data.location.as_ref();
+        let mut variables = self.base_variables(&data.base, location);
+        variables.push(("author", data.author.clone()));
+        variables.push(("author_username", data.author_username.to_string()));
+        variables.push(("file", location.map(|l| l.file.clone()).unwrap_or_default()));
+        variables.push((
+            "line",
+            location
+                .and_then(|l| l.line)
+                .map(|line| line.to_string())
+                .unwrap_or_default(),
+        ));
+        self.render(MessageEvent::CommentAdded, variables)
+    }
+
+    fn compose_reviewer_added_message(&self, data: &ReviewerAddedData) -> Result<String, String> {
+        let mut variables = self.base_variables(&reviewer_added_base(data), None);
+        variables.push(("reviewer", data.reviewer.clone()));
+        variables.push(("reviewer_username", data.reviewer_username.to_string()));
+        self.render(MessageEvent::ReviewerAdded, variables)
+    }
+
+    fn label(&self, label: Label) -> &'static str {
+        match (self.language, label) {
+            (Language::English, Label::Project) => "Project",
+            (Language::English, Label::PatchSet) => "Patch set",
+            (Language::English, Label::Author) => "Author",
+            (Language::English, Label::File) => "File",
+            (Language::English, Label::Votes) => "Votes",
+            (Language::English, Label::Reviewer) => "Reviewer",
+            (Language::English, Label::OpenChange) => "Open change",
+            (Language::German, Label::Project) => "Projekt",
+            (Language::German, Label::PatchSet) => "Patchset",
+            (Language::German, Label::Author) => "Autor",
+            (Language::German, Label::File) => "Datei",
+            (Language::German, Label::Votes) => "Bewertungen",
+            (Language::German, Label::Reviewer) => "Reviewer",
+            (Language::German, Label::OpenChange) => "Change öffnen",
+        }
     }
 
-    fn compose_comment_added_message(&self, data: &CommentAddedData) -> String {
-        format!(
-            "Comment was added by {}. 💬 {}",
-            data.author_username,
-            self.generate_patch_url(&data.base)
+    /// Adds the project, the patch set and a button to the change.
+    fn with_change_details(
+        &self,
+        message: RichMessage,
+        base: &BaseData,
+        location: Option<&CommentLocation>,
+    ) -> RichMessage {
+        let message = message.with_field(self.label(Label::Project), base.project.to_string());
+        let message = match base.patch_set {
+            Some(patch_set) => {
+                message.with_field(self.label(Label::PatchSet), patch_set.to_string())
+            }
+            None => message,
+        };
+        message.with_link(
+            self.label(Label::OpenChange),
+            &self.generate_patch_url(base, location),
         )
     }
 
-    pub fn compose<'a>(&self, value: &'a GerritTrigger) -> Result<String, ()> {
+    fn with_details(&self, message: RichMessage, trigger: &GerritTrigger) -> RichMessage {
+        match trigger {
+            GerritTrigger::CommentAdded(data) => {
+                let location = data.location.as_ref();
+                let mut message = self
+                    .with_change_details(message, &data.base, location)
+                    .with_field(self.label(Label::Author), data.author.clone());
+                if let Some(location) = location {
+                    let file = match location.line {
+                        Some(line) => format!("{}:{}", location.file, line),
+                        None => location.file.clone(),
+                    };
+                    message = message.with_field(self.label(Label::File), file);
+                }
+                message.with_quote(data.comment.as_deref().map(shorten))
+            }
+            GerritTrigger::PatchStatusChanged(data) => {
+                let message = self.with_change_details(message, &data.base, None);
+                let votes = match &data.patch_status {
+                    PatchStatus::Both(code_review, verified) => Some(format!(
+                        "Code-Review {}, Verified {}",
+                        code_review, verified
+                    )),
+                    PatchStatus::CodeReview(code_review) => {
+                        Some(format!("Code-Review {}", code_review))
+                    }
+                    PatchStatus::Verified(verified) => Some(format!("Verified {}", verified)),
+                    PatchStatus::ReadyForSubmit | PatchStatus::None => None,
+                };
+                match votes {
+                    Some(votes) => message.with_field(self.label(Label::Votes), votes),
+                    None => message,
+                }
+            }
+            GerritTrigger::ReviewerAdded(data) => message
+                .with_field(self.label(Label::Project), data.project.clone())
+                .with_field(self.label(Label::Reviewer), data.reviewer.clone())
+                .with_link(
+                    self.label(Label::OpenChange),
+                    &self.generate_patch_url(&reviewer_added_base(data), None),
+                ),
+        }
+    }
+
+    /// Composes the notification for a trigger. Its text is rendered from the
+    /// template of the event, the details are added for chats with richer
+    /// formatting.
+    pub fn compose(&self, value: &GerritTrigger) -> Result<RichMessage, String> {
+        let message = self.with_details(RichMessage::text(self.compose_text(value)?), value);
+        if self.plain_text {
+            return Ok(RichMessage::text(message.to_plain_text()));
+        }
+        Ok(message)
+    }
+
+    fn compose_text(&self, value: &GerritTrigger) -> Result<String, String> {
         match value {
-            GerritTrigger::CommentAdded(data) => Ok(self.compose_comment_added_message(data)),
+            GerritTrigger::CommentAdded(data) => self.compose_comment_added_message(data),
             GerritTrigger::PatchStatusChanged(data) => match &data.patch_status {
                 PatchStatus::Both(_, value) | PatchStatus::Verified(value) => {
-                    Ok(self.compose_verified_message(value, &data.base))
+                    self.compose_verified_message(value, &data.author_username, &data.base)
                 }
                 PatchStatus::ReadyForSubmit => {
-                    Ok(self.compose_ready_for_submit_message(&data.base))
-                }
-                _ => {
-                    debug!(
-                        "Can't compose message for patch status {:?}.",
-                        &data.patch_status
-                    );
-                    Err(())
+                    self.compose_ready_for_submit_message(&data.author_username, &data.base)
                 }
+                _ => Err(format!(
+                    "Can't compose message for patch status {:?}.",
+                    &data.patch_status
+                )),
             },
-            _ => {
-                debug!("Can't compose message for type {:?}.", value);
-                Err(())
-            }
+            GerritTrigger::ReviewerAdded(data) => self.compose_reviewer_added_message(data),
         }
     }
 }
@@ -78,10 +330,23 @@ mod test {
 
     use super::NotificationMessageComposer;
     use super::VerifiedStatus;
-    use crate::gerrit_triggers::GerritTrigger;
-    use crate::gerrit_triggers::{BaseData, CommentAddedData, PatchStatusChangedData};
-    use crate::patch_status::PatchStatus;
-    use crate::user::types::{GerritUsername, ProjectName};
+    use crate::service::MessageTemplates;
+    use crate::types::{
+        BaseData, CommentAddedData, CommentLocation, GerritTrigger, GerritUsername, Language,
+        PatchStatus, PatchStatusChangedData, ProjectName, ReviewerAddedData,
+    };
+
+    fn reviewer_added() -> GerritTrigger {
+        GerritTrigger::ReviewerAdded(ReviewerAddedData {
+            change_owner: String::from("change_owner"),
+            change_owner_username: GerritUsername::from("change.owner"),
+            reviewer: String::from("Reviewer <reviewer@example.com>"),
+            reviewer_username: GerritUsername::from("reviewer"),
+            change_url: String::from("2"),
+            project: String::from("prj"),
+            instance: None,
+        })
+    }
 
     #[test]
     fn notification_message_for_verified() {
@@ -90,9 +355,11 @@ mod test {
             change_owner_username: GerritUsername::from("change.owner"),
             change_url: String::from("2"),
             project: ProjectName::from("prj"),
+            instance: None,
+            patch_set: None,
         };
 
-        let composer = NotificationMessageComposer::create(String::from("domain"));
+        let composer = NotificationMessageComposer::create(String::from("https://domain"));
         let message_minus_one =
             composer.compose(&GerritTrigger::PatchStatusChanged(PatchStatusChangedData {
                 base: base.clone(),
@@ -101,7 +368,7 @@ mod test {
             }));
         assert_eq!(
             "-1 Verified for your patch 😰 https://domain/c/prj/+/2.",
-            message_minus_one.unwrap()
+            message_minus_one.unwrap().text
         );
 
         let message_plus_one =
@@ -112,7 +379,7 @@ mod test {
             }));
         assert_eq!(
             "+1 Verified for your patch 🌈 https://domain/c/prj/+/2.",
-            message_plus_one.unwrap()
+            message_plus_one.unwrap().text
         );
         let message_none =
             composer.compose(&GerritTrigger::PatchStatusChanged(PatchStatusChangedData {
@@ -122,7 +389,18 @@ mod test {
             }));
         assert_eq!(
             "Crazy logic error. Don't tell anyone about MetallicSheep.",
-            message_none.unwrap()
+            message_none.unwrap().text
+        );
+
+        let no_status =
+            composer.compose(&GerritTrigger::PatchStatusChanged(PatchStatusChangedData {
+                base: base.clone(),
+                author_username: GerritUsername::from("author.username"),
+                patch_status: PatchStatus::None,
+            }));
+        assert_eq!(
+            no_status,
+            Err(String::from("Can't compose message for patch status None."))
         );
     }
 
@@ -133,9 +411,11 @@ mod test {
             change_owner_username: GerritUsername::from("change.owner"),
             change_url: String::from("2"),
             project: ProjectName::from("prj"),
+            instance: None,
+            patch_set: None,
         };
 
-        let message = NotificationMessageComposer::create(String::from("domain")).compose(
+        let message = NotificationMessageComposer::create(String::from("https://domain")).compose(
             &GerritTrigger::PatchStatusChanged(PatchStatusChangedData {
                 base: base.clone(),
                 author_username: GerritUsername::from("author.username"),
@@ -144,7 +424,7 @@ mod test {
         );
         assert_eq!(
             "☑️ A patch is ready to submit! ✨ https://domain/c/prj/+/2",
-            message.unwrap()
+            message.unwrap().text
         );
     }
 
@@ -195,21 +475,135 @@ mod test {
     #[test]
     fn test_comment_added_notification() {
         // 💬
-        let message = NotificationMessageComposer::create(String::from("gerrit.domain")).compose(
-            &GerritTrigger::CommentAdded(CommentAddedData {
+        let message = NotificationMessageComposer::create(String::from("https://gerrit.domain"))
+            .compose(&GerritTrigger::CommentAdded(CommentAddedData {
                 base: BaseData {
                     change_owner: String::from("change_owner"),
                     change_owner_username: GerritUsername::from("change.owner"),
                     change_url: String::from("2"),
                     project: ProjectName::from("prj"),
+                    instance: None,
+                    patch_set: None,
                 },
                 author: String::from("author lastname <author email>"),
                 author_username: GerritUsername::from("author"),
+                location: None,
+                comment: None,
+            }));
+        assert_eq!(
+            message.unwrap().text,
+            "Comment was added by author. 💬 https://gerrit.domain/c/prj/+/2"
+        );
+    }
+
+    #[test]
+    fn notification_message_for_reviewer_added() {
+        let message = NotificationMessageComposer::create(String::from("https://domain"))
+            .compose(&reviewer_added());
+        assert_eq!(
+            message.unwrap().text,
+            "You were added as reviewer. https://domain/c/prj/+/2"
+        );
+    }
+
+    #[test]
+    fn notification_message_in_german() {
+        let message = NotificationMessageComposer::create(String::from("https://domain"))
+            .with_templates(MessageTemplates::default(), Language::German)
+            .compose(&reviewer_added());
+        assert_eq!(
+            message.unwrap().text,
+            "Du wurdest als Reviewer hinzugefügt. https://domain/c/prj/+/2"
+        );
+    }
+
+    #[test]
+    fn notification_message_from_custom_template() {
+        let templates = MessageTemplates::parse(
+            r#"{"en": {"reviewer_added": "{change_owner_username} asks {reviewer} in {project}"}}"#,
+        )
+        .unwrap();
+        let message = NotificationMessageComposer::create(String::from("https://domain"))
+            .with_templates(templates, Language::English)
+            .compose(&reviewer_added());
+        assert_eq!(
+            message.unwrap().text,
+            "change.owner asks Reviewer <reviewer@example.com> in prj"
+        );
+    }
+
+    #[test]
+    fn links_patch_set_and_comment_location() {
+        let trigger = GerritTrigger::CommentAdded(CommentAddedData {
+            base: BaseData {
+                change_owner: String::from("change_owner"),
+                change_owner_username: GerritUsername::from("change.owner"),
+                change_url: String::from("https://gerrit.domain/r/c/tools/prj/+/2"),
+                project: ProjectName::from("tools/prj"),
+                instance: None,
+                patch_set: Some(3),
+            },
+            author: String::from("author lastname <author email>"),
+            author_username: GerritUsername::from("author"),
+            location: Some(CommentLocation {
+                file: String::from("src/my file.rs"),
+                line: Some(12),
             }),
+            comment: None,
+        });
+
+        let message = NotificationMessageComposer::create(String::from("https://gerrit.domain/r"))
+            .compose(&trigger);
+        assert_eq!(
+            message.unwrap().text,
+            "Comment was added by author. 💬 https://gerrit.domain/r/c/tools/prj/+/2/3/src/my%20file.rs#12"
+        );
+    }
+
+    #[test]
+    fn adds_details_for_rich_chats() {
+        let trigger = GerritTrigger::CommentAdded(CommentAddedData {
+            base: BaseData {
+                change_owner: String::from("change_owner"),
+                change_owner_username: GerritUsername::from("change.owner"),
+                change_url: String::from("2"),
+                project: ProjectName::from("prj"),
+                instance: None,
+                patch_set: Some(3),
+            },
+            author: String::from("Author Lastname <author@example.com>"),
+            author_username: GerritUsername::from("author"),
+            location: Some(CommentLocation {
+                file: String::from("main.rs"),
+                line: None,
+            }),
+            comment: Some("x".repeat(600)),
+        });
+
+        let message = NotificationMessageComposer::create(String::from("https://domain"))
+            .with_templates(MessageTemplates::default(), Language::German)
+            .compose(&trigger)
+            .unwrap();
+
+        assert_eq!(
+            message.fields,
+            vec![
+                (String::from("Projekt"), String::from("prj")),
+                (String::from("Patchset"), String::from("3")),
+                (
+                    String::from("Autor"),
+                    String::from("Author Lastname <author@example.com>")
+                ),
+                (String::from("Datei"), String::from("main.rs")),
+            ]
         );
         assert_eq!(
-            message.unwrap(),
-            "Comment was added by author. 💬 https://gerrit.domain/c/prj/+/2"
+            message.links,
+            vec![(
+                String::from("Change öffnen"),
+                String::from("https://domain/c/prj/+/2/3/main.rs")
+            )]
         );
+        assert_eq!(message.quote.unwrap().chars().count(), 501);
     }
 }
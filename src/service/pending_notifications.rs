@@ -0,0 +1,162 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use super::AuditEntry;
+use crate::types::{GerritUsername, PathToUserData, RichMessage};
+
+/// How many notifications are kept per user with an ambiguous mapping. Older
+/// ones are dropped.
+const MAX_PENDING_NOTIFICATIONS: usize = 20;
+
+/// A notification kept until the mapping of its recipient is fixed, with the
+/// audit entry that is recorded once it is delivered.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PendingNotification {
+    pub message: RichMessage,
+    pub entry: AuditEntry,
+}
+
+/// Notifications for users with an ambiguous mapping, oldest first. Every
+/// change is written to `pending.json` in the data directory, so they survive
+/// a restart of the server.
+#[derive(Debug, Default)]
+pub struct PendingNotifications {
+    path: PathBuf,
+    queues: HashMap<GerritUsername, Vec<PendingNotification>>,
+}
+
+impl PendingNotifications {
+    /// Loads the notifications kept in the data directory. An unreadable file
+    /// is logged and the server starts without them.
+    pub fn load(data_dir: &str) -> PendingNotifications {
+        let path = PathToUserData::pending_notifications(data_dir)
+            .as_path()
+            .to_path_buf();
+        let queues = match read_queues(&path) {
+            Ok(queues) => queues,
+            Err(e) => {
+                error!("{} Continuing without pending notifications.", e);
+                HashMap::new()
+            }
+        };
+        PendingNotifications { path, queues }
+    }
+
+    /// Keeps a notification until the mapping of the user is fixed.
+    pub fn push(&mut self, username: &GerritUsername, notification: PendingNotification) {
+        let pending = self.queues.entry(username.clone()).or_default();
+        pending.push(notification);
+        if pending.len() > MAX_PENDING_NOTIFICATIONS {
+            warn!("Dropping oldest pending notification of '{}'.", username);
+            pending.remove(0);
+        }
+        self.save();
+    }
+
+    /// Removes and returns the notifications kept for a user.
+    pub fn take(&mut self, username: &GerritUsername) -> Vec<PendingNotification> {
+        let pending = self.queues.remove(username).unwrap_or_default();
+        if !pending.is_empty() {
+            self.save();
+        }
+        pending
+    }
+
+    pub fn count(&self, username: &GerritUsername) -> usize {
+        self.queues.get(username).map_or(0, Vec::len)
+    }
+
+    /// Writes the notifications to a temporary file and renames it, so a
+    /// crash never leaves a partially written file.
+    fn save(&self) {
+        let temporary = self.path.with_extension("json.tmp");
+        let result = serde_json::to_vec(&self.queues)
+            .map_err(|e| e.to_string())
+            .and_then(|content| {
+                fs::write(&temporary, content)
+                    .and_then(|_| fs::rename(&temporary, &self.path))
+                    .map_err(|e| e.to_string())
+            });
+        if let Err(e) = result {
+            error!(
+                "Couldn't write pending notifications to {}. Cause: {}.",
+                self.path.display(),
+                e
+            );
+        }
+    }
+}
+
+fn read_queues(path: &Path) -> Result<HashMap<GerritUsername, Vec<PendingNotification>>, String> {
+    let content = match fs::read(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(HashMap::new()),
+        Err(e) => {
+            return Err(format!(
+                "Couldn't read pending notifications {}. Cause: {}.",
+                path.display(),
+                e
+            ))
+        }
+    };
+    serde_json::from_slice(&content).map_err(|e| {
+        format!(
+            "Invalid pending notifications {}. Cause: {}.",
+            path.display(),
+            e
+        )
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PendingNotification, PendingNotifications, MAX_PENDING_NOTIFICATIONS};
+    use crate::{
+        service::{AuditEntry, AuditOutcome},
+        types::{GerritUsername, RichMessage},
+    };
+    use chrono::Utc;
+
+    fn notification(text: &str) -> PendingNotification {
+        PendingNotification {
+            message: RichMessage::text(text),
+            entry: AuditEntry {
+                time: Utc::now(),
+                trigger: String::from("comment_added"),
+                instance: None,
+                project: String::from("my_project"),
+                change: String::from("1234"),
+                author: GerritUsername::from("author"),
+                recipient: GerritUsername::from("jdoe"),
+                outcome: AuditOutcome::Queued,
+            },
+        }
+    }
+
+    #[test]
+    fn keeps_notifications_across_restarts() {
+        let dir = std::env::temp_dir().join(format!("chtbtr-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let data_dir = dir.to_str().unwrap();
+        let jdoe = GerritUsername::from("jdoe");
+
+        let mut pending = PendingNotifications::load(data_dir);
+        for index in 0..=MAX_PENDING_NOTIFICATIONS {
+            pending.push(&jdoe, notification(&index.to_string()));
+        }
+
+        let mut reloaded = PendingNotifications::load(data_dir);
+        assert_eq!(reloaded.count(&jdoe), MAX_PENDING_NOTIFICATIONS);
+        let taken = reloaded.take(&jdoe);
+        assert_eq!(taken.len(), MAX_PENDING_NOTIFICATIONS);
+        assert_eq!(taken[0].message, RichMessage::text("1"));
+        assert_eq!(PendingNotifications::load(data_dir).count(&jdoe), 0);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,34 +1,42 @@
-use acteur::ServiceAssistant;
+use acteur::ActorAssistant;
 use async_trait::async_trait;
+use chrono::{Duration, Utc};
 use std::collections::HashMap;
 
 use crate::{
     actor::{
-        messages::{SearchProfileId, SetProfileIdMapping},
-        JustClient, ResolverClient, UserServiceClient,
+        messages::{RecordMetric, SearchProfileId, SetProfileIdMapping},
+        JustClient, MetricsCollector, ResolverClient, UserServiceClient,
     },
-    types::{GerritUsername, ProfileId, Synchronization},
+    service::{AuditLog, DirectoryEntry, Metric, PendingNotifications, UserDirectory},
+    types::{GerritAccount, GerritUsername, ProfileId, SyncRecord, Synchronization, TenantId},
 };
 
 /// Resolver maps a username or name to a ProfileId and caches the result.
+/// Usernames of namespaced Gerrit instances carry the namespace (see
+/// `GerritInstance`), so accounts with the same username on different
+/// instances are resolved and cached separately.
 ///
 /// Expects to be initialized with a cache. Resolver will automatically try to
 /// resolve unavailable mappings using the Just API.
 ///
-/// A mapping is represented by three states:
+/// A mapping is represented by four states:
 ///
 /// * A mapping has been found.
 /// * A mapping can't be resolved (i.e. no ProfileId was found).
+/// * A mapping is ambiguous (i.e. several ProfileIds were found). An admin has
+///   to pick one, notifications are kept until then.
 /// * A mapping is unavailable and no attempt has been made to resolve it.
 #[async_trait]
 pub trait ResolverService {
-    /// Resolver will go through a three step process to map a username to a
+    /// Resolver will go through a two step process to map a username to a
     /// ProfileId.
     ///
-    ///   1. Look up the username in the cache and return the saved status.
-    ///   2. Look up the mapping from disk. We do this to ensure that a manual
-    ///      change on the disk can be discovered.
-    ///   3. Make a request to the Just API trying different combinations of the
+    ///   1. Look up the username in the cache and return the saved status. The
+    ///      server watches the data directory and reloads the cache, so a
+    ///      manual change on the disk is discovered (see
+    ///      `ReloadProfileIdMappings`).
+    ///   2. Make a request to the Just API trying different combinations of the
     ///      name associated with the Gerrit account. We do this in case no name
     ///      is found, e.g. because there is an unsupported Umlaut or too many
     ///      users are found for only part of the name.
@@ -45,8 +53,17 @@ pub trait ResolverService {
 #[derive(Debug)]
 pub struct ProfileIdResolver {
     // just_api_actor: Addr<JustApiActor>,
-    pub cache: HashMap<GerritUsername, Synchronization<ProfileId>>,
-    pub acteur: ServiceAssistant<ResolverClient>,
+    pub cache: HashMap<GerritUsername, SyncRecord>,
+    pub acteur: ActorAssistant<ResolverClient>,
+    // The tenant whose users are resolved, addresses its other actors
+    pub tenant: TenantId,
+    // How long until resolving an unresolvable user is tried again, never if
+    // not set
+    pub unresolved_ttl: Option<Duration>,
+    // Notifications for users with an ambiguous mapping
+    pub pending: PendingNotifications,
+    // Records what happened to pending notifications once they are delivered
+    pub audit_log: AuditLog,
 }
 
 #[async_trait]
@@ -56,36 +73,44 @@ impl ResolverService for ProfileIdResolver {
         username: &GerritUsername,
         name: &str,
     ) -> Result<Option<ProfileId>, String> {
-        let result = self.lookup_cache(username);
+        let mut result = self.lookup_cache(username);
         debug!("Looking up {} from cache. Result: {:?}.", username, result);
+        if self.should_retry(username) {
+            info!(
+                "Resolving '{}' again, the last attempt is older than {:?}.",
+                username, self.unresolved_ttl
+            );
+            result = Synchronization::NotMappedYet;
+        }
+
+        let metric = match result {
+            Synchronization::NotMappedYet => Metric::ResolverCacheMiss,
+            _ => Metric::ResolverCacheHit,
+        };
+        self.acteur
+            .send_to_service::<MetricsCollector, _>(RecordMetric(metric))
+            .await;
+
         match result {
             Synchronization::Some(v) => Ok(Some(v)),
-            Synchronization::None => Ok(None),
+            Synchronization::None | Synchronization::Ambiguous(_) => Ok(None),
             Synchronization::NotMappedYet => {
                 debug!(
                     "Handling missing ProfileId mapping for Gerrit user '{}'",
                     username
                 );
-                let stuff = self.request_mapping(name);
-                let request_result = stuff.await?;
-                debug!("Request was successful. Result is {:?}.", request_result);
-                if let Some(profile_id_from_api) = request_result {
-                    let sync_result = Synchronization::Some(profile_id_from_api.clone());
-                    self.cache.insert(username.clone(), sync_result.clone());
-                    self.acteur
-                        .send_to_service::<UserServiceClient, SetProfileIdMapping>(
-                            SetProfileIdMapping(username.clone(), sync_result.clone()),
-                        )
-                        .await;
-                    Ok(Some(profile_id_from_api))
-                } else {
-                    self.cache.insert(username.clone(), Synchronization::None);
-                    self.acteur
-                        .send_to_service::<UserServiceClient, SetProfileIdMapping>(
-                            SetProfileIdMapping(username.clone(), Synchronization::None),
-                        )
-                        .await;
-                    Ok(None)
+                let record = self.request_mapping(name).await?;
+                debug!("Request was successful. Result is {:?}.", record);
+                self.cache.insert(username.clone(), record.clone());
+                self.acteur
+                    .send_to_actor::<UserServiceClient, SetProfileIdMapping>(
+                        self.tenant.clone(),
+                        SetProfileIdMapping(username.clone(), record.clone()),
+                    )
+                    .await;
+                match record.mapping {
+                    Synchronization::Some(profile_id) => Ok(Some(profile_id)),
+                    _ => Ok(None),
                 }
             }
         }
@@ -94,10 +119,40 @@ impl ResolverService for ProfileIdResolver {
 
 impl ProfileIdResolver {
     pub fn new(
-        cache: HashMap<GerritUsername, Synchronization<ProfileId>>,
-        acteur: ServiceAssistant<ResolverClient>,
+        cache: HashMap<GerritUsername, SyncRecord>,
+        acteur: ActorAssistant<ResolverClient>,
+        tenant: TenantId,
+        data_dir: &str,
+        unresolved_ttl: Option<Duration>,
     ) -> ProfileIdResolver {
-        ProfileIdResolver { cache, acteur }
+        ProfileIdResolver {
+            cache,
+            acteur,
+            tenant,
+            unresolved_ttl,
+            pending: PendingNotifications::load(data_dir),
+            audit_log: AuditLog::new(data_dir),
+        }
+    }
+
+    /// Whether the user couldn't be resolved for longer than the TTL.
+    fn should_retry(&self, username: &GerritUsername) -> bool {
+        match (self.unresolved_ttl, self.cache.get(username)) {
+            (Some(ttl), Some(record)) => record.should_retry(ttl, &Utc::now()),
+            _ => false,
+        }
+    }
+
+    /// Reverse lookup of a `ProfileId` in the cache. A user with accounts on
+    /// several Gerrit instances is mapped several times, the username of the
+    /// default instance is preferred.
+    pub fn username_for(&self, profile_id: &ProfileId) -> Option<GerritUsername> {
+        self.cache
+            .iter()
+            .filter(|(_, record)| record.mapping == Synchronization::Some(profile_id.clone()))
+            .map(|(username, _)| username)
+            .min_by_key(|username| (username.namespace().is_some(), username.0.clone()))
+            .cloned()
     }
 
     /**
@@ -106,19 +161,54 @@ impl ProfileIdResolver {
      * the difference between a missing entry and an entry that can't be mapped
      * (e.g. multiple results when asking API).
      */
-    fn lookup_cache(&self, username: &GerritUsername) -> Synchronization<ProfileId> {
+    pub fn lookup_cache(&self, username: &GerritUsername) -> Synchronization<ProfileId> {
         match self.cache.get(username) {
-            Some(status) => status.clone(),
+            Some(record) => record.mapping.clone(),
             // No entry found most likely means we haven't tried yet.
             None => Synchronization::NotMappedYet,
         }
     }
 
-    async fn request_mapping(&self, name: &str) -> Result<Option<ProfileId>, String> {
+    async fn request_mapping(&self, name: &str) -> Result<SyncRecord, String> {
         self.acteur
-            .call_service::<JustClient, SearchProfileId>(SearchProfileId(name.to_string()))
+            .call_actor::<JustClient, SearchProfileId>(
+                self.tenant.clone(),
+                SearchProfileId(name.to_string()),
+            )
             .await
             .expect("Couldn't send")
         //.unwrap_or("Couldn't send SearchProfileId  message to JustClient actor.".to_string())
     }
 }
+
+/// Consults a `UserDirectory` before the `inner` resolver, the directory takes
+/// precedence. Users mapped to a `ProfileId` in the directory are never
+/// searched in Just, users mapped to an email are searched by that email.
+pub struct DirectoryResolver<'a, R> {
+    pub directory: &'a UserDirectory,
+    pub inner: &'a mut R,
+}
+
+#[async_trait]
+impl<'a, R: ResolverService + Send> ResolverService for DirectoryResolver<'a, R> {
+    async fn resolve(
+        &mut self,
+        username: &GerritUsername,
+        name: &str,
+    ) -> Result<Option<ProfileId>, String> {
+        match self.directory.get(username) {
+            Some(DirectoryEntry::ProfileId(profile_id)) => {
+                debug!("Found {} in the user directory.", username);
+                Ok(Some(profile_id.clone()))
+            }
+            Some(DirectoryEntry::Email(email)) => {
+                let account = GerritAccount {
+                    email: Some(email.clone()),
+                    ..GerritAccount::from(name)
+                };
+                self.inner.resolve(username, &account.to_string()).await
+            }
+            None => self.inner.resolve(username, name).await,
+        }
+    }
+}
@@ -0,0 +1,304 @@
+use ron::ser::PrettyConfig;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::HashMap;
+use std::path::Path;
+
+use super::{SettingsError, UserService};
+use crate::{
+    default::DEFAULT_SETTINGS,
+    types::{GerritUsername, ProfileId, Settings, SyncRecord, Synchronization},
+};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS settings (
+    username TEXT PRIMARY KEY NOT NULL,
+    content TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS settings_backups (
+    username TEXT NOT NULL,
+    version TEXT NOT NULL,
+    content TEXT NOT NULL,
+    PRIMARY KEY (username, version)
+);
+CREATE TABLE IF NOT EXISTS sync (
+    username TEXT PRIMARY KEY NOT NULL,
+    record TEXT NOT NULL
+);
+";
+
+/// Stores user data in an embedded SQLite database instead of a directory per
+/// user. Settings and sync records are stored in the same format as their
+/// files, so they behave the same, e.g. outdated settings are migrated on load
+/// and the original is kept as backup.
+#[derive(Debug)]
+pub struct SqliteUserService {
+    connection: Connection,
+}
+
+fn io_error(e: rusqlite::Error) -> SettingsError {
+    SettingsError::Io(format!("Database error. Cause: {}", e))
+}
+
+impl SqliteUserService {
+    /// Opens the database, creating it and its tables if necessary.
+    pub fn open(path: &Path) -> Result<SqliteUserService, String> {
+        let connection = Connection::open(path)
+            .map_err(|e| format!("Couldn't open database {}. Cause: {}.", path.display(), e))?;
+        SqliteUserService::with_connection(connection)
+    }
+
+    pub fn in_memory() -> Result<SqliteUserService, String> {
+        let connection = Connection::open_in_memory()
+            .map_err(|e| format!("Couldn't open in-memory database. Cause: {}.", e))?;
+        SqliteUserService::with_connection(connection)
+    }
+
+    fn with_connection(connection: Connection) -> Result<SqliteUserService, String> {
+        connection
+            .execute_batch(SCHEMA)
+            .map_err(|e| format!("Couldn't create database tables. Cause: {}.", e))?;
+        Ok(SqliteUserService { connection })
+    }
+
+    fn settings_content(&self, user: &GerritUsername) -> Result<Option<String>, SettingsError> {
+        self.connection
+            .query_row(
+                "SELECT content FROM settings WHERE username = ?1",
+                params![user.0],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(io_error)
+    }
+
+    /// Stores the settings of a user as they are, without validating them.
+    /// Used to import settings files, so broken files stay broken.
+    pub fn store_settings_content(
+        &self,
+        user: &GerritUsername,
+        content: &str,
+    ) -> Result<(), SettingsError> {
+        self.connection
+            .execute(
+                "INSERT OR REPLACE INTO settings (username, content) VALUES (?1, ?2)",
+                params![user.0, content],
+            )
+            .map(|_| ())
+            .map_err(io_error)
+    }
+
+    fn write_migrated_settings(
+        &self,
+        user: &GerritUsername,
+        original: &str,
+        original_version: &str,
+        settings: &Settings,
+    ) -> Result<(), SettingsError> {
+        self.connection
+            .execute(
+                "INSERT OR REPLACE INTO settings_backups (username, version, content) VALUES (?1, ?2, ?3)",
+                params![user.0, original_version, original],
+            )
+            .map_err(io_error)?;
+        self.save_settings(user, settings)?;
+
+        info!(
+            "Migrated settings for {} from {} to {}.",
+            user,
+            original_version,
+            settings.version()
+        );
+        Ok(())
+    }
+}
+
+impl UserService for SqliteUserService {
+    fn load_sync_cache(&self) -> HashMap<GerritUsername, SyncRecord> {
+        let mut result = HashMap::new();
+        let mut statement = match self.connection.prepare("SELECT username, record FROM sync") {
+            Ok(statement) => statement,
+            Err(e) => {
+                error!("Couldn't read sync records. Cause: {}.", e);
+                return result;
+            }
+        };
+
+        let rows = statement.query_map(params![], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        });
+        let rows = match rows {
+            Ok(rows) => rows,
+            Err(e) => {
+                error!("Couldn't read sync records. Cause: {}.", e);
+                return result;
+            }
+        };
+
+        for row in rows {
+            match row {
+                Ok((username, record)) => match SyncRecord::parse(&record) {
+                    Ok(record) => {
+                        result.insert(GerritUsername(username), record);
+                    }
+                    Err(e) => warn!("Couldn't parse sync record of {}. Cause: {}.", username, e),
+                },
+                Err(e) => warn!("Couldn't read sync record. Cause: {}.", e),
+            }
+        }
+
+        result
+    }
+
+    fn load_settings(&self, user: &GerritUsername) -> Result<Settings, SettingsError> {
+        let content = match self.settings_content(user)? {
+            Some(content) => content,
+            None => {
+                self.store_settings_content(user, DEFAULT_SETTINGS)?;
+                String::from(DEFAULT_SETTINGS)
+            }
+        };
+
+        let settings: Settings = ron::de::from_str(&content)?;
+        if settings.is_current() {
+            return Ok(settings);
+        }
+
+        // A failed rewrite is not fatal, we migrate again on the next load.
+        let original_version = settings.version();
+        let settings = settings.migrate();
+        if let Err(e) = self.write_migrated_settings(user, &content, original_version, &settings) {
+            warn!(
+                "Couldn't write migrated settings for {}. Cause: {}",
+                user, e
+            );
+        }
+
+        Ok(settings)
+    }
+
+    fn read_settings(&self, user: &GerritUsername) -> Result<Option<Settings>, SettingsError> {
+        match self.settings_content(user)? {
+            Some(content) => Ok(Some(ron::de::from_str(&content)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn watchers(&self, project: &str, change: &str) -> Result<Vec<GerritUsername>, String> {
+        let error = |e: rusqlite::Error| format!("Couldn't read settings. Cause: {}.", e);
+        let mut statement = self
+            .connection
+            .prepare("SELECT username, content FROM settings ORDER BY username")
+            .map_err(error)?;
+        let rows = statement
+            .query_map(params![], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(error)?;
+
+        let mut watchers = vec![];
+        for row in rows {
+            let (username, content) = row.map_err(error)?;
+            match ron::de::from_str::<Settings>(&content) {
+                Ok(settings) if settings.watches(project, change) => {
+                    watchers.push(GerritUsername(username))
+                }
+                Ok(_) => {}
+                Err(e) => debug!(
+                    "Skipping settings of {} for watches. Cause: {}",
+                    username, e
+                ),
+            }
+        }
+        Ok(watchers)
+    }
+
+    fn save_settings(
+        &self,
+        user: &GerritUsername,
+        settings: &Settings,
+    ) -> Result<(), SettingsError> {
+        let serialized =
+            ron::ser::to_string_pretty(settings, PrettyConfig::default()).map_err(|e| {
+                SettingsError::Invalid(format!("Error serializing settings. Cause: {}", e))
+            })?;
+        self.store_settings_content(user, &serialized)
+    }
+
+    fn update_settings(
+        &self,
+        user: &GerritUsername,
+        update: &dyn Fn(Settings) -> Settings,
+    ) -> Result<Settings, SettingsError> {
+        let transaction = self.connection.unchecked_transaction().map_err(io_error)?;
+        let settings = update(self.load_settings(user)?);
+        self.save_settings(user, &settings)?;
+        transaction.commit().map_err(io_error)?;
+        Ok(settings)
+    }
+
+    fn save_sync(
+        &self,
+        user: &GerritUsername,
+        profile_id: &ProfileId,
+    ) -> Result<Synchronization<ProfileId>, String> {
+        let profile_id = Synchronization::Some(profile_id.clone());
+        self.set_sync(user, &SyncRecord::new(profile_id.clone(), None))?;
+        Ok(profile_id)
+    }
+
+    fn set_sync(&self, user: &GerritUsername, record: &SyncRecord) -> Result<(), String> {
+        let serialized = ron::ser::to_string(record)
+            .map_err(|e| format!("Error serializing Synchronization struct. Cause: {}", e))?;
+        self.connection
+            .execute(
+                "INSERT OR REPLACE INTO sync (username, record) VALUES (?1, ?2)",
+                params![user.0, serialized],
+            )
+            .map(|_| ())
+            .map_err(|e| format!("Error writing sync data of {}. Cause: {}.", user, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SqliteUserService;
+    use crate::service::{user_service::suite, SettingsError, UserService};
+    use crate::types::GerritUsername;
+
+    #[test]
+    fn passes_user_service_suite() {
+        suite::run(&SqliteUserService::in_memory().unwrap());
+    }
+
+    #[test]
+    fn migrates_stored_settings() {
+        let service = SqliteUserService::in_memory().unwrap();
+        let user = GerritUsername::from("user.v1");
+        let v1 =
+            std::fs::read_to_string("tests/user2/migrate_settings/user.v1/settings.ron").unwrap();
+        service.store_settings_content(&user, &v1).unwrap();
+
+        assert!(service.load_settings(&user).unwrap().is_current());
+        let backup: String = service
+            .connection
+            .query_row(
+                "SELECT content FROM settings_backups WHERE username = ?1",
+                rusqlite::params![user.0],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(backup, v1);
+    }
+
+    #[test]
+    fn reports_invalid_settings() {
+        let service = SqliteUserService::in_memory().unwrap();
+        let user = GerritUsername::from("user.invalid");
+        service.store_settings_content(&user, "V2(").unwrap();
+
+        match service.load_settings(&user) {
+            Err(SettingsError::Invalid(_)) => {}
+            other => panic!("Expected invalid settings, got {:?}", other),
+        }
+    }
+}
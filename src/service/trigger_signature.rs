@@ -0,0 +1,88 @@
+use chrono::{DateTime, Utc};
+use hmac_sha256::HMAC;
+
+use super::login_token::{constant_time_eq, from_hex, to_hex};
+
+/// Header with the time a trigger was signed, as Unix timestamp.
+pub const TIMESTAMP_HEADER: &str = "X-Chtbtr-Timestamp";
+
+/// Header with the signature of a trigger.
+pub const SIGNATURE_HEADER: &str = "X-Chtbtr-Signature";
+
+/// How many seconds the time of signing may be off, so a recorded trigger
+/// can't be replayed later.
+const MAX_CLOCK_SKEW: i64 = 300;
+
+/// Signs the body of a trigger request with a secret shared by the hook
+/// binaries and the server. The signature is the hex HMAC-SHA256 of
+/// `{timestamp}.{body}`.
+pub struct TriggerSignature;
+
+impl TriggerSignature {
+    fn mac(timestamp: i64, body: &[u8], secret: &str) -> [u8; 32] {
+        let mut payload = format!("{}.", timestamp).into_bytes();
+        payload.extend_from_slice(body);
+        HMAC::mac(&payload, secret.as_bytes())
+    }
+
+    pub fn sign(timestamp: i64, body: &[u8], secret: &str) -> String {
+        to_hex(&TriggerSignature::mac(timestamp, body, secret))
+    }
+
+    /// Checks the signature and that the trigger was signed recently.
+    pub fn verify(
+        timestamp: &str,
+        signature: &str,
+        body: &[u8],
+        secret: &str,
+        now: &DateTime<Utc>,
+    ) -> Result<(), String> {
+        let timestamp: i64 = timestamp
+            .parse()
+            .map_err(|_| String::from("Invalid timestamp."))?;
+        let expected = TriggerSignature::mac(timestamp, body, secret);
+        let signature = from_hex(signature).ok_or_else(|| String::from("Invalid signature."))?;
+        if !constant_time_eq(&expected, &signature) {
+            return Err(String::from("Invalid signature."));
+        }
+
+        if (now.timestamp() - timestamp).abs() > MAX_CLOCK_SKEW {
+            return Err(String::from("The trigger was signed too long ago."));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TriggerSignature;
+    use chrono::{Duration, TimeZone, Utc};
+
+    #[test]
+    fn verifies_signed_body() {
+        let now = Utc.ymd(2020, 7, 1).and_hms(12, 0, 0);
+        let body = br#"{"ReviewerAdded":{}}"#;
+        let timestamp = now.timestamp().to_string();
+        let signature = TriggerSignature::sign(now.timestamp(), body, "secret");
+
+        assert_eq!(
+            TriggerSignature::verify(&timestamp, &signature, body, "secret", &now),
+            Ok(())
+        );
+        assert!(TriggerSignature::verify(&timestamp, &signature, b"{}", "secret", &now).is_err());
+        assert!(TriggerSignature::verify(&timestamp, &signature, body, "other", &now).is_err());
+        assert!(TriggerSignature::verify("0", &signature, body, "secret", &now).is_err());
+        assert!(TriggerSignature::verify(&timestamp, "zz", body, "secret", &now).is_err());
+    }
+
+    #[test]
+    fn rejects_old_signatures() {
+        let signed_at = Utc.ymd(2020, 7, 1).and_hms(12, 0, 0);
+        let body = b"{}";
+        let timestamp = signed_at.timestamp().to_string();
+        let signature = TriggerSignature::sign(signed_at.timestamp(), body, "secret");
+        let now = signed_at + Duration::minutes(10);
+
+        assert!(TriggerSignature::verify(&timestamp, &signature, body, "secret", &now).is_err());
+    }
+}
@@ -0,0 +1,173 @@
+use chrono::Utc;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::types::GerritTrigger;
+
+/// Header with the id the hook gave a trigger. If the hook spools the trigger,
+/// e.g. because the server didn't respond in time, the file is named after
+/// the id, so the server can skip it if it handled the trigger anyway.
+pub const TRIGGER_ID_HEADER: &str = "X-Chtbtr-Trigger-Id";
+
+/// A directory of triggers the hook binaries couldn't deliver, e.g. because
+/// the server was down. The server delivers them on startup.
+///
+/// Every trigger is a JSON file named after the time it was spooled, so they
+/// can be delivered in order, and its id (see `TRIGGER_ID_HEADER`). Files are written under a temporary name and
+/// renamed, so the server never reads a partially written trigger.
+#[derive(Clone, Debug)]
+pub struct TriggerSpool {
+    pub dir: PathBuf,
+}
+
+impl TriggerSpool {
+    pub fn new(dir: &str) -> TriggerSpool {
+        TriggerSpool {
+            dir: PathBuf::from(dir),
+        }
+    }
+
+    /// Writes a trigger to the spool and returns the path of its file.
+    pub fn push(&self, id: &str, trigger: &GerritTrigger) -> Result<PathBuf, String> {
+        let content = serde_json::to_vec(trigger)
+            .map_err(|e| format!("Couldn't serialize trigger. Cause: {}", e))?;
+        let name = format!("{}-{}.json", Utc::now().timestamp_millis(), id);
+        let path = self.dir.join(&name);
+        let temporary = self.dir.join(format!(".{}.tmp", name));
+
+        fs::create_dir_all(&self.dir)
+            .and_then(|_| fs::write(&temporary, content))
+            .and_then(|_| fs::rename(&temporary, &path))
+            .map_err(|e| {
+                format!(
+                    "Couldn't spool trigger to {}. Cause: {}.",
+                    path.display(),
+                    e
+                )
+            })?;
+        Ok(path)
+    }
+
+    /// The spooled triggers, oldest first. Files that can't be parsed are
+    /// rejected and skipped.
+    pub fn pending(&self) -> Vec<(PathBuf, GerritTrigger)> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return vec![],
+            Err(e) => {
+                error!("Couldn't read spool {}. Cause: {}.", self.dir.display(), e);
+                return vec![];
+            }
+        };
+
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| is_spooled_trigger(path))
+            .collect();
+        paths.sort();
+
+        paths
+            .into_iter()
+            .filter_map(|path| {
+                let parsed = fs::read(&path)
+                    .map_err(|e| e.to_string())
+                    .and_then(|content| {
+                        serde_json::from_slice(&content).map_err(|e| e.to_string())
+                    });
+                match parsed {
+                    Ok(trigger) => Some((path, trigger)),
+                    Err(e) => {
+                        warn!("Skipping spooled trigger {}. Cause: {}", path.display(), e);
+                        if let Err(e) = self.reject(&path) {
+                            warn!("{}", e);
+                        }
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// The id of a spooled trigger, as given by the hook.
+    pub fn id(path: &Path) -> Option<String> {
+        path.file_stem()
+            .map(|name| name.to_string_lossy())
+            .and_then(|name| name.split_once('-').map(|(_, id)| id.to_string()))
+    }
+
+    /// Renames a trigger that can't be delivered to `*.invalid`, so it isn't
+    /// retried but kept for an admin to look at.
+    pub fn reject(&self, path: &Path) -> Result<(), String> {
+        fs::rename(path, path.with_extension("invalid"))
+            .map_err(|e| format!("Couldn't rename {}. Cause: {}.", path.display(), e))
+    }
+
+    /// Removes a delivered trigger from the spool.
+    pub fn remove(&self, path: &Path) -> Result<(), String> {
+        fs::remove_file(path)
+            .map_err(|e| format!("Couldn't remove {}. Cause: {}.", path.display(), e))
+    }
+}
+
+fn is_spooled_trigger(path: &Path) -> bool {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    !name.starts_with('.') && name.ends_with(".json")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TriggerSpool;
+    use crate::types::{GerritTrigger, GerritUsername, ReviewerAddedData};
+    use std::fs;
+
+    fn trigger(project: &str) -> GerritTrigger {
+        GerritTrigger::ReviewerAdded(ReviewerAddedData {
+            change_url: String::from("123"),
+            project: String::from(project),
+            reviewer: String::from("Reviewer <reviewer@example.com>"),
+            reviewer_username: GerritUsername::from("reviewer"),
+            change_owner: String::from("Owner <owner@example.com>"),
+            change_owner_username: GerritUsername::from("owner"),
+            instance: None,
+        })
+    }
+
+    fn project(trigger: &GerritTrigger) -> String {
+        match trigger {
+            GerritTrigger::ReviewerAdded(data) => data.project.clone(),
+            _ => panic!("Unexpected trigger {:?}", trigger),
+        }
+    }
+
+    #[test]
+    fn returns_spooled_triggers_in_order() {
+        let dir = std::env::temp_dir().join(format!("chtbtr-{}", uuid::Uuid::new_v4()));
+        let spool = TriggerSpool::new(dir.to_str().unwrap());
+        assert!(spool.pending().is_empty());
+
+        let first = spool.push("first-id", &trigger("first")).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        spool.push("second-id", &trigger("second")).unwrap();
+        fs::write(dir.join("broken.json"), "{").unwrap();
+        fs::write(dir.join(".unfinished.json.tmp"), "{").unwrap();
+
+        let pending = spool.pending();
+        let projects: Vec<String> = pending.iter().map(|(_, t)| project(t)).collect();
+        assert_eq!(projects, vec!["first", "second"]);
+        assert_eq!(TriggerSpool::id(&first).as_deref(), Some("first-id"));
+        assert!(dir.join("broken.invalid").exists());
+
+        spool.remove(&pending[0].0).unwrap();
+        assert_eq!(spool.pending().len(), 1);
+
+        spool.reject(&pending[1].0).unwrap();
+        assert!(spool.pending().is_empty());
+        assert!(pending[1].0.with_extension("invalid").exists());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
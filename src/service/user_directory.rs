@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs;
+use std::path::Path;
+
+use crate::types::{GerritUsername, ProfileId, Synchronization};
+
+/// What the directory knows about a user.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DirectoryEntry {
+    // The user is mapped to this profile, Just isn't asked
+    ProfileId(ProfileId),
+    // Just is searched for this email instead of the one known to Gerrit
+    Email(String),
+}
+
+impl TryFrom<&str> for DirectoryEntry {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, String> {
+        let value = value.trim();
+        if value.contains('@') {
+            return Ok(DirectoryEntry::Email(value.to_string()));
+        }
+
+        ProfileId::try_from(value)
+            .map(DirectoryEntry::ProfileId)
+            .map_err(|_| format!("'{}' is neither 'PROFILE,n' nor an email.", value))
+    }
+}
+
+/// A static mapping of Gerrit usernames to profiles, maintained by an admin
+/// for installations where searching Just isn't reliable.
+///
+/// The file is either CSV with the columns `username,profile`, or RON or JSON
+/// with a map of username to profile. A profile is either `PROFILE,n` or an
+/// email. The format is chosen by the file extension.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UserDirectory {
+    entries: HashMap<GerritUsername, DirectoryEntry>,
+}
+
+impl UserDirectory {
+    pub fn load(path: &Path) -> Result<UserDirectory, String> {
+        let content = fs::read_to_string(path).map_err(|e| {
+            format!(
+                "Couldn't read user directory {}. Cause: {}.",
+                path.display(),
+                e
+            )
+        })?;
+
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        UserDirectory::parse(&content, &extension)
+            .map_err(|e| format!("Invalid user directory {}: {}", path.display(), e))
+    }
+
+    /// Parses the content of a directory file, `format` is one of `csv`, `ron`
+    /// or `json`.
+    pub fn parse(content: &str, format: &str) -> Result<UserDirectory, String> {
+        let pairs: Vec<(String, String)> = match format {
+            "csv" => parse_csv(content)?,
+            "ron" => ron::de::from_str::<HashMap<String, String>>(content)
+                .map_err(|e| e.to_string())?
+                .into_iter()
+                .collect(),
+            "json" => serde_json::from_str::<HashMap<String, String>>(content)
+                .map_err(|e| e.to_string())?
+                .into_iter()
+                .collect(),
+            _ => {
+                return Err(format!(
+                    "Unknown format '{}'. Use csv, ron or json.",
+                    format
+                ))
+            }
+        };
+
+        UserDirectory::from_pairs(pairs)
+    }
+
+    /// Fails if a user is listed twice with different profiles, or a
+    /// `ProfileId` is given to several users.
+    fn from_pairs(pairs: Vec<(String, String)>) -> Result<UserDirectory, String> {
+        let mut entries = HashMap::new();
+        let mut owners: HashMap<u32, GerritUsername> = HashMap::new();
+        for (username, value) in pairs {
+            let username = GerritUsername::from(username.trim());
+            let entry = DirectoryEntry::try_from(value.as_str())
+                .map_err(|e| format!("Entry of '{}': {}", username, e))?;
+
+            if let DirectoryEntry::ProfileId(profile_id) = &entry {
+                if let Some(owner) = owners.insert(profile_id.0, username.clone()) {
+                    if owner != username {
+                        return Err(format!(
+                            "{} is given to both '{}' and '{}'.",
+                            profile_id, owner, username
+                        ));
+                    }
+                }
+            }
+
+            match entries.insert(username.clone(), entry.clone()) {
+                Some(previous) if previous != entry => {
+                    return Err(format!("'{}' is listed with different profiles.", username))
+                }
+                _ => {}
+            }
+        }
+
+        Ok(UserDirectory { entries })
+    }
+
+    pub fn get(&self, username: &GerritUsername) -> Option<&DirectoryEntry> {
+        self.entries.get(username)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// All entries, sorted by username.
+    pub fn entries(&self) -> Vec<(&GerritUsername, &DirectoryEntry)> {
+        let mut entries: Vec<(&GerritUsername, &DirectoryEntry)> = self.entries.iter().collect();
+        entries.sort_by(|a, b| a.0 .0.cmp(&b.0 .0));
+        entries
+    }
+
+    /// Reverse lookup of a `ProfileId` in the directory.
+    pub fn username_for(&self, profile_id: &ProfileId) -> Option<GerritUsername> {
+        self.entries
+            .iter()
+            .find(|(_, entry)| *entry == &DirectoryEntry::ProfileId(profile_id.clone()))
+            .map(|(username, _)| username.clone())
+    }
+
+    /// Describes every user that is mapped to a different `ProfileId` than
+    /// the directory says. The directory takes precedence.
+    pub fn conflicts(
+        &self,
+        mappings: &HashMap<GerritUsername, Synchronization<ProfileId>>,
+    ) -> Vec<String> {
+        self.entries()
+            .into_iter()
+            .filter_map(|(username, entry)| match (entry, mappings.get(username)) {
+                (DirectoryEntry::ProfileId(expected), Some(Synchronization::Some(actual)))
+                    if expected != actual =>
+                {
+                    Some(format!(
+                        "'{}' is mapped to {}, but the directory says {}.",
+                        username, actual, expected
+                    ))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Reads `username,profile` lines. Empty lines, lines starting with `#` and a
+/// header line starting with `username` are skipped.
+fn parse_csv(content: &str) -> Result<Vec<(String, String)>, String> {
+    let mut pairs = vec![];
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || (number == 0 && line.starts_with("username"))
+        {
+            continue;
+        }
+
+        let columns: Vec<&str> = line
+            .split(',')
+            .map(|column| column.trim().trim_matches('"'))
+            .collect();
+        match columns.as_slice() {
+            [username, value] => pairs.push((username.to_string(), value.to_string())),
+            // PROFILE,n contains a comma itself
+            [username, "PROFILE", id] => {
+                pairs.push((username.to_string(), format!("PROFILE,{}", id)))
+            }
+            _ => {
+                return Err(format!(
+                    "line {}: Expected 'username,profile', got '{}'.",
+                    number + 1,
+                    line
+                ))
+            }
+        }
+    }
+
+    Ok(pairs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DirectoryEntry, UserDirectory};
+    use crate::types::{GerritUsername, ProfileId, Synchronization};
+    use std::collections::HashMap;
+
+    #[test]
+    fn parses_all_formats() {
+        let csv = "username,profile\n# Comment\nuser.a,PROFILE,1\nuser.b,\"b@example.com\"\n";
+        let ron = r#"{"user.a": "PROFILE,1", "user.b": "b@example.com"}"#;
+        let json = r#"{"user.a": "PROFILE,1", "user.b": "b@example.com"}"#;
+
+        let expected = UserDirectory::parse(csv, "csv").unwrap();
+        assert_eq!(expected.len(), 2);
+        assert_eq!(
+            expected.get(&GerritUsername::from("user.a")),
+            Some(&DirectoryEntry::ProfileId(ProfileId(1)))
+        );
+        assert_eq!(
+            expected.get(&GerritUsername::from("user.b")),
+            Some(&DirectoryEntry::Email(String::from("b@example.com")))
+        );
+        assert_eq!(UserDirectory::parse(ron, "ron"), Ok(expected.clone()));
+        assert_eq!(UserDirectory::parse(json, "json"), Ok(expected));
+    }
+
+    #[test]
+    fn rejects_conflicting_entries() {
+        assert!(UserDirectory::parse("user.a,PROFILE,1\nuser.a,PROFILE,2", "csv").is_err());
+        assert!(UserDirectory::parse("user.a,PROFILE,1\nuser.b,PROFILE,1", "csv").is_err());
+        assert!(UserDirectory::parse("user.a,PROFILE,1\nuser.a,PROFILE,1", "csv").is_ok());
+        assert!(UserDirectory::parse("user.a,someone", "csv").is_err());
+        assert!(UserDirectory::parse("user.a", "csv").is_err());
+        assert!(UserDirectory::parse("", "xml").is_err());
+    }
+
+    #[test]
+    fn reports_conflicts_with_mappings() {
+        let directory = UserDirectory::parse("user.a,PROFILE,1\nuser.b,PROFILE,2", "csv").unwrap();
+        let mut mappings = HashMap::new();
+        mappings.insert(
+            GerritUsername::from("user.a"),
+            Synchronization::Some(ProfileId(3)),
+        );
+        mappings.insert(
+            GerritUsername::from("user.b"),
+            Synchronization::Some(ProfileId(2)),
+        );
+
+        assert_eq!(directory.conflicts(&mappings).len(), 1);
+        assert_eq!(
+            directory.username_for(&ProfileId(2)),
+            Some(GerritUsername::from("user.b"))
+        );
+    }
+}
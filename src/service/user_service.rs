@@ -1,30 +1,115 @@
 use crate::{
     default::DEFAULT_SETTINGS,
-    types::{GerritUsername, PathToUserData, ProfileId, Settings, Synchronization},
+    types::{GerritUsername, PathToUserData, ProfileId, Settings, SyncRecord, Synchronization},
 };
-use ron;
+use chrono::{DateTime, Utc};
+use fs2::FileExt;
+use ron::{self, ser::PrettyConfig};
 use std::collections::HashMap;
+use std::fmt;
 use std::fs;
+use std::io::Write;
 use std::path::Path;
 
-pub trait UserService {
+/// Reasons why the settings of a user couldn't be loaded.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SettingsError {
+    /// The settings file couldn't be read or written.
+    Io(String),
+
+    /// The settings file isn't valid RON or doesn't match the settings schema.
+    /// The message contains the position of the error, if available.
+    Invalid(String),
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SettingsError::Io(message) => write!(f, "Couldn't access settings: {}", message),
+            SettingsError::Invalid(message) => write!(f, "Invalid settings: {}", message),
+        }
+    }
+}
+
+impl From<ron::de::Error> for SettingsError {
+    fn from(err: ron::de::Error) -> SettingsError {
+        match &err {
+            ron::de::Error::Parser(_, position) => {
+                let prefix = format!("{}: ", position);
+                let message = err.to_string();
+                SettingsError::Invalid(format!(
+                    "line {}, column {}: {}",
+                    position.line,
+                    position.col,
+                    message.trim_start_matches(&prefix)
+                ))
+            }
+            _ => SettingsError::Invalid(err.to_string()),
+        }
+    }
+}
+
+/// Writes the content to a temporary file, flushes it to disk and moves it over
+/// the target. A crash leaves either the old or the new file, never a
+/// partially written one.
+fn write_atomically(path: &Path, temporary: &Path, content: &str) -> std::io::Result<()> {
+    let parent = path
+        .parent()
+        .expect("No parent directory for path to user data.");
+    fs::create_dir_all(parent)?;
+
+    let mut file = fs::File::create(temporary)?;
+    file.write_all(content.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(temporary, path)?;
+
+    // The rename is only durable once the directory is flushed as well.
+    fs::File::open(parent)?.sync_all()
+}
+
+pub trait UserService: fmt::Debug {
+    /**
+     * Load a map of Gerrit username to available Synchronization data. Sync
+     * files of an older format are migrated on the way.
+     */
+    fn load_sync_cache(&self) -> HashMap<GerritUsername, SyncRecord>;
+    fn load_settings(&self, user: &GerritUsername) -> Result<Settings, SettingsError>;
+    /**
+     * Read and validate the settings of a user as they are stored. Nothing is
+     * written: outdated settings aren't migrated and users without settings
+     * get `None` instead of default settings.
+     */
+    fn read_settings(&self, user: &GerritUsername) -> Result<Option<Settings>, SettingsError>;
+    /**
+     * Find the users whose stored settings watch the change of the project.
+     * Like `read_settings`, nothing is written. Invalid settings watch nothing.
+     */
+    fn watchers(&self, project: &str, change: &str) -> Result<Vec<GerritUsername>, String>;
+    /**
+     * Replace the settings of a user. The file is replaced atomically, so a
+     * reader never sees a partially written file. Comments in the file are lost.
+     */
+    fn save_settings(
+        &self,
+        user: &GerritUsername,
+        settings: &Settings,
+    ) -> Result<(), SettingsError>;
     /**
-     * Load a map of Gerrit username to available Synchronization data.
+     * Load the settings of a user, change them and save the result. Nobody
+     * else, e.g. `chtbtr-admin`, writes the settings in between.
      */
-    fn load_sync_cache(&self) -> HashMap<GerritUsername, Synchronization<ProfileId>>;
-    fn load_settings(&self, user: &GerritUsername) -> Result<Settings, String>;
-    // fn save_settings(&self, user: &GerritUsername, settings: &Settings) -> Result<(), &str>;
+    fn update_settings(
+        &self,
+        user: &GerritUsername,
+        update: &dyn Fn(Settings) -> Settings,
+    ) -> Result<Settings, SettingsError>;
     // fn load_sync(&self, user: &GerritUsername);
     fn save_sync(
         &self,
         user: &GerritUsername,
         profile_id: &ProfileId,
     ) -> Result<Synchronization<ProfileId>, String>;
-    fn set_sync(
-        &self,
-        user: &GerritUsername,
-        profile_id: &Synchronization<ProfileId>,
-    ) -> Result<(), String>;
+    fn set_sync(&self, user: &GerritUsername, record: &SyncRecord) -> Result<(), String>;
 }
 
 #[derive(Clone, Debug)]
@@ -33,9 +118,112 @@ pub struct FileBackedUserService {
 }
 
 impl FileBackedUserService {
-    fn synchronization_from_path(&self, path: &Path) -> Option<Synchronization<ProfileId>> {
+    /// Locks the files of a user until the returned file is dropped. Blocks
+    /// while another thread or process, e.g. `chtbtr-admin`, holds the lock.
+    /// The lock isn't reentrant, so only public operations take it.
+    fn lock(&self, user: &GerritUsername) -> std::io::Result<fs::File> {
+        let path = PathToUserData::lock(&self.data_dir, user);
+        fs::create_dir_all(
+            path.as_path()
+                .parent()
+                .expect("No parent directory for path to lock file."),
+        )?;
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(&path)?;
+        file.lock_exclusive()?;
+        Ok(file)
+    }
+
+    fn lock_or_err(&self, user: &GerritUsername) -> Result<fs::File, String> {
+        self.lock(user)
+            .map_err(|e| format!("Couldn't lock the files of {}. Cause: {}.", user, e))
+    }
+
+    fn lock_settings(&self, user: &GerritUsername) -> Result<fs::File, SettingsError> {
+        self.lock_or_err(user).map_err(SettingsError::Io)
+    }
+
+    /// Loads the settings of a user, writes default settings for a new user
+    /// and migrates outdated ones. The caller has to hold the lock.
+    fn load_settings_locked(&self, user: &GerritUsername) -> Result<Settings, SettingsError> {
+        let path = PathToUserData::settings(&self.data_dir, user);
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let temporary = PathToUserData::settings_temporary(&self.data_dir, user);
+                write_atomically(path.as_path(), temporary.as_path(), DEFAULT_SETTINGS).map_err(
+                    |e| {
+                        SettingsError::Io(format!(
+                            "Error writing default settings to {}. Cause: {}.",
+                            path.as_path().display(),
+                            e
+                        ))
+                    },
+                )?;
+                String::from(DEFAULT_SETTINGS)
+            }
+            Err(e) => {
+                error!("Error reading settings for {}. Cause: {}.", user, e);
+                return Err(SettingsError::Io(format!(
+                    "Error reading settings for {}. Cause: {}.",
+                    user, e
+                )));
+            }
+        };
+
+        let settings: Settings = match ron::de::from_str(&content) {
+            Ok(settings) => settings,
+            Err(e) => {
+                error!("Error deserializing settings for {}. Cause: {}.", user, e);
+                return Err(e.into());
+            }
+        };
+
+        if settings.is_current() {
+            return Ok(settings);
+        }
+
+        // A failed rewrite is not fatal, we migrate again on the next load.
+        let original_version = settings.version();
+        let settings = settings.migrate();
+        if let Err(e) = self.write_migrated_settings(user, &content, original_version, &settings) {
+            warn!(
+                "Couldn't write migrated settings for {}. Cause: {}",
+                user, e
+            );
+        }
+
+        Ok(settings)
+    }
+
+    /// Writes the settings of a user without taking the lock.
+    fn write_settings(
+        &self,
+        user: &GerritUsername,
+        settings: &Settings,
+    ) -> Result<(), SettingsError> {
+        let serialized =
+            ron::ser::to_string_pretty(settings, PrettyConfig::default()).map_err(|e| {
+                SettingsError::Invalid(format!("Error serializing settings. Cause: {}", e))
+            })?;
+
+        let path = PathToUserData::settings(&self.data_dir, user);
+        let temporary = PathToUserData::settings_temporary(&self.data_dir, user);
+        write_atomically(path.as_path(), temporary.as_path(), &serialized).map_err(|e| {
+            SettingsError::Io(format!(
+                "Error writing settings to {}. Cause: {}.",
+                path.as_path().display(),
+                e
+            ))
+        })
+    }
+
+    fn synchronization_from_path(&self, path: &Path) -> Option<SyncRecord> {
         match fs::read_to_string(path) {
-            Ok(content) => match ron::de::from_str(&content) {
+            Ok(content) => match SyncRecord::parse(&content) {
                 Ok(s) => return Some(s),
                 Err(e) => {
                     println!("Couldn't deserialize {}. Cause: {}.", path.display(), e);
@@ -52,11 +240,244 @@ impl FileBackedUserService {
             }
         }
     }
+
+    /// Sync files of the legacy format have no time of resolution. The time the
+    /// file was last modified is used instead and the file is rewritten in the
+    /// current format.
+    fn migrate_sync(&self, user: &GerritUsername, path: &Path, record: SyncRecord) -> SyncRecord {
+        if record.resolved_at.is_some() {
+            return record;
+        }
+
+        let modified: DateTime<Utc> = fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .map(DateTime::from)
+            .unwrap_or_else(|_| Utc::now());
+        let record = SyncRecord {
+            resolved_at: Some(modified),
+            ..record
+        };
+
+        match self.set_sync(user, &record) {
+            Ok(()) => info!("Migrated sync file of {}.", user),
+            Err(e) => warn!("Couldn't migrate sync file of {}. Cause: {}", user, e),
+        }
+        record
+    }
+
+    /// Writes a backup of the original settings file and replaces the settings
+    /// file with the migrated settings. Comments of the original file are only
+    /// kept in the backup. The caller has to hold the lock.
+    fn write_migrated_settings(
+        &self,
+        user: &GerritUsername,
+        original: &str,
+        original_version: &str,
+        settings: &Settings,
+    ) -> Result<(), String> {
+        let backup = PathToUserData::settings_backup(&self.data_dir, user, original_version);
+        let temporary =
+            PathToUserData::settings_backup_temporary(&self.data_dir, user, original_version);
+        write_atomically(backup.as_path(), temporary.as_path(), original).map_err(|e| {
+            format!(
+                "Error writing settings backup to {}. Cause: {}.",
+                backup.as_path().display(),
+                e
+            )
+        })?;
+
+        self.write_settings(user, settings)
+            .map_err(|e| format!("Error writing migrated settings. Cause: {}", e))?;
+
+        info!(
+            "Migrated settings for {} from {} to {}. Backup written to {}.",
+            user,
+            original_version,
+            settings.version(),
+            backup.as_path().display()
+        );
+        Ok(())
+    }
+}
+
+/// Operations for maintaining a data directory, e.g. from the admin CLI. None
+/// of them writes default settings for unknown users.
+impl FileBackedUserService {
+    /// All users with a directory in the data directory, sorted by name.
+    pub fn list_users(&self) -> Result<Vec<GerritUsername>, String> {
+        let entries = fs::read_dir(&self.data_dir).map_err(|e| {
+            format!(
+                "Couldn't read data directory {}. Cause: {}.",
+                self.data_dir, e
+            )
+        })?;
+
+        let mut users: Vec<GerritUsername> = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().is_dir())
+            .map(|entry| GerritUsername(entry.file_name().to_string_lossy().to_string()))
+            .collect();
+        users.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(users)
+    }
+
+    /// The `ProfileId` mapping of a user as stored on disk. A missing or
+    /// unreadable file counts as `NotMappedYet`.
+    pub fn read_sync(&self, user: &GerritUsername) -> SyncRecord {
+        let path = PathToUserData::sync(&self.data_dir, user);
+        if !path.as_path().exists() {
+            return SyncRecord::new(Synchronization::NotMappedYet, None);
+        }
+
+        self.synchronization_from_path(path.as_path())
+            .unwrap_or_else(|| SyncRecord::new(Synchronization::NotMappedYet, None))
+    }
+
+    /// Removes the `ProfileId` mapping of a user, so it's resolved again.
+    pub fn clear_sync(&self, user: &GerritUsername) -> Result<(), String> {
+        let path = PathToUserData::sync(&self.data_dir, user);
+        if !path.as_path().exists() {
+            return Ok(());
+        }
+
+        let _lock = self.lock_or_err(user)?;
+        match fs::remove_file(&path) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(format!(
+                "Error removing {}. Cause: {}.",
+                path.as_path().display(),
+                e
+            )),
+        }
+    }
+
+    /// Migrates the settings file of a user to the latest version, like
+    /// loading the settings would. Returns the original version, if the file
+    /// was migrated.
+    pub fn migrate_settings(&self, user: &GerritUsername) -> Result<Option<&'static str>, String> {
+        let path = PathToUserData::settings(&self.data_dir, user);
+        if !path.as_path().exists() {
+            return Ok(None);
+        }
+
+        let _lock = self.lock_or_err(user)?;
+        let original = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(format!(
+                    "Error reading {}. Cause: {}.",
+                    path.as_path().display(),
+                    e
+                ))
+            }
+        };
+
+        let settings: Settings =
+            ron::de::from_str(&original).map_err(|e| SettingsError::from(e).to_string())?;
+        if settings.is_current() {
+            return Ok(None);
+        }
+
+        let original_version = settings.version();
+        self.write_migrated_settings(user, &original, original_version, &settings.migrate())?;
+        Ok(Some(original_version))
+    }
+
+    /// Repairs what an interrupted write may have left behind. Meant to run on
+    /// startup, before the files are read. Returns a description of every
+    /// repair.
+    ///
+    /// * A temporary settings file replaces a broken settings file, if it's
+    ///   valid. Otherwise it's removed.
+    /// * An empty settings file is removed, so default settings are written.
+    /// * A temporary sync file is removed.
+    /// * A sync file that can't be parsed is moved to `sync.ron.corrupt`, so
+    ///   the user is resolved again.
+    pub fn repair(&self) -> Result<Vec<String>, String> {
+        let mut repairs = vec![];
+        for user in self.list_users()? {
+            let _lock = self.lock_or_err(&user)?;
+            self.repair_settings(&user, &mut repairs)?;
+            self.repair_sync(&user, &mut repairs)?;
+        }
+        Ok(repairs)
+    }
+
+    fn repair_settings(
+        &self,
+        user: &GerritUsername,
+        repairs: &mut Vec<String>,
+    ) -> Result<(), String> {
+        let path = PathToUserData::settings(&self.data_dir, user);
+        let temporary = PathToUserData::settings_temporary(&self.data_dir, user);
+        let io_error =
+            |e: std::io::Error| format!("Couldn't repair settings of {}. Cause: {}.", user, e);
+
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => Some(content),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(io_error(e)),
+        };
+        let is_valid = |content: &str| ron::de::from_str::<Settings>(content).is_ok();
+
+        if temporary.as_path().exists() {
+            let unfinished = fs::read_to_string(&temporary).map_err(io_error)?;
+            if !matches!(content.as_deref(), Some(content) if is_valid(content))
+                && is_valid(&unfinished)
+            {
+                fs::rename(&temporary, &path).map_err(io_error)?;
+                repairs.push(format!(
+                    "{}: restored settings from an unfinished write",
+                    user
+                ));
+                return Ok(());
+            }
+
+            fs::remove_file(&temporary).map_err(io_error)?;
+            repairs.push(format!("{}: removed an unfinished settings write", user));
+        }
+
+        if matches!(&content, Some(content) if content.trim().is_empty()) {
+            fs::remove_file(&path).map_err(io_error)?;
+            repairs.push(format!("{}: removed an empty settings file", user));
+        }
+        Ok(())
+    }
+
+    fn repair_sync(&self, user: &GerritUsername, repairs: &mut Vec<String>) -> Result<(), String> {
+        let path = PathToUserData::sync(&self.data_dir, user);
+        let temporary = PathToUserData::sync_temporary(&self.data_dir, user);
+        let io_error =
+            |e: std::io::Error| format!("Couldn't repair sync file of {}. Cause: {}.", user, e);
+
+        if temporary.as_path().exists() {
+            fs::remove_file(&temporary).map_err(io_error)?;
+            repairs.push(format!("{}: removed an unfinished sync write", user));
+        }
+
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(io_error(e)),
+        };
+        if SyncRecord::parse(&content).is_err() {
+            let corrupt = PathToUserData::sync_corrupt(&self.data_dir, user);
+            fs::rename(&path, &corrupt).map_err(io_error)?;
+            repairs.push(format!(
+                "{}: moved the corrupt sync file to {}",
+                user,
+                corrupt.as_path().display()
+            ));
+        }
+        Ok(())
+    }
 }
 
 impl UserService for FileBackedUserService {
     // TODO use async file api
-    fn load_sync_cache(&self) -> HashMap<GerritUsername, Synchronization<ProfileId>> {
+    fn load_sync_cache(&self) -> HashMap<GerritUsername, SyncRecord> {
         let mut result = HashMap::new();
         let user_repo = match fs::read_dir(&self.data_dir) {
             Ok(dir) => dir,
@@ -88,8 +509,9 @@ impl UserService for FileBackedUserService {
                 }
             };
 
-            let optional_sync: Option<Synchronization<ProfileId>> =
-                self.synchronization_from_path(syncfile.as_path());
+            let optional_sync: Option<SyncRecord> = self
+                .synchronization_from_path(syncfile.as_path())
+                .map(|record| self.migrate_sync(&username, syncfile.as_path(), record));
             debug!(
                 "Parsing synchronization status for {}. Result {:?}.",
                 username, optional_sync
@@ -108,16 +530,12 @@ impl UserService for FileBackedUserService {
         profile_id: &ProfileId,
     ) -> Result<Synchronization<ProfileId>, String> {
         let profile_id = Synchronization::Some(profile_id.clone());
-        self.set_sync(user, &profile_id)?;
+        self.set_sync(user, &SyncRecord::new(profile_id.clone(), None))?;
         return Ok(profile_id);
     }
 
-    fn set_sync(
-        &self,
-        user: &GerritUsername,
-        profile_id: &Synchronization<ProfileId>,
-    ) -> Result<(), String> {
-        let as_str = match ron::ser::to_string(profile_id) {
+    fn set_sync(&self, user: &GerritUsername, record: &SyncRecord) -> Result<(), String> {
+        let as_str = match ron::ser::to_string(record) {
             Ok(result) => result,
             Err(e) => {
                 return Err(format!(
@@ -128,68 +546,193 @@ impl UserService for FileBackedUserService {
         };
 
         let path = PathToUserData::sync(&self.data_dir, user);
-        return fs::create_dir_all(&path.as_path().parent().expect(
-            "Expected to find a parent directory. Guess this shouldn't have happened. Fix me.",
-        ))
-        .and_then(|_| {
-            fs::write(&path, as_str)?;
-            return Ok(());
-        })
-        .or_else(|e| {
-            Err(format!(
+        let temporary = PathToUserData::sync_temporary(&self.data_dir, user);
+        let _lock = self.lock_or_err(user)?;
+        write_atomically(path.as_path(), temporary.as_path(), &as_str).map_err(|e| {
+            format!(
                 "Error writing sync data to {}. Cause: {}.",
                 path.as_path().display(),
                 e
-            ))
-        });
+            )
+        })
     }
 
-    fn load_settings(&self, user: &GerritUsername) -> Result<Settings, String> {
-        let path_to_settings = PathToUserData::settings(&self.data_dir, user);
-        let file: Result<String, std::io::Error> = fs::read_to_string(&path_to_settings);
+    fn save_settings(
+        &self,
+        user: &GerritUsername,
+        settings: &Settings,
+    ) -> Result<(), SettingsError> {
+        let _lock = self.lock_settings(user)?;
+        self.write_settings(user, settings)
+    }
 
-        let settings_as_str: String = match file {
-            Ok(result) => result,
-            Err(e) => match e.kind() {
-                std::io::ErrorKind::NotFound => {
-                    fs::create_dir_all(
-                        &path_to_settings
-                            .as_path()
-                            .parent()
-                            .expect("No parent directory for path to  settings file."),
-                    )
-                    .expect("Couldn't create directories for settings file.");
-                    fs::write(&path_to_settings, DEFAULT_SETTINGS)
-                        .expect("Couldn't write default file.");
-                    String::from(DEFAULT_SETTINGS)
-                }
-                _ => {
-                    error!("Error reading settings for {}. Cause: {}.", user, e);
-                    return Err(format!(
-                        "Error reading settings for {}. Cause: {}.",
-                        user, e
-                    ));
-                }
-            },
-        };
+    fn update_settings(
+        &self,
+        user: &GerritUsername,
+        update: &dyn Fn(Settings) -> Settings,
+    ) -> Result<Settings, SettingsError> {
+        let _lock = self.lock_settings(user)?;
+        let settings = update(self.load_settings_locked(user)?);
+        self.write_settings(user, &settings)?;
+        Ok(settings)
+    }
 
-        match ron::de::from_str(&settings_as_str) {
-            Ok(settings) => Ok(settings),
-            Err(e) => {
-                error!("Error deserializing settings for {}. Cause: {}.", user, e);
-                Err(format!(
-                    "Error deserializing settings for {}. Cause: {}.",
-                    user, e
-                ))
+    /// Reads and validates the settings file of a user as it is. Returns
+    /// `None` if the user has no settings file.
+    fn read_settings(&self, user: &GerritUsername) -> Result<Option<Settings>, SettingsError> {
+        let path = PathToUserData::settings(&self.data_dir, user);
+        match fs::read_to_string(&path) {
+            Ok(content) => Ok(Some(ron::de::from_str(&content)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(SettingsError::Io(format!(
+                "Error reading {}. Cause: {}.",
+                path.as_path().display(),
+                e
+            ))),
+        }
+    }
+
+    fn load_settings(&self, user: &GerritUsername) -> Result<Settings, SettingsError> {
+        let _lock = self.lock_settings(user)?;
+        self.load_settings_locked(user)
+    }
+
+    fn watchers(&self, project: &str, change: &str) -> Result<Vec<GerritUsername>, String> {
+        let mut watchers = vec![];
+        for user in self.list_users()? {
+            match self.read_settings(&user) {
+                Ok(Some(settings)) if settings.watches(project, change) => watchers.push(user),
+                Ok(_) => {}
+                Err(e) => debug!("Skipping settings of {} for watches. Cause: {}", user, e),
             }
         }
+        Ok(watchers)
+    }
+}
+
+/// Checks every `UserService` implementation has to pass, so the storage
+/// backends behave the same.
+#[cfg(test)]
+pub mod suite {
+    use super::UserService;
+    use crate::{
+        default::default_settings,
+        types::{
+            ChatCommand, GerritUsername, ProfileId, ProjectName, ResolutionStrategy, Settings,
+            SyncRecord, Synchronization, Watch,
+        },
+    };
+    use chrono::Utc;
+
+    pub fn run(service: &dyn UserService) {
+        loads_default_settings_for_new_users(service);
+        reads_settings_without_writing(service);
+        saves_settings(service);
+        updates_settings(service);
+        finds_watchers(service);
+        saves_sync_records(service);
+    }
+
+    fn loads_default_settings_for_new_users(service: &dyn UserService) {
+        let user = GerritUsername::from("suite.new");
+        assert_eq!(service.load_settings(&user), Ok(default_settings()));
+        assert_eq!(service.load_settings(&user), Ok(default_settings()));
+    }
+
+    fn reads_settings_without_writing(service: &dyn UserService) {
+        let user = GerritUsername::from("suite.read");
+        assert_eq!(service.read_settings(&user), Ok(None));
+        assert_eq!(service.read_settings(&user), Ok(None));
+
+        service.load_settings(&user).unwrap();
+        assert_eq!(service.read_settings(&user), Ok(Some(default_settings())));
+    }
+
+    fn saves_settings(service: &dyn UserService) {
+        let user = GerritUsername::from("suite.settings");
+        let settings = ChatCommand::IgnoreProject(ProjectName::from("playground"))
+            .apply(default_settings(), Utc::now());
+        assert_ne!(settings, default_settings());
+
+        service.save_settings(&user, &settings).unwrap();
+        assert_eq!(service.load_settings(&user), Ok(settings));
+    }
+
+    fn updates_settings(service: &dyn UserService) {
+        let user = GerritUsername::from("suite.update");
+        let command = ChatCommand::IgnoreProject(ProjectName::from("playground"));
+        let expected = command.apply(default_settings(), Utc::now());
+
+        let updated = service
+            .update_settings(&user, &|settings| command.apply(settings, Utc::now()))
+            .unwrap();
+
+        assert_eq!(updated, expected);
+        assert_eq!(service.read_settings(&user), Ok(Some(expected)));
+    }
+
+    fn finds_watchers(service: &dyn UserService) {
+        let project = GerritUsername::from("suite.watch.project");
+        let change = GerritUsername::from("suite.watch.change");
+        let watch = |watch: &str| {
+            let mut settings = default_settings();
+            if let Settings::V2 { watches, .. } = &mut settings {
+                watches.push(Watch::from(watch));
+            }
+            settings
+        };
+        service.save_settings(&project, &watch("juco")).unwrap();
+        service
+            .save_settings(&change, &watch("juco/+/1234"))
+            .unwrap();
+
+        assert_eq!(
+            service.watchers("juco", "1234"),
+            Ok(vec![change, project.clone()])
+        );
+        assert_eq!(service.watchers("juco", "1235"), Ok(vec![project]));
+        assert_eq!(service.watchers("tools", "1234"), Ok(vec![]));
+    }
+
+    fn saves_sync_records(service: &dyn UserService) {
+        let user_a = GerritUsername::from("suite.a");
+        let user_b = GerritUsername::from("suite.b");
+        let user_c = GerritUsername::from("suite.c");
+        let record_a = SyncRecord::new(
+            Synchronization::Some(ProfileId(1)),
+            Some(ResolutionStrategy::Email),
+        );
+        let record_b = SyncRecord::new(Synchronization::None, None);
+
+        service.set_sync(&user_a, &record_a).unwrap();
+        service.set_sync(&user_b, &record_b).unwrap();
+        assert_eq!(
+            service.save_sync(&user_c, &ProfileId(3)),
+            Ok(Synchronization::Some(ProfileId(3)))
+        );
+
+        let cache = service.load_sync_cache();
+        assert_eq!(cache.get(&user_a), Some(&record_a));
+        assert_eq!(cache.get(&user_b), Some(&record_b));
+        assert_eq!(
+            cache.get(&user_c).map(|record| &record.mapping),
+            Some(&Synchronization::Some(ProfileId(3)))
+        );
+
+        let record_a = SyncRecord::new(
+            Synchronization::Some(ProfileId(2)),
+            Some(ResolutionStrategy::Manual),
+        );
+        service.set_sync(&user_a, &record_a).unwrap();
+        assert_eq!(service.load_sync_cache().get(&user_a), Some(&record_a));
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::types::ProfileId;
+    use crate::types::{OwnerSettings, ProfileId, ProjectName, ReviewerSettings};
+    use std::path::PathBuf;
 
     fn assert_synchronization(
         actual: &Synchronization<ProfileId>,
@@ -200,12 +743,17 @@ mod tests {
 
     #[test]
     fn test_load_sync_cache() {
-        let data_dir = "tests/user2/load_sync_cache";
+        // Loading migrates the legacy sync files of the test data
+        let data_dir = copy_to_temp_dir("tests/user2/load_sync_cache");
 
         let user_service = FileBackedUserService {
-            data_dir: String::from(data_dir),
+            data_dir: data_dir.to_string_lossy().to_string(),
         };
-        let actual = user_service.load_sync_cache();
+        let actual: HashMap<GerritUsername, Synchronization<ProfileId>> = user_service
+            .load_sync_cache()
+            .into_iter()
+            .map(|(username, record)| (username, record.mapping))
+            .collect();
 
         let user_a = (
             GerritUsername::from("user.a"),
@@ -235,5 +783,255 @@ mod tests {
             actual.get(&user_no_settings.0).unwrap(),
             &user_no_settings.1,
         );
+
+        fs::remove_dir_all(data_dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_sync_cache_migrates_legacy_files() {
+        let data_dir = copy_to_temp_dir("tests/user2/load_sync_cache");
+        let user_service = FileBackedUserService {
+            data_dir: data_dir.to_string_lossy().to_string(),
+        };
+        let user = GerritUsername::from("user.no_profile_id");
+        assert_eq!(user_service.read_sync(&user).resolved_at, None);
+
+        let record = user_service.load_sync_cache().remove(&user).unwrap();
+
+        assert!(record.resolved_at.is_some());
+        assert_eq!(user_service.read_sync(&user), record);
+        assert_eq!(user_service.load_sync_cache().remove(&user), Some(record));
+
+        fs::remove_dir_all(data_dir).unwrap();
+    }
+
+    #[test]
+    fn passes_user_service_suite() {
+        let data_dir = std::env::temp_dir().join(format!("chtbtr-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&data_dir).unwrap();
+
+        suite::run(&FileBackedUserService {
+            data_dir: data_dir.to_string_lossy().to_string(),
+        });
+
+        fs::remove_dir_all(data_dir).unwrap();
+    }
+
+    /// Copies a directory of test data into a fresh temporary directory, so
+    /// tests can modify it.
+    fn copy_to_temp_dir(data_dir: &str) -> PathBuf {
+        let target = std::env::temp_dir().join(format!("chtbtr-{}", uuid::Uuid::new_v4()));
+        for user in fs::read_dir(data_dir).unwrap() {
+            let user = user.unwrap().path();
+            let user_target = target.join(user.file_name().unwrap());
+            fs::create_dir_all(&user_target).unwrap();
+            for file in fs::read_dir(&user).unwrap() {
+                let file = file.unwrap().path();
+                fs::copy(&file, user_target.join(file.file_name().unwrap())).unwrap();
+            }
+        }
+        target
+    }
+
+    #[test]
+    fn test_load_settings_migrates_v1() {
+        let data_dir = copy_to_temp_dir("tests/user2/migrate_settings");
+        let user_service = FileBackedUserService {
+            data_dir: String::from(data_dir.to_str().unwrap()),
+        };
+        let user = GerritUsername::from("user.v1");
+        let original = fs::read_to_string(data_dir.join("user.v1/settings.ron")).unwrap();
+
+        let settings = user_service.load_settings(&user).unwrap();
+
+        assert!(settings.is_current());
+        let as_owner: OwnerSettings = settings.clone().into();
+        assert!(as_owner.subscribe_comment);
+        assert_eq!(as_owner.ignore_projects, vec![ProjectName::from("juco")]);
+        let as_reviewer: ReviewerSettings = settings.clone().into();
+        assert!(as_reviewer.subscribe);
+
+        // The original file is kept as backup, the settings file is rewritten.
+        let backup = fs::read_to_string(data_dir.join("user.v1/settings.ron.v1.bak")).unwrap();
+        assert_eq!(backup, original);
+        let rewritten = fs::read_to_string(data_dir.join("user.v1/settings.ron")).unwrap();
+        let rewritten: Settings = ron::de::from_str(&rewritten).unwrap();
+        assert_eq!(rewritten, settings);
+
+        // Loading again uses the migrated file.
+        assert_eq!(user_service.load_settings(&user).unwrap(), settings);
+
+        fs::remove_dir_all(data_dir).unwrap();
+    }
+
+    #[test]
+    fn test_save_settings() {
+        let data_dir = std::env::temp_dir().join(format!("chtbtr-{}", uuid::Uuid::new_v4()));
+        let user_service = FileBackedUserService {
+            data_dir: String::from(data_dir.to_str().unwrap()),
+        };
+        let user = GerritUsername::from("new.user");
+        let settings = crate::default::default_settings();
+
+        user_service.save_settings(&user, &settings).unwrap();
+
+        assert_eq!(user_service.load_settings(&user).unwrap(), settings);
+        assert!(!data_dir.join("new.user/settings.ron.tmp").exists());
+
+        fs::remove_dir_all(data_dir).unwrap();
+    }
+
+    #[test]
+    fn test_repair() {
+        let data_dir = copy_to_temp_dir("tests/user2/load_sync_cache");
+        let user_service = FileBackedUserService {
+            data_dir: String::from(data_dir.to_str().unwrap()),
+        };
+        let settings = ron::ser::to_string(&crate::default::default_settings()).unwrap();
+        fs::write(data_dir.join("user.a/settings.ron"), "Settings(").unwrap();
+        fs::write(data_dir.join("user.a/settings.ron.tmp"), &settings).unwrap();
+        fs::write(data_dir.join("user.b/settings.ron"), "").unwrap();
+        fs::write(data_dir.join("user.b/sync.ron"), "(mapping: Some(").unwrap();
+        fs::write(data_dir.join("user.no_settings/sync.ron.tmp"), "").unwrap();
+
+        let repairs = user_service.repair().unwrap();
+
+        assert_eq!(repairs.len(), 4);
+        assert_eq!(
+            user_service.read_settings(&GerritUsername::from("user.a")),
+            Ok(Some(crate::default::default_settings()))
+        );
+        assert!(!data_dir.join("user.a/settings.ron.tmp").exists());
+        assert!(!data_dir.join("user.b/settings.ron").exists());
+        assert!(data_dir.join("user.b/sync.ron.corrupt").exists());
+        assert_eq!(
+            user_service
+                .read_sync(&GerritUsername::from("user.b"))
+                .mapping,
+            Synchronization::NotMappedYet
+        );
+        assert!(!data_dir.join("user.no_settings/sync.ron.tmp").exists());
+
+        // Nothing left to repair.
+        assert_eq!(user_service.repair(), Ok(vec![]));
+
+        fs::remove_dir_all(data_dir).unwrap();
+    }
+
+    #[test]
+    fn test_concurrent_writes_leave_a_valid_file() {
+        let data_dir = std::env::temp_dir().join(format!("chtbtr-{}", uuid::Uuid::new_v4()));
+        let user = GerritUsername::from("concurrent.user");
+
+        let writers: Vec<_> = (0..8)
+            .map(|n| {
+                let user_service = FileBackedUserService {
+                    data_dir: String::from(data_dir.to_str().unwrap()),
+                };
+                let user = user.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..20 {
+                        user_service.save_sync(&user, &ProfileId(n)).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for writer in writers {
+            writer.join().unwrap();
+        }
+
+        let user_service = FileBackedUserService {
+            data_dir: String::from(data_dir.to_str().unwrap()),
+        };
+        assert!(matches!(
+            user_service.read_sync(&user).mapping,
+            Synchronization::Some(_)
+        ));
+        assert!(!data_dir.join("concurrent.user/sync.ron.tmp").exists());
+
+        fs::remove_dir_all(data_dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_settings_reports_position_of_syntax_error() {
+        let data_dir = copy_to_temp_dir("tests/user2/invalid_settings");
+        let user_service = FileBackedUserService {
+            data_dir: String::from(data_dir.to_str().unwrap()),
+        };
+
+        let result = user_service.load_settings(&GerritUsername::from("user.syntax_error"));
+
+        assert_eq!(
+            result.unwrap_err(),
+            SettingsError::Invalid(String::from("line 3, column 20: Expected boolean"))
+        );
+        fs::remove_dir_all(data_dir).unwrap();
+    }
+
+    #[test]
+    fn test_update_settings_keeps_invalid_settings() {
+        let data_dir = copy_to_temp_dir("tests/user2/invalid_settings");
+        let user_service = FileBackedUserService {
+            data_dir: String::from(data_dir.to_str().unwrap()),
+        };
+        let user = GerritUsername::from("user.syntax_error");
+        let path = data_dir.join("user.syntax_error/settings.ron");
+        let content = fs::read_to_string(&path).unwrap();
+
+        let result = user_service.update_settings(&user, &|settings| settings);
+
+        assert!(matches!(result, Err(SettingsError::Invalid(_))));
+        assert_eq!(fs::read_to_string(&path).unwrap(), content);
+        fs::remove_dir_all(data_dir).unwrap();
+    }
+
+    #[test]
+    fn test_maintenance_of_data_dir() {
+        let data_dir = copy_to_temp_dir("tests/user2/load_sync_cache");
+        fs::create_dir_all(data_dir.join("user.v1")).unwrap();
+        fs::copy(
+            "tests/user2/migrate_settings/user.v1/settings.ron",
+            data_dir.join("user.v1/settings.ron"),
+        )
+        .unwrap();
+        let user_service = FileBackedUserService {
+            data_dir: String::from(data_dir.to_str().unwrap()),
+        };
+        let user_a = GerritUsername::from("user.a");
+        let user_v1 = GerritUsername::from("user.v1");
+
+        let users = user_service.list_users().unwrap();
+        assert_eq!(users.len(), 5);
+        assert_eq!(users[0], user_a);
+
+        // Reading doesn't write default settings for users without settings.
+        assert_eq!(user_service.read_settings(&user_a), Ok(None));
+        assert!(!data_dir.join("user.a/settings.ron").exists());
+
+        assert!(!user_service
+            .read_settings(&user_v1)
+            .unwrap()
+            .unwrap()
+            .is_current());
+        assert_eq!(user_service.migrate_settings(&user_v1), Ok(Some("v1")));
+        assert!(user_service
+            .read_settings(&user_v1)
+            .unwrap()
+            .unwrap()
+            .is_current());
+        assert_eq!(user_service.migrate_settings(&user_v1), Ok(None));
+
+        assert!(matches!(
+            user_service.read_sync(&user_a).mapping,
+            Synchronization::Some(_)
+        ));
+        user_service.clear_sync(&user_a).unwrap();
+        assert_eq!(
+            user_service.read_sync(&user_a).mapping,
+            Synchronization::NotMappedYet
+        );
+        assert_eq!(user_service.clear_sync(&user_a), Ok(()));
+
+        fs::remove_dir_all(data_dir).unwrap();
     }
 }
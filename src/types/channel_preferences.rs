@@ -0,0 +1,27 @@
+use super::Language;
+use serde::{Deserialize, Serialize};
+
+/// Defines how notifications are delivered to you.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelPreferences {
+    // Deliver notifications as direct chat message from the chatbot
+    pub direct_message: bool,
+
+    // Prefer plain text, even if the chat supports richer formatting
+    pub plain_text: bool,
+
+    // The language of notifications, missing in settings written before
+    // notifications were translated
+    #[serde(default)]
+    pub language: Language,
+}
+
+impl Default for ChannelPreferences {
+    fn default() -> Self {
+        ChannelPreferences {
+            direct_message: true,
+            plain_text: false,
+            language: Language::default(),
+        }
+    }
+}
@@ -0,0 +1,366 @@
+use chrono::{DateTime, Duration, Utc};
+use std::str::FromStr;
+
+use super::{change_number, GerritUsername, Language, ProjectName, Settings, Watch};
+
+/// A notification a user can subscribe to via chat.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Subscription {
+    Comments,
+    Verified,
+    ReadyForSubmit,
+    Submitted,
+    Reviews,
+}
+
+impl FromStr for Subscription {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "comments" | "comment" => Ok(Subscription::Comments),
+            "verified" => Ok(Subscription::Verified),
+            "ready for submit" | "ready-for-submit" | "submittable" => {
+                Ok(Subscription::ReadyForSubmit)
+            }
+            "submitted" | "submit" => Ok(Subscription::Submitted),
+            "reviews" | "reviewer" | "review" => Ok(Subscription::Reviews),
+            other => Err(format!("I don't know the notification '{}'.", other)),
+        }
+    }
+}
+
+/// A command a user sends to the chatbot to change their settings, e.g.
+/// "subscribe comments" or "mute 2h".
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChatCommand {
+    Help,
+    ShowSettings,
+    SettingsLink,
+    Subscribe(Subscription),
+    Unsubscribe(Subscription),
+    IgnoreProject(ProjectName),
+    UnignoreProject(ProjectName),
+    IgnoreUser(GerritUsername),
+    UnignoreUser(GerritUsername),
+    Watch(Watch),
+    Unwatch(Watch),
+    Mute(Duration),
+    Unmute,
+    Language(Language),
+    // Explains which events of the change with the given number notify you
+    Explain(String),
+}
+
+/// The longest mute, in minutes.
+const MAX_MUTE_MINUTES: i64 = 365 * 24 * 60;
+
+/// Parses a duration like "30m", "2h" or "1d", up to a year.
+fn parse_duration(value: &str) -> Result<Duration, String> {
+    let error = || {
+        format!(
+            "I don't understand the duration '{}'. Try something like 30m, 2h or 1d.",
+            value
+        )
+    };
+    let value = value.trim();
+    let (index, unit) = value.char_indices().last().ok_or_else(error)?;
+    let amount: i64 = value[..index].trim().parse().map_err(|_| error())?;
+    let minutes_per_unit = match unit {
+        'm' => 1,
+        'h' => 60,
+        'd' => 24 * 60,
+        _ => return Err(error()),
+    };
+    match amount.checked_mul(minutes_per_unit) {
+        Some(minutes) if (1..=MAX_MUTE_MINUTES).contains(&minutes) => {
+            Ok(Duration::minutes(minutes))
+        }
+        Some(minutes) if minutes <= 0 => Err(error()),
+        _ => Err(String::from("I can mute notifications for a year at most.")),
+    }
+}
+
+impl FromStr for ChatCommand {
+    type Err = String;
+
+    /// Commands are case insensitive, but arguments like project or user names
+    /// are taken as they are.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        let mut words = value.splitn(2, char::is_whitespace);
+        let command = words.next().unwrap_or("").to_lowercase();
+        let argument = words.next().unwrap_or("").trim();
+
+        let target = || -> Result<(String, String), String> {
+            let mut parts = argument.splitn(2, char::is_whitespace);
+            let kind = parts.next().unwrap_or("").to_lowercase();
+            let name = parts.next().unwrap_or("").trim();
+            if name.is_empty() {
+                return Err(format!("Please tell me which {} to {}.", kind, command));
+            }
+            Ok((kind, name.to_string()))
+        };
+
+        match command.as_str() {
+            "help" | "?" => Ok(ChatCommand::Help),
+            "show" | "settings" => Ok(ChatCommand::ShowSettings),
+            "link" | "web" => Ok(ChatCommand::SettingsLink),
+            "subscribe" => Ok(ChatCommand::Subscribe(argument.to_lowercase().parse()?)),
+            "unsubscribe" => Ok(ChatCommand::Unsubscribe(argument.to_lowercase().parse()?)),
+            "ignore" | "unignore" => {
+                let (kind, name) = target()?;
+                match (command.as_str(), kind.as_str()) {
+                    ("ignore", "project") => Ok(ChatCommand::IgnoreProject(ProjectName(name))),
+                    ("ignore", "user") => Ok(ChatCommand::IgnoreUser(GerritUsername(name))),
+                    ("unignore", "project") => Ok(ChatCommand::UnignoreProject(ProjectName(name))),
+                    ("unignore", "user") => Ok(ChatCommand::UnignoreUser(GerritUsername(name))),
+                    _ => Err(format!(
+                        "I can only {} a project or a user, not a '{}'.",
+                        command, kind
+                    )),
+                }
+            }
+            "watch" | "unwatch" if argument.is_empty() => Err(format!(
+                "Please tell me which project or change to {}.",
+                command
+            )),
+            "watch" => Ok(ChatCommand::Watch(Watch::from(argument))),
+            "unwatch" => Ok(ChatCommand::Unwatch(Watch::from(argument))),
+            "mute" => Ok(ChatCommand::Mute(parse_duration(argument)?)),
+            "unmute" => Ok(ChatCommand::Unmute),
+            "language" => Ok(ChatCommand::Language(argument.parse()?)),
+            "why" | "explain" => {
+                let change = change_number(argument);
+                if change.is_empty() || !change.chars().all(|c| c.is_ascii_digit()) {
+                    return Err(format!(
+                        "Please tell me the number of the change, e.g. {} 1234.",
+                        command
+                    ));
+                }
+                Ok(ChatCommand::Explain(change.to_string()))
+            }
+            _ => Err(format!("I don't know the command '{}'.", value)),
+        }
+    }
+}
+
+fn add<T: PartialEq>(list: &mut Vec<T>, value: T) {
+    if !list.contains(&value) {
+        list.push(value);
+    }
+}
+
+fn remove<T: PartialEq>(list: &mut Vec<T>, value: &T) {
+    list.retain(|v| v != value);
+}
+
+impl ChatCommand {
+    /// Returns `true` if the command changes the settings.
+    pub fn modifies_settings(&self) -> bool {
+        !matches!(
+            self,
+            ChatCommand::Help
+                | ChatCommand::ShowSettings
+                | ChatCommand::SettingsLink
+                | ChatCommand::Explain(_)
+        )
+    }
+
+    /// Applies the command to the given settings. The settings are migrated to
+    /// the latest version first. Ignored projects and users apply to you as
+    /// owner and as reviewer.
+    pub fn apply(&self, settings: Settings, now: DateTime<Utc>) -> Settings {
+        let mut settings = settings.migrate();
+        if let Settings::V2 {
+            as_reviewer,
+            as_owner,
+            watches,
+            schedule,
+            channel,
+            ..
+        } = &mut settings
+        {
+            match self {
+                ChatCommand::Help
+                | ChatCommand::ShowSettings
+                | ChatCommand::SettingsLink
+                | ChatCommand::Explain(_) => {}
+                ChatCommand::Subscribe(subscription) | ChatCommand::Unsubscribe(subscription) => {
+                    let value = matches!(self, ChatCommand::Subscribe(_));
+                    match subscription {
+                        Subscription::Comments => as_owner.subscribe_comment = value,
+                        Subscription::Verified => as_owner.subscribe_verified = value,
+                        Subscription::ReadyForSubmit => as_owner.subscribe_ready_for_submit = value,
+                        Subscription::Submitted => as_owner.subscribe_submitted = value,
+                        Subscription::Reviews => as_reviewer.subscribe = value,
+                    }
+                }
+                ChatCommand::IgnoreProject(project) => {
+                    add(&mut as_owner.ignore_projects, project.clone());
+                    add(&mut as_reviewer.ignore_projects, project.clone());
+                }
+                ChatCommand::UnignoreProject(project) => {
+                    remove(&mut as_owner.ignore_projects, project);
+                    remove(&mut as_reviewer.ignore_projects, project);
+                }
+                ChatCommand::IgnoreUser(user) => {
+                    add(&mut as_owner.ignore_by_username, user.clone());
+                    add(&mut as_reviewer.ignore_by_username, user.clone());
+                }
+                ChatCommand::UnignoreUser(user) => {
+                    remove(&mut as_owner.ignore_by_username, user);
+                    remove(&mut as_reviewer.ignore_by_username, user);
+                }
+                ChatCommand::Watch(watch) => add(watches, watch.clone()),
+                ChatCommand::Unwatch(watch) => remove(watches, watch),
+                ChatCommand::Mute(duration) => {
+                    schedule.muted_until = now.checked_add_signed(*duration)
+                }
+                ChatCommand::Unmute => schedule.muted_until = None,
+                ChatCommand::Language(language) => channel.language = *language,
+            }
+        }
+
+        settings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChatCommand, Subscription};
+    use crate::default::default_settings;
+    use crate::types::{
+        GerritUsername, Language, OwnerSettings, ProjectName, ReviewerSettings, Settings, Watch,
+    };
+    use chrono::{Duration, Utc};
+
+    fn parse(value: &str) -> ChatCommand {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_commands_case_insensitive() {
+        assert_eq!(
+            parse("Subscribe Comments"),
+            ChatCommand::Subscribe(Subscription::Comments)
+        );
+        assert_eq!(
+            parse("unsubscribe ready for submit"),
+            ChatCommand::Unsubscribe(Subscription::ReadyForSubmit)
+        );
+        assert_eq!(parse(" show settings "), ChatCommand::ShowSettings);
+        assert_eq!(parse("HELP"), ChatCommand::Help);
+        assert_eq!(parse("link"), ChatCommand::SettingsLink);
+    }
+
+    #[test]
+    fn keeps_case_of_names() {
+        assert_eq!(
+            parse("ignore project Foo"),
+            ChatCommand::IgnoreProject(ProjectName::from("Foo"))
+        );
+        assert_eq!(
+            parse("unignore user tools.Just"),
+            ChatCommand::UnignoreUser(GerritUsername::from("tools.Just"))
+        );
+        assert_eq!(parse("watch juco"), ChatCommand::Watch(Watch::from("juco")));
+        assert_eq!(
+            parse("unwatch Juco/+/1234"),
+            ChatCommand::Unwatch(Watch {
+                project: ProjectName::from("Juco"),
+                change: Some(String::from("1234")),
+            })
+        );
+    }
+
+    #[test]
+    fn parses_mute_durations() {
+        assert_eq!(parse("mute 2h"), ChatCommand::Mute(Duration::hours(2)));
+        assert_eq!(parse("mute 30m"), ChatCommand::Mute(Duration::minutes(30)));
+        assert_eq!(parse("mute 1d"), ChatCommand::Mute(Duration::days(1)));
+        assert!("mute".parse::<ChatCommand>().is_err());
+        assert!("mute 2 weeks".parse::<ChatCommand>().is_err());
+        assert!("mute 2ä".parse::<ChatCommand>().is_err());
+        assert!("mute h".parse::<ChatCommand>().is_err());
+        assert!("mute 0h".parse::<ChatCommand>().is_err());
+        assert!("mute -2h".parse::<ChatCommand>().is_err());
+        assert!("mute 400d".parse::<ChatCommand>().is_err());
+        assert!("mute 9223372036854775807d".parse::<ChatCommand>().is_err());
+    }
+
+    #[test]
+    fn parses_explain() {
+        assert_eq!(
+            parse("why 1234"),
+            ChatCommand::Explain(String::from("1234"))
+        );
+        assert_eq!(
+            parse("Explain https://gerrit/c/project/+/1234/"),
+            ChatCommand::Explain(String::from("1234"))
+        );
+        assert!(!parse("why 1234").modifies_settings());
+        assert!("why".parse::<ChatCommand>().is_err());
+        assert!("why not".parse::<ChatCommand>().is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_commands() {
+        assert!("dance".parse::<ChatCommand>().is_err());
+        assert!("subscribe everything".parse::<ChatCommand>().is_err());
+        assert!("ignore branch master".parse::<ChatCommand>().is_err());
+        assert!("ignore project".parse::<ChatCommand>().is_err());
+        assert!("language fr".parse::<ChatCommand>().is_err());
+    }
+
+    #[test]
+    fn applies_language() {
+        let settings = parse("Language DE").apply(default_settings(), Utc::now());
+
+        assert_eq!(settings.language(), Language::German);
+    }
+
+    #[test]
+    fn applies_subscriptions() {
+        let settings = parse("subscribe comments").apply(default_settings(), Utc::now());
+        let settings = parse("subscribe reviews").apply(settings, Utc::now());
+
+        let as_owner: OwnerSettings = settings.clone().into();
+        let as_reviewer: ReviewerSettings = settings.into();
+        assert!(as_owner.subscribe_comment);
+        assert!(as_reviewer.subscribe);
+    }
+
+    #[test]
+    fn applies_ignore_once_for_owner_and_reviewer() {
+        let settings = parse("ignore project foo").apply(default_settings(), Utc::now());
+        let settings = parse("ignore project foo").apply(settings, Utc::now());
+
+        let as_owner: OwnerSettings = settings.clone().into();
+        let as_reviewer: ReviewerSettings = settings.clone().into();
+        assert_eq!(as_owner.ignore_projects, vec![ProjectName::from("foo")]);
+        assert_eq!(as_reviewer.ignore_projects, vec![ProjectName::from("foo")]);
+
+        let settings = parse("unignore project foo").apply(settings, Utc::now());
+        let as_owner: OwnerSettings = settings.into();
+        assert!(as_owner.ignore_projects.is_empty());
+    }
+
+    #[test]
+    fn applies_mute() {
+        let now = Utc::now();
+        let settings = parse("mute 2h").apply(default_settings(), now);
+
+        match &settings {
+            Settings::V2 { schedule, .. } => {
+                assert_eq!(schedule.muted_until, Some(now + Duration::hours(2)))
+            }
+            _ => panic!("Settings weren't migrated."),
+        }
+
+        match parse("unmute").apply(settings, now) {
+            Settings::V2 { schedule, .. } => assert_eq!(schedule.muted_until, None),
+            _ => panic!("Settings weren't migrated."),
+        }
+    }
+}
@@ -10,6 +10,19 @@ pub enum CodeReviewStatus {
     MinusTwo,
 }
 
+impl CodeReviewStatus {
+    /// The vote as number, e.g. `2` for `PlusTwo`.
+    pub fn value(&self) -> i8 {
+        match self {
+            CodeReviewStatus::PlusTwo => 2,
+            CodeReviewStatus::PlusOne => 1,
+            CodeReviewStatus::None => 0,
+            CodeReviewStatus::MinusOne => -1,
+            CodeReviewStatus::MinusTwo => -2,
+        }
+    }
+}
+
 impl fmt::Display for CodeReviewStatus {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let result = match self {
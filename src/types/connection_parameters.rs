@@ -1,14 +1,134 @@
-use super::ProfileId;
+use super::{GerritInstance, GerritUsername, MessageFormat, ProfileId, Tenant, TenantId};
+use std::str::FromStr;
+
+/// Where user data is stored.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Storage {
+    // A directory per user with settings.ron and sync.ron
+    Files,
+    // An SQLite database in the data directory
+    Sqlite,
+}
+
+impl FromStr for Storage {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "files" => Ok(Storage::Files),
+            "sqlite" => Ok(Storage::Sqlite),
+            _ => Err(format!("Unknown storage '{}'. Use files or sqlite.", value)),
+        }
+    }
+}
 
 /// Provides various values required to run the program.
 #[derive(Clone, Debug)]
 pub struct ConnectionParameters {
     pub profile_id: ProfileId,
-    pub gerrit_domain: String,
+    // The Gerrit servers notifications are forwarded for, the first one is the
+    // default
+    pub gerrit_instances: Vec<GerritInstance>,
     pub domain: String,
     pub username: String,
     pub password: String,
     pub oauth_token: String,
     pub data_dir: String,
+    // How user data is stored in the data directory
+    pub storage: Storage,
     pub client_id: String,
+    // Seconds between polls for chat commands, 0 disables them
+    pub chat_poll_interval: u64,
+    // Signs links to the settings page, the page is disabled without it
+    pub web_secret: Option<String>,
+    // The URL users reach the server with, used to construct links
+    pub public_url: String,
+    // Bearer token for the admin API, the API is disabled without it
+    pub admin_token: Option<String>,
+    // Hours until resolving an unresolvable user is tried again, 0 never retries
+    pub unresolved_ttl: u64,
+    // Path to a file mapping usernames to profiles, consulted before Just
+    pub user_directory: Option<String>,
+    // The richest format the Just installation shows messages in
+    pub message_format: MessageFormat,
+    // Seconds without changes before changed sync files are reloaded, 0 disables reloading
+    pub reload_debounce: u64,
+    // The address and port the server listens on
+    pub bind_address: String,
+    pub port: u16,
+    // PEM files with certificate chain and private key, serves HTTPS if set
+    pub tls_cert: Option<String>,
+    pub tls_key: Option<String>,
+    // Triggers have to be signed with it, if set
+    pub trigger_secret: Option<String>,
+    // Triggers the hooks couldn't deliver, delivered on startup
+    pub spool_dir: Option<String>,
+    // Further tenants, besides the default one configured above
+    pub tenants: Vec<Tenant>,
+}
+
+impl ConnectionParameters {
+    /// The Gerrit instance with the given name, the default one for `None`.
+    pub fn gerrit_instance(&self, name: Option<&str>) -> Option<&GerritInstance> {
+        match name {
+            Some(name) => self
+                .gerrit_instances
+                .iter()
+                .find(|instance| instance.name == name),
+            None => self.gerrit_instances.first(),
+        }
+    }
+
+    /// The tenant configured by the top level options.
+    pub fn default_tenant(&self) -> Tenant {
+        Tenant {
+            id: TenantId::default_tenant(),
+            profile_id: self.profile_id.clone(),
+            domain: self.domain.clone(),
+            username: self.username.clone(),
+            password: self.password.clone(),
+            client_id: self.client_id.clone(),
+            data_dir: self.data_dir.clone(),
+            user_directory: self.user_directory.clone(),
+            message_format: self.message_format,
+            gerrit_instances: vec![],
+        }
+    }
+
+    /// Every tenant, the default one first.
+    pub fn all_tenants(&self) -> Vec<Tenant> {
+        std::iter::once(self.default_tenant())
+            .chain(self.tenants.iter().cloned())
+            .collect()
+    }
+
+    pub fn tenant(&self, id: &TenantId) -> Option<Tenant> {
+        self.all_tenants()
+            .into_iter()
+            .find(|tenant| &tenant.id == id)
+    }
+
+    /// The tenant whose users a Gerrit instance notifies.
+    pub fn tenant_for_instance(&self, instance: &GerritInstance) -> TenantId {
+        self.tenants
+            .iter()
+            .find(|tenant| tenant.gerrit_instances.contains(&instance.name))
+            .map(|tenant| tenant.id.clone())
+            .unwrap_or_else(TenantId::default_tenant)
+    }
+
+    /// The Gerrit instance of a user, found by the namespace of the username.
+    pub fn instance_of(&self, username: &GerritUsername) -> Option<&GerritInstance> {
+        let namespace = username.namespace();
+        self.gerrit_instances
+            .iter()
+            .find(|instance| instance.namespace.as_deref() == namespace)
+    }
+
+    /// The tenant a user belongs to, found by the namespace of the username.
+    pub fn tenant_of(&self, username: &GerritUsername) -> TenantId {
+        self.instance_of(username)
+            .map(|instance| self.tenant_for_instance(instance))
+            .unwrap_or_else(TenantId::default_tenant)
+    }
 }
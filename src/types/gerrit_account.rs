@@ -0,0 +1,124 @@
+use super::ResolutionStrategy;
+use std::fmt;
+
+/// A Gerrit account as Gerrit hooks describe it, e.g.
+/// `First Last <first.last@example.com>`. Both parts are optional, so parsing
+/// never fails.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GerritAccount {
+    pub name: String,
+    pub email: Option<String>,
+}
+
+impl From<&str> for GerritAccount {
+    fn from(value: &str) -> GerritAccount {
+        let value = value.trim().trim_matches('"').trim();
+        let (name, email) = match (value.find('<'), value.rfind('>')) {
+            (Some(start), Some(end)) if start < end => {
+                (&value[..start], Some(value[start + 1..end].trim()))
+            }
+            _ if value.contains('@') && !value.contains(char::is_whitespace) => ("", Some(value)),
+            _ => (value, None),
+        };
+
+        GerritAccount {
+            // Gerrit doesn't normalize whitespace in names
+            name: name.split_whitespace().collect::<Vec<&str>>().join(" "),
+            email: email.filter(|e| !e.is_empty()).map(String::from),
+        }
+    }
+}
+
+impl fmt::Display for GerritAccount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.email {
+            Some(email) if self.name.is_empty() => write!(f, "<{}>", email),
+            Some(email) => write!(f, "{} <{}>", self.name, email),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+impl GerritAccount {
+    /// Terms to search the account in Just with, most reliable first: The
+    /// email, the full name, and the first and last name if there are middle
+    /// names.
+    pub fn search_terms(&self) -> Vec<(ResolutionStrategy, String)> {
+        let mut terms = vec![];
+        if let Some(email) = &self.email {
+            terms.push((ResolutionStrategy::Email, email.clone()));
+        }
+
+        if !self.name.is_empty() {
+            terms.push((ResolutionStrategy::FullName, self.name.clone()));
+        }
+
+        let words: Vec<&str> = self.name.split(' ').collect();
+        if words.len() > 2 {
+            terms.push((
+                ResolutionStrategy::FirstAndLastName,
+                format!("{} {}", words[0], words[words.len() - 1]),
+            ));
+        }
+
+        terms
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GerritAccount;
+    use crate::types::ResolutionStrategy;
+
+    #[test]
+    fn parses_name_and_email() {
+        let account = GerritAccount::from("\"First Last <first.last@example.com>\"");
+
+        assert_eq!(account.name, "First Last");
+        assert_eq!(account.email, Some(String::from("first.last@example.com")));
+    }
+
+    #[test]
+    fn parses_accounts_without_email_or_name() {
+        assert_eq!(
+            GerritAccount::from("First  Last"),
+            GerritAccount {
+                name: String::from("First Last"),
+                email: None,
+            }
+        );
+        assert_eq!(
+            GerritAccount::from("first.last@example.com"),
+            GerritAccount {
+                name: String::new(),
+                email: Some(String::from("first.last@example.com")),
+            }
+        );
+        assert_eq!(GerritAccount::from("Broken <").name, "Broken <");
+        assert_eq!(
+            GerritAccount::from(" <first.last@example.com>").to_string(),
+            "<first.last@example.com>"
+        );
+        assert_eq!(GerritAccount::from("").search_terms(), vec![]);
+    }
+
+    #[test]
+    fn searches_email_first() {
+        let account = GerritAccount::from("Jörg Peter Müller <jpm@example.com>");
+
+        assert_eq!(
+            account.search_terms(),
+            vec![
+                (ResolutionStrategy::Email, String::from("jpm@example.com")),
+                (
+                    ResolutionStrategy::FullName,
+                    String::from("Jörg Peter Müller")
+                ),
+                (
+                    ResolutionStrategy::FirstAndLastName,
+                    String::from("Jörg Müller")
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,248 @@
+use super::{GerritTrigger, GerritUsername};
+
+/// Separates the namespace from the username in the username of a user of a
+/// namespaced instance, e.g. `legacy~jdoe`. Gerrit doesn't allow it in
+/// usernames.
+pub const NAMESPACE_SEPARATOR: char = '~';
+
+/// A Gerrit server Chtbtr forwards notifications for.
+///
+/// Users of different instances may have the same username, so every instance
+/// but the default one has a namespace. The usernames in its triggers are
+/// prefixed with it (see `GerritUsername::namespaced`), which keeps their
+/// data directories, settings and `ProfileId` mappings apart.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GerritInstance {
+    // Identifies the instance in triggers, e.g. legacy
+    pub name: String,
+    // http or https
+    pub scheme: String,
+    // The domain, optionally with a port, e.g. gerrit.example.com:8080
+    pub domain: String,
+    // The path Gerrit is served under, e.g. /r, empty if served at the root
+    pub path: String,
+    // Prefix of the usernames, None for the default instance
+    pub namespace: Option<String>,
+}
+
+impl GerritInstance {
+    /// The default instance for a domain served via HTTPS, used if only
+    /// `gerrit_domain` is configured. The domain may end with the path Gerrit
+    /// is served under, e.g. `gerrit.example.com/r`.
+    pub fn from_domain(domain: &str) -> GerritInstance {
+        let (domain, path) = split_path(domain);
+        GerritInstance {
+            name: String::from("default"),
+            scheme: String::from("https"),
+            domain: String::from(domain),
+            path,
+            namespace: None,
+        }
+    }
+
+    /// The URL Gerrit is served under, e.g. `https://gerrit.example.com/r`.
+    pub fn base_url(&self) -> String {
+        format!("{}://{}{}", self.scheme, self.domain, self.path)
+    }
+
+    /// The username of a user of this instance in Chtbtr.
+    pub fn username(&self, username: &GerritUsername) -> GerritUsername {
+        match &self.namespace {
+            Some(namespace) => GerritUsername::namespaced(namespace, &username.0),
+            None => username.clone(),
+        }
+    }
+
+    /// Replaces the usernames in a trigger of this instance with the
+    /// usernames in Chtbtr.
+    pub fn qualify(&self, trigger: GerritTrigger) -> GerritTrigger {
+        match trigger {
+            GerritTrigger::CommentAdded(mut data) => {
+                data.base.change_owner_username = self.username(&data.base.change_owner_username);
+                data.author_username = self.username(&data.author_username);
+                GerritTrigger::CommentAdded(data)
+            }
+            GerritTrigger::PatchStatusChanged(mut data) => {
+                data.base.change_owner_username = self.username(&data.base.change_owner_username);
+                data.author_username = self.username(&data.author_username);
+                GerritTrigger::PatchStatusChanged(data)
+            }
+            GerritTrigger::ReviewerAdded(mut data) => {
+                data.change_owner_username = self.username(&data.change_owner_username);
+                data.reviewer_username = self.username(&data.reviewer_username);
+                GerritTrigger::ReviewerAdded(data)
+            }
+        }
+    }
+
+    /// Parses a comma separated list of instances in the form `name=url`,
+    /// e.g. `main=https://gerrit.example.com, legacy=http://old.example.com/r`.
+    /// The first instance is the default one and has no namespace, so data of
+    /// a single instance setup stays valid. The others are namespaced by their
+    /// name.
+    pub fn parse_list(value: &str) -> Result<Vec<GerritInstance>, String> {
+        let mut instances: Vec<GerritInstance> = vec![];
+        for (index, entry) in value
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .enumerate()
+        {
+            let mut parts = entry.splitn(2, '=');
+            let name = parts.next().unwrap_or_default().trim();
+            let url = parts
+                .next()
+                .ok_or_else(|| format!("Expected 'name=url', got '{}'.", entry))?
+                .trim();
+            if name.is_empty()
+                || !name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                return Err(format!(
+                    "Invalid instance name '{}'. Use letters, digits, '-' and '_'.",
+                    name
+                ));
+            }
+            if instances.iter().any(|instance| instance.name == name) {
+                return Err(format!("Instance '{}' is listed twice.", name));
+            }
+
+            let (scheme, domain) = match url.split_once("://") {
+                Some((scheme, domain)) if scheme == "http" || scheme == "https" => (scheme, domain),
+                _ => {
+                    return Err(format!(
+                        "Invalid URL '{}' of instance '{}'. Use http:// or https://.",
+                        url, name
+                    ))
+                }
+            };
+            let (domain, path) = split_path(domain);
+            if domain.is_empty() || path.contains(['?', '#']) {
+                return Err(format!(
+                    "Invalid URL '{}' of instance '{}'. Expected a domain and optionally a path.",
+                    url, name
+                ));
+            }
+
+            instances.push(GerritInstance {
+                name: String::from(name),
+                scheme: String::from(scheme),
+                domain: String::from(domain),
+                path,
+                namespace: if index == 0 {
+                    None
+                } else {
+                    Some(String::from(name))
+                },
+            });
+        }
+
+        if instances.is_empty() {
+            return Err(String::from("Expected at least one instance."));
+        }
+        Ok(instances)
+    }
+}
+
+/// Splits `gerrit.example.com/r/` into the domain and the path without a
+/// trailing slash, `/r`.
+fn split_path(value: &str) -> (&str, String) {
+    let value = value.trim().trim_end_matches('/');
+    match value.find('/') {
+        Some(index) => (&value[..index], String::from(&value[index..])),
+        None => (value, String::new()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::GerritInstance;
+    use crate::types::{GerritTrigger, GerritUsername, ReviewerAddedData};
+
+    #[test]
+    fn parses_instance_list() {
+        let instances = GerritInstance::parse_list(
+            "main=https://gerrit.example.com/, legacy=http://old.example.com:8080/r/",
+        )
+        .unwrap();
+
+        assert_eq!(
+            instances,
+            vec![
+                GerritInstance {
+                    name: String::from("main"),
+                    scheme: String::from("https"),
+                    domain: String::from("gerrit.example.com"),
+                    path: String::new(),
+                    namespace: None,
+                },
+                GerritInstance {
+                    name: String::from("legacy"),
+                    scheme: String::from("http"),
+                    domain: String::from("old.example.com:8080"),
+                    path: String::from("/r"),
+                    namespace: Some(String::from("legacy")),
+                },
+            ]
+        );
+        assert_eq!(instances[1].base_url(), "http://old.example.com:8080/r");
+        assert_eq!(
+            GerritInstance::from_domain("gerrit.example.com/r/").base_url(),
+            "https://gerrit.example.com/r"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_instance_lists() {
+        for value in &[
+            "",
+            "main",
+            "main=gerrit.example.com",
+            "main=ftp://gerrit.example.com",
+            "main=https:///r",
+            "main=https://gerrit.example.com/r?x=1",
+            "ma~in=https://gerrit.example.com",
+            "main=https://a.example.com,main=https://b.example.com",
+        ] {
+            assert!(GerritInstance::parse_list(value).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn qualifies_usernames_of_namespaced_instances() {
+        let trigger = GerritTrigger::ReviewerAdded(ReviewerAddedData {
+            change_url: String::from("123"),
+            project: String::from("project"),
+            reviewer: String::from("Reviewer <reviewer@example.com>"),
+            reviewer_username: GerritUsername::from("reviewer"),
+            change_owner: String::from("Owner <owner@example.com>"),
+            change_owner_username: GerritUsername::from("owner"),
+            instance: Some(String::from("legacy")),
+        });
+        let instances =
+            GerritInstance::parse_list("main=https://a.example.com,legacy=https://b.example.com")
+                .unwrap();
+
+        match instances[1].qualify(trigger.clone()) {
+            GerritTrigger::ReviewerAdded(data) => {
+                assert_eq!(
+                    data.reviewer_username,
+                    GerritUsername::from("legacy~reviewer")
+                );
+                assert_eq!(
+                    data.change_owner_username,
+                    GerritUsername::from("legacy~owner")
+                );
+                assert_eq!(data.reviewer_username.namespace(), Some("legacy"));
+            }
+            other => panic!("Unexpected trigger {:?}", other),
+        }
+        match instances[0].qualify(trigger) {
+            GerritTrigger::ReviewerAdded(data) => {
+                assert_eq!(data.reviewer_username, GerritUsername::from("reviewer"));
+            }
+            other => panic!("Unexpected trigger {:?}", other),
+        }
+    }
+}
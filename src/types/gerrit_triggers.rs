@@ -2,13 +2,92 @@ use serde::{Deserialize, Serialize};
 
 use crate::types::{GerritUsername, PatchStatus, ProjectName};
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub enum GerritTrigger {
     CommentAdded(CommentAddedData),
     ReviewerAdded(ReviewerAddedData),
     PatchStatusChanged(PatchStatusChangedData),
 }
 
+impl GerritTrigger {
+    /// The name of the Gerrit instance the trigger is from, `None` for the
+    /// default instance.
+    pub fn instance(&self) -> Option<&str> {
+        let instance = match self {
+            GerritTrigger::CommentAdded(data) => &data.base.instance,
+            GerritTrigger::PatchStatusChanged(data) => &data.base.instance,
+            GerritTrigger::ReviewerAdded(data) => &data.instance,
+        };
+        instance.as_deref()
+    }
+
+    /// The type of the trigger, named like the Gerrit hook, e.g.
+    /// `comment_added`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            GerritTrigger::CommentAdded(_) => "comment_added",
+            GerritTrigger::PatchStatusChanged(_) => "patch_status_changed",
+            GerritTrigger::ReviewerAdded(_) => "reviewer_added",
+        }
+    }
+
+    pub fn change_url(&self) -> &str {
+        match self {
+            GerritTrigger::CommentAdded(data) => &data.base.change_url,
+            GerritTrigger::PatchStatusChanged(data) => &data.base.change_url,
+            GerritTrigger::ReviewerAdded(data) => &data.change_url,
+        }
+    }
+
+    pub fn project(&self) -> &str {
+        match self {
+            GerritTrigger::CommentAdded(data) => &data.base.project.0,
+            GerritTrigger::PatchStatusChanged(data) => &data.base.project.0,
+            GerritTrigger::ReviewerAdded(data) => &data.project,
+        }
+    }
+
+    /// The user who caused the trigger. Gerrit doesn't pass who added a
+    /// reviewer, the change owner is assumed.
+    pub fn author(&self) -> &GerritUsername {
+        match self {
+            GerritTrigger::CommentAdded(data) => &data.author_username,
+            GerritTrigger::PatchStatusChanged(data) => &data.author_username,
+            GerritTrigger::ReviewerAdded(data) => &data.change_owner_username,
+        }
+    }
+
+    /// The user the trigger may notify, the change owner or the new reviewer.
+    pub fn recipient(&self) -> &GerritUsername {
+        match self {
+            GerritTrigger::CommentAdded(data) => &data.base.change_owner_username,
+            GerritTrigger::PatchStatusChanged(data) => &data.base.change_owner_username,
+            GerritTrigger::ReviewerAdded(data) => &data.reviewer_username,
+        }
+    }
+
+    /// Tags the trigger with the Gerrit instance it is from.
+    pub fn with_instance(mut self, name: Option<String>) -> GerritTrigger {
+        match &mut self {
+            GerritTrigger::CommentAdded(data) => data.base.instance = name,
+            GerritTrigger::PatchStatusChanged(data) => data.base.instance = name,
+            GerritTrigger::ReviewerAdded(data) => data.instance = name,
+        }
+        self
+    }
+}
+
+/// Gerrit passes the URL of the change to hooks, older setups only the change
+/// number. Either way, the number is the last path segment.
+pub fn change_number(change_url: &str) -> &str {
+    change_url
+        .trim()
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+}
+
 // Always necessary to construct a meaningful message.
 #[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct BaseData {
@@ -16,23 +95,44 @@ pub struct BaseData {
     pub change_owner_username: GerritUsername,
     pub change_url: String,
     pub project: ProjectName,
+    // The Gerrit instance, missing in triggers of the default instance
+    #[serde(default)]
+    pub instance: Option<String>,
+    // The patch set the trigger is about, if the hook knows it
+    #[serde(default)]
+    pub patch_set: Option<u32>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct PatchStatusChangedData {
     pub base: BaseData,
     pub author_username: GerritUsername,
     pub patch_status: PatchStatus,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct CommentAddedData {
     pub base: BaseData,
     pub author: String,
     pub author_username: GerritUsername,
+    // Where an inline comment was added, None for comments on the change
+    #[serde(default)]
+    pub location: Option<CommentLocation>,
+    // The text of the comment, if the hook passed it
+    #[serde(default)]
+    pub comment: Option<String>,
+}
+
+/// The file and line of an inline comment.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct CommentLocation {
+    pub file: String,
+    // None for comments on the whole file
+    #[serde(default)]
+    pub line: Option<u32>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct ReviewerAddedData {
     pub change_owner: String,
     pub change_owner_username: GerritUsername,
@@ -40,4 +140,6 @@ pub struct ReviewerAddedData {
     pub reviewer_username: GerritUsername,
     pub change_url: String,
     pub project: String,
+    #[serde(default)]
+    pub instance: Option<String>,
 }
@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+
+/// Subscribe to votes on a Gerrit label on patches you own. The hooks pass the
+/// votes on `Code-Review` and `Verified`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LabelSubscription {
+    // The label name as configured in Gerrit, e.g. "Code-Review"
+    pub label: String,
+
+    /*
+     * Only notify about these votes, e.g. [-2, 2]. An empty list notifies about
+     * every vote on the label.
+     */
+    pub values: Vec<i8>,
+}
+
+impl LabelSubscription {
+    pub fn matches(&self, label: &str, value: i8) -> bool {
+        self.label.eq_ignore_ascii_case(label)
+            && (self.values.is_empty() || self.values.contains(&value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LabelSubscription;
+
+    #[test]
+    fn empty_values_match_every_vote() {
+        let subscription = LabelSubscription {
+            label: String::from("Code-Review"),
+            values: vec![],
+        };
+
+        assert!(subscription.matches("Code-Review", -2));
+        assert!(subscription.matches("code-review", 1));
+        assert!(!subscription.matches("Verified", 1));
+    }
+
+    #[test]
+    fn values_restrict_matching_votes() {
+        let subscription = LabelSubscription {
+            label: String::from("Code-Review"),
+            values: vec![-2, 2],
+        };
+
+        assert!(subscription.matches("Code-Review", 2));
+        assert!(!subscription.matches("Code-Review", 1));
+    }
+}
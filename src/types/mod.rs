@@ -1,32 +1,55 @@
 mod app_state;
+mod channel_preferences;
+mod chat_command;
 mod code_review_status;
 mod connection_parameters;
 mod conversation_id;
+mod gerrit_account;
+mod gerrit_instance;
 mod gerrit_triggers;
+mod label_subscription;
+mod language;
 mod owner_settings;
 mod patch_status;
 mod path_to_user_data;
 mod profile_id;
 mod reviewer_settings;
+mod rich_message;
+mod schedule;
 mod settings;
+mod sync_record;
 mod synchronization;
+mod tenant;
 mod verified_status;
+mod watch;
 
 pub use self::app_state::AppState;
+pub use self::channel_preferences::ChannelPreferences;
+pub use self::chat_command::{ChatCommand, Subscription};
 pub use self::code_review_status::CodeReviewStatus;
-pub use self::connection_parameters::ConnectionParameters;
+pub use self::connection_parameters::{ConnectionParameters, Storage};
 pub use self::conversation_id::ConversationId;
+pub use self::gerrit_account::GerritAccount;
+pub use self::gerrit_instance::{GerritInstance, NAMESPACE_SEPARATOR};
 pub use self::gerrit_triggers::{
-    BaseData, CommentAddedData, GerritTrigger, PatchStatusChangedData, ReviewerAddedData,
+    change_number, BaseData, CommentAddedData, CommentLocation, GerritTrigger,
+    PatchStatusChangedData, ReviewerAddedData,
 };
+pub use self::label_subscription::LabelSubscription;
+pub use self::language::Language;
 pub use self::owner_settings::OwnerSettings;
 pub use self::patch_status::{patch_status, PatchStatus};
 pub use self::path_to_user_data::PathToUserData;
 pub use self::profile_id::ProfileId;
 pub use self::reviewer_settings::ReviewerSettings;
+pub use self::rich_message::{MessageFormat, RichMessage};
+pub use self::schedule::{QuietHours, Schedule};
 pub use self::settings::Settings;
-pub use self::synchronization::Synchronization;
+pub use self::sync_record::{ResolutionStrategy, SyncRecord};
+pub use self::synchronization::{Candidate, Synchronization};
+pub use self::tenant::Tenant;
 pub use self::verified_status::VerifiedStatus;
+pub use self::watch::Watch;
 
 use serde::{Deserialize, Serialize};
 use std::fmt;
@@ -49,7 +72,7 @@ mod macros {
     }
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TopicName(pub String);
 
 from_for_string_struct!(TopicName);
@@ -70,8 +93,43 @@ pub struct GerritUsername(pub String);
 
 from_for_string_struct!(GerritUsername);
 
+impl GerritUsername {
+    /// The username of a user of a namespaced Gerrit instance, e.g.
+    /// `legacy~jdoe`.
+    pub fn namespaced(namespace: &str, username: &str) -> GerritUsername {
+        GerritUsername(format!("{}{}{}", namespace, NAMESPACE_SEPARATOR, username))
+    }
+
+    /// The namespace of the Gerrit instance of the user, `None` for users of
+    /// the default instance.
+    pub fn namespace(&self) -> Option<&str> {
+        self.0
+            .find(NAMESPACE_SEPARATOR)
+            .map(|index| &self.0[..index])
+    }
+}
+
 impl fmt::Display for GerritUsername {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.0)
     }
 }
+
+/// Identifies a `Tenant`, e.g. to address its actors.
+#[derive(PartialEq, Eq, Clone, Hash, Debug, Serialize, Deserialize)]
+pub struct TenantId(pub String);
+
+from_for_string_struct!(TenantId);
+
+impl TenantId {
+    /// The tenant configured by the top level options.
+    pub fn default_tenant() -> TenantId {
+        TenantId::from("default")
+    }
+}
+
+impl fmt::Display for TenantId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
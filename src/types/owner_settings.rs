@@ -4,7 +4,7 @@ use serde::{Deserialize, Serialize};
 /**
  * All settings below apply to you, only when you are the owner of a given patch.
  */
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct OwnerSettings {
     // Be notified when someone comments on your review
     pub subscribe_comment: bool,
@@ -41,10 +41,7 @@ pub struct OwnerSettings {
 impl From<Settings> for OwnerSettings {
     fn from(origin: Settings) -> Self {
         match origin {
-            Settings::V1 {
-                as_reviewer: _,
-                as_owner,
-            } => as_owner,
+            Settings::V1 { as_owner, .. } | Settings::V2 { as_owner, .. } => as_owner,
         }
     }
 }
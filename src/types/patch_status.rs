@@ -2,7 +2,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::types::{CodeReviewStatus, VerifiedStatus};
 
-#[derive(PartialEq, Debug, Serialize, Deserialize)]
+#[derive(PartialEq, Clone, Debug, Serialize, Deserialize)]
 pub enum PatchStatus {
     Both(CodeReviewStatus, VerifiedStatus),
     CodeReview(CodeReviewStatus),
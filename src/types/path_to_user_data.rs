@@ -9,6 +9,17 @@ use std::path::PathBuf;
 /// * A users synchronisation file, that holds the mapping information to his
 ///   `ProfileId`.
 /// * The users settings file.
+/// * The SQLite database, which holds the data of all users.
+/// * The notification templates, shared by all users.
+/// * The audit log of all triggers and their outcome.
+/// * The notifications kept for users with an ambiguous mapping.
+/// * A backup of the users settings file, written before the settings are
+///   migrated to a new version.
+/// * The lock file, which is locked while files of the user are written.
+///
+/// The directory of a user is named after the username, which contains the
+/// namespace for users of namespaced Gerrit instances, e.g. `legacy~jdoe`.
+/// Users with the same username on different instances don't share files.
 ///
 pub struct PathToUserData {
     path: PathBuf,
@@ -38,6 +49,85 @@ impl PathToUserData {
         PathToUserData { path }
     }
 
+    /// Path to a backup of the settings file, e.g. `settings.ron.v1.bak` for
+    /// version `v1`.
+    pub fn settings_backup(
+        data_dir: &str,
+        username: &GerritUsername,
+        version: &str,
+    ) -> PathToUserData {
+        let filename = format!("settings.ron.{}.bak", version);
+        let path: PathBuf = [data_dir, &username.0, &filename].iter().collect();
+        PathToUserData { path }
+    }
+
+    /// Path to a temporary backup of the settings file. Like for settings, the
+    /// backup is written to it first and then moved over the backup.
+    pub fn settings_backup_temporary(
+        data_dir: &str,
+        username: &GerritUsername,
+        version: &str,
+    ) -> PathToUserData {
+        let filename = format!("settings.ron.{}.bak.tmp", version);
+        let path: PathBuf = [data_dir, &username.0, &filename].iter().collect();
+        PathToUserData { path }
+    }
+
+    /// Path to a temporary settings file. New settings are written to it first
+    /// and then moved over the settings file.
+    pub fn settings_temporary(data_dir: &str, username: &GerritUsername) -> PathToUserData {
+        let path: PathBuf = [data_dir, &username.0, "settings.ron.tmp"].iter().collect();
+        PathToUserData { path }
+    }
+
+    /// Path to a temporary sync file. Like for settings, the sync file is
+    /// written to it first and then moved over the sync file.
+    pub fn sync_temporary(data_dir: &str, username: &GerritUsername) -> PathToUserData {
+        let path: PathBuf = [data_dir, &username.0, "sync.ron.tmp"].iter().collect();
+        PathToUserData { path }
+    }
+
+    /// Path to a sync file that couldn't be parsed on startup. The file is
+    /// moved aside, so the user is resolved again.
+    pub fn sync_corrupt(data_dir: &str, username: &GerritUsername) -> PathToUserData {
+        let path: PathBuf = [data_dir, &username.0, "sync.ron.corrupt"].iter().collect();
+        PathToUserData { path }
+    }
+
+    /// Path to the lock file of a user. The server and `chtbtr-admin` lock it
+    /// while writing files of the user.
+    pub fn lock(data_dir: &str, username: &GerritUsername) -> PathToUserData {
+        let path: PathBuf = [data_dir, &username.0, ".lock"].iter().collect();
+        PathToUserData { path }
+    }
+
+    /// Path to the SQLite database, used instead of the user directories if
+    /// the SQLite storage is selected.
+    pub fn database(data_dir: &str) -> PathToUserData {
+        let path: PathBuf = [data_dir, "chtbtr.sqlite"].iter().collect();
+        PathToUserData { path }
+    }
+
+    /// Path to the file with the notification templates, which replace the
+    /// built-in texts. See `MessageTemplates`.
+    pub fn templates(data_dir: &str) -> PathToUserData {
+        let path: PathBuf = [data_dir, "templates.ron"].iter().collect();
+        PathToUserData { path }
+    }
+
+    /// Path to the audit log, which records every trigger and its outcome.
+    /// Rotated logs get a number appended, e.g. `audit.jsonl.1`.
+    pub fn audit_log(data_dir: &str) -> PathToUserData {
+        let path: PathBuf = [data_dir, "audit.jsonl"].iter().collect();
+        PathToUserData { path }
+    }
+
+    /// Path to the notifications kept for users with an ambiguous mapping.
+    pub fn pending_notifications(data_dir: &str) -> PathToUserData {
+        let path: PathBuf = [data_dir, "pending.json"].iter().collect();
+        PathToUserData { path }
+    }
+
     pub fn as_path(&self) -> &Path {
         self.path.as_path()
     }
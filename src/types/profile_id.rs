@@ -3,7 +3,6 @@ use std::convert::TryFrom;
 use std::fmt;
 
 /// Represents a profile id in the format PROFILE,id.
-// TODO: Write some tests for me!
 #[derive(PartialEq, Clone, Debug, Deserialize, Serialize)]
 pub struct ProfileId(pub u32);
 
@@ -23,15 +22,28 @@ impl TryFrom<&str> for ProfileId {
     type Error = &'static str;
 
     fn try_from(value: &str) -> Result<Self, &'static str> {
-        if !value.starts_with("PROFILE,") {
-            //Err(())
-            ()
-        }
+        let id_part = match value.strip_prefix("PROFILE,") {
+            Some(id_part) => id_part,
+            None => return Err("Expected 'PROFILE,n'."),
+        };
 
-        let id_part = value.replace("PROFILE,", "");
         match id_part.parse() {
             Ok(int) => Ok(ProfileId { 0: int }),
-            Err(_e) => Err(""),
+            Err(_e) => Err("The id isn't a number."),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::ProfileId;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn parses_profile_ids() {
+        assert_eq!(ProfileId::try_from("PROFILE,42"), Ok(ProfileId(42)));
+        assert!(ProfileId::try_from("42").is_err());
+        assert!(ProfileId::try_from("PROFILE,").is_err());
+        assert!(ProfileId::try_from("PROFILE,PROFILE,42").is_err());
+    }
+}
@@ -3,7 +3,7 @@ use serde::{Deserialize, Serialize};
 
 /// All settings below apply to you, only when you are a reviewer of a given
 /// patch.
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct ReviewerSettings {
     // Be notified when you are added as reviewer
     pub subscribe: bool,
@@ -32,10 +32,7 @@ pub struct ReviewerSettings {
 impl From<Settings> for ReviewerSettings {
     fn from(origin: Settings) -> Self {
         match origin {
-            Settings::V1 {
-                as_reviewer,
-                as_owner: _,
-            } => as_reviewer,
+            Settings::V1 { as_reviewer, .. } | Settings::V2 { as_reviewer, .. } => as_reviewer,
         }
     }
 }
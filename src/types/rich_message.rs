@@ -0,0 +1,210 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{fmt, str::FromStr};
+
+/// The richest format a chat backend renders messages in.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MessageFormat {
+    #[default]
+    PlainText,
+    Markdown,
+}
+
+impl FromStr for MessageFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "text" => Ok(MessageFormat::PlainText),
+            "markdown" => Ok(MessageFormat::Markdown),
+            _ => Err(format!(
+                "Unknown message format '{}'. Use text or markdown.",
+                value
+            )),
+        }
+    }
+}
+
+/// A notification with details. The text alone is a complete message, chat
+/// backends add the details as far as their format allows, see
+/// `RichMessage::render` and `RichMessage::to_card`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RichMessage {
+    // The message as plain text, e.g. rendered from a template
+    pub text: String,
+    // Details like the project or the votes, as label and value
+    pub fields: Vec<(String, String)>,
+    // Buttons, as label and URL
+    pub links: Vec<(String, String)>,
+    // The comment the notification is about
+    pub quote: Option<String>,
+}
+
+impl RichMessage {
+    /// A message without details, e.g. a reply to a chat command.
+    pub fn text<S: Into<String>>(text: S) -> RichMessage {
+        RichMessage {
+            text: text.into(),
+            fields: vec![],
+            links: vec![],
+            quote: None,
+        }
+    }
+
+    pub fn with_field<S: Into<String>>(mut self, label: &str, value: S) -> RichMessage {
+        self.fields.push((label.to_string(), value.into()));
+        self
+    }
+
+    pub fn with_link(mut self, label: &str, url: &str) -> RichMessage {
+        self.links.push((label.to_string(), url.to_string()));
+        self
+    }
+
+    pub fn with_quote(mut self, quote: Option<String>) -> RichMessage {
+        self.quote = quote;
+        self
+    }
+
+    /// Returns `true` if the message has no details, so it looks the same in
+    /// every format.
+    pub fn is_plain(&self) -> bool {
+        self.fields.is_empty() && self.links.is_empty() && self.quote.is_none()
+    }
+
+    /// The message without details but the quote, for users and chats
+    /// preferring plain text.
+    pub fn to_plain_text(&self) -> String {
+        match &self.quote {
+            Some(quote) => format!("{}\n\n{}", self.text, quoted(quote, str::to_string)),
+            None => self.text.clone(),
+        }
+    }
+
+    /// The message with its details as Markdown. Links already part of the
+    /// text aren't repeated.
+    pub fn to_markdown(&self) -> String {
+        let mut sections = vec![self.text.clone()];
+        if !self.fields.is_empty() {
+            let fields: Vec<String> = self
+                .fields
+                .iter()
+                .map(|(label, value)| {
+                    format!(
+                        "- **{}:** {}",
+                        escape_markdown(label),
+                        escape_markdown(value)
+                    )
+                })
+                .collect();
+            sections.push(fields.join("\n"));
+        }
+        if let Some(quote) = &self.quote {
+            sections.push(quoted(quote, escape_markdown));
+        }
+        let links: Vec<String> = self
+            .links
+            .iter()
+            .filter(|(_, url)| !self.text.contains(url.as_str()))
+            .map(|(label, url)| format!("[{}]({})", escape_markdown(label), url))
+            .collect();
+        if !links.is_empty() {
+            sections.push(links.join(" · "));
+        }
+        sections.join("\n\n")
+    }
+
+    pub fn render(&self, format: MessageFormat) -> String {
+        match format {
+            MessageFormat::PlainText => self.to_plain_text(),
+            MessageFormat::Markdown => self.to_markdown(),
+        }
+    }
+
+    /// The message as a card, for chat backends showing cards with fields and
+    /// buttons.
+    pub fn to_card(&self) -> Value {
+        json!({
+            "title": self.text,
+            "fields": self
+                .fields
+                .iter()
+                .map(|(label, value)| json!({ "label": label, "value": value }))
+                .collect::<Vec<Value>>(),
+            "quote": self.quote,
+            "actions": self
+                .links
+                .iter()
+                .map(|(label, url)| json!({ "label": label, "url": url }))
+                .collect::<Vec<Value>>(),
+        })
+    }
+}
+
+impl fmt::Display for RichMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_plain_text())
+    }
+}
+
+fn quoted(text: &str, escape: fn(&str) -> String) -> String {
+    text.lines()
+        .map(|line| format!("> {}", escape(line)))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn escape_markdown(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if "\\`*_[]<>#|~".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::{MessageFormat, RichMessage};
+
+    fn comment() -> RichMessage {
+        RichMessage::text("Comment was added by jdoe. 💬 https://gerrit/c/prj/+/2")
+            .with_field("Project", "my_project")
+            .with_link("Open change", "https://gerrit/c/prj/+/2")
+            .with_link("Open file", "https://gerrit/c/prj/+/2/1/main.rs")
+            .with_quote(Some(String::from("Looks *good*\nThanks")))
+    }
+
+    #[test]
+    fn renders_plain_text() {
+        assert_eq!(
+            comment().render(MessageFormat::PlainText),
+            "Comment was added by jdoe. 💬 https://gerrit/c/prj/+/2\n\n> Looks *good*\n> Thanks"
+        );
+        assert_eq!(RichMessage::text("Done").to_plain_text(), "Done");
+        assert!(RichMessage::text("Done").is_plain());
+        assert!(!comment().is_plain());
+    }
+
+    #[test]
+    fn renders_markdown() {
+        assert_eq!(
+            comment().render(MessageFormat::Markdown),
+            "Comment was added by jdoe. 💬 https://gerrit/c/prj/+/2\n\n\
+             - **Project:** my\\_project\n\n\
+             > Looks \\*good\\*\n> Thanks\n\n\
+             [Open file](https://gerrit/c/prj/+/2/1/main.rs)"
+        );
+    }
+
+    #[test]
+    fn renders_card() {
+        let card = comment().to_card();
+
+        assert_eq!(card["fields"][0]["value"], "my_project");
+        assert_eq!(card["actions"][0]["url"], "https://gerrit/c/prj/+/2");
+        assert_eq!(card["quote"], "Looks *good*\nThanks");
+    }
+}
@@ -0,0 +1,125 @@
+use chrono::{DateTime, FixedOffset, TimeZone, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Defines when notifications may be delivered.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Schedule {
+    // Don't deliver notifications during these hours, e.g. at night
+    pub quiet_hours: Option<QuietHours>,
+
+    // The offset of the quiet hours to UTC in minutes, e.g. 120 for UTC+02:00
+    #[serde(default)]
+    pub utc_offset: i32,
+
+    // Don't deliver any notifications until this point in time
+    pub muted_until: Option<DateTime<Utc>>,
+}
+
+/// A range of hours of the day, e.g. `from: 20, to: 8`. The range may wrap
+/// around midnight. `from` is inclusive, `to` is exclusive.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuietHours {
+    pub from: u32,
+    pub to: u32,
+}
+
+impl QuietHours {
+    pub fn contains(&self, hour: u32) -> bool {
+        if self.from <= self.to {
+            self.from <= hour && hour < self.to
+        } else {
+            hour >= self.from || hour < self.to
+        }
+    }
+}
+
+impl Schedule {
+    /// The time zone of the quiet hours. Offsets of a day or more fall back to
+    /// UTC.
+    pub fn time_zone(&self) -> FixedOffset {
+        self.utc_offset
+            .checked_mul(60)
+            .and_then(FixedOffset::east_opt)
+            .unwrap_or_else(|| FixedOffset::east(0))
+    }
+
+    pub fn is_muted<Tz: TimeZone>(&self, now: &DateTime<Tz>) -> bool {
+        match &self.muted_until {
+            Some(until) => now < until,
+            None => false,
+        }
+    }
+
+    /// Returns `true` if neither a mute nor the quiet hours apply at the given
+    /// point in time. Quiet hours are evaluated in `time_zone`.
+    pub fn allows_notification_at<Tz: TimeZone>(&self, now: &DateTime<Tz>) -> bool {
+        if self.is_muted(now) {
+            return false;
+        }
+
+        match &self.quiet_hours {
+            Some(quiet_hours) => !quiet_hours.contains(now.with_timezone(&self.time_zone()).hour()),
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{QuietHours, Schedule};
+    use chrono::{Duration, FixedOffset, TimeZone, Utc};
+
+    #[test]
+    fn quiet_hours_wrap_around_midnight() {
+        let quiet_hours = QuietHours { from: 20, to: 8 };
+
+        assert!(quiet_hours.contains(23));
+        assert!(quiet_hours.contains(0));
+        assert!(quiet_hours.contains(7));
+        assert!(!quiet_hours.contains(8));
+        assert!(!quiet_hours.contains(12));
+    }
+
+    #[test]
+    fn quiet_hours_within_a_day() {
+        let quiet_hours = QuietHours { from: 12, to: 13 };
+
+        assert!(quiet_hours.contains(12));
+        assert!(!quiet_hours.contains(13));
+    }
+
+    #[test]
+    fn mute_expires() {
+        let now = Utc::now();
+        let schedule = Schedule {
+            quiet_hours: None,
+            utc_offset: 0,
+            muted_until: Some(now + Duration::hours(2)),
+        };
+
+        assert!(!schedule.allows_notification_at(&now));
+        assert!(schedule.allows_notification_at(&(now + Duration::hours(3))));
+    }
+
+    #[test]
+    fn quiet_hours_use_utc_offset_of_schedule() {
+        let mut schedule = Schedule {
+            quiet_hours: Some(QuietHours { from: 20, to: 8 }),
+            utc_offset: 0,
+            muted_until: None,
+        };
+        let utc = Utc.ymd(2020, 7, 1).and_hms(19, 0, 0);
+        let berlin_summer = FixedOffset::east(2 * 3600);
+
+        // The time zone of the given time doesn't matter
+        assert!(schedule.allows_notification_at(&utc));
+        assert!(schedule.allows_notification_at(&utc.with_timezone(&berlin_summer)));
+
+        // 19:00 UTC is 21:00 in UTC+02:00
+        schedule.utc_offset = 120;
+        assert!(!schedule.allows_notification_at(&utc));
+
+        schedule.utc_offset = 24 * 60;
+        assert_eq!(schedule.time_zone(), FixedOffset::east(0));
+    }
+}
@@ -1,47 +1,265 @@
-use super::{OwnerSettings, ReviewerSettings};
+use super::{
+    ChannelPreferences, GerritUsername, LabelSubscription, Language, OwnerSettings, ProjectName,
+    ReviewerSettings, Schedule, Watch,
+};
 use serde::{Deserialize, Serialize};
 /*
  * Should be loaded on each interaction. I guess it would be more expensive to
  * generate a hash of the file content and compare it with an existing has, than
  * to simply read the file.
  */
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum Settings {
     V1 {
         as_reviewer: ReviewerSettings,
         as_owner: OwnerSettings,
     },
+    V2 {
+        as_reviewer: ReviewerSettings,
+        as_owner: OwnerSettings,
+        labels: Vec<LabelSubscription>,
+        watches: Vec<Watch>,
+        schedule: Schedule,
+        channel: ChannelPreferences,
+    },
+}
+
+impl Settings {
+    /// Returns `true` if the settings use the latest schema version.
+    pub fn is_current(&self) -> bool {
+        matches!(self, Settings::V2 { .. })
+    }
+
+    /// The schema version, e.g. `v1`.
+    pub fn version(&self) -> &'static str {
+        match self {
+            Settings::V1 { .. } => "v1",
+            Settings::V2 { .. } => "v2",
+        }
+    }
+
+    /// Migrates the settings to the latest schema version. All choices of the
+    /// user are kept, new options are set to their defaults.
+    pub fn migrate(self) -> Settings {
+        match self {
+            Settings::V1 {
+                as_reviewer,
+                as_owner,
+            } => Settings::V2 {
+                as_reviewer,
+                as_owner,
+                labels: vec![],
+                watches: vec![],
+                schedule: Schedule::default(),
+                channel: ChannelPreferences::default(),
+            },
+            current => current,
+        }
+    }
+
+    fn owner_and_reviewer(&self) -> (&OwnerSettings, &ReviewerSettings) {
+        match self {
+            Settings::V1 {
+                as_reviewer,
+                as_owner,
+            }
+            | Settings::V2 {
+                as_reviewer,
+                as_owner,
+                ..
+            } => (as_owner, as_reviewer),
+        }
+    }
+
+    /// Projects ignored as owner or as reviewer, without duplicates.
+    pub fn ignored_projects(&self) -> Vec<ProjectName> {
+        let (as_owner, as_reviewer) = self.owner_and_reviewer();
+        union(&as_owner.ignore_projects, &as_reviewer.ignore_projects)
+    }
+
+    /// The language of notifications. Settings without a language choice get
+    /// English.
+    pub fn language(&self) -> Language {
+        match self {
+            Settings::V1 { .. } => Language::default(),
+            Settings::V2 { channel, .. } => channel.language,
+        }
+    }
+
+    /// The labels the user subscribed to as owner. Settings without label
+    /// subscriptions get none.
+    pub fn labels(&self) -> &[LabelSubscription] {
+        match self {
+            Settings::V1 { .. } => &[],
+            Settings::V2 { labels, .. } => labels,
+        }
+    }
+
+    /// When notifications may be delivered. Settings without a schedule allow
+    /// notifications at any time.
+    pub fn schedule(&self) -> Option<&Schedule> {
+        match self {
+            Settings::V1 { .. } => None,
+            Settings::V2 { schedule, .. } => Some(schedule),
+        }
+    }
+
+    /// Whether the user watches the change of the project. Settings without
+    /// watches watch nothing.
+    pub fn watches(&self, project: &str, change: &str) -> bool {
+        match self {
+            Settings::V1 { .. } => false,
+            Settings::V2 { watches, .. } => watches.iter().any(|w| w.covers(project, change)),
+        }
+    }
+
+    /// Returns `false` if the user turned off notifications via direct
+    /// message, the only way the chatbot delivers them.
+    pub fn direct_message(&self) -> bool {
+        match self {
+            Settings::V1 { .. } => true,
+            Settings::V2 { channel, .. } => channel.direct_message,
+        }
+    }
+
+    /// Returns `true` if notifications should be plain text, even if the chat
+    /// supports richer formatting.
+    pub fn prefers_plain_text(&self) -> bool {
+        match self {
+            Settings::V1 { .. } => false,
+            Settings::V2 { channel, .. } => channel.plain_text,
+        }
+    }
+
+    /// Users ignored as owner or as reviewer, without duplicates.
+    pub fn ignored_users(&self) -> Vec<GerritUsername> {
+        let (as_owner, as_reviewer) = self.owner_and_reviewer();
+        union(
+            &as_owner.ignore_by_username,
+            &as_reviewer.ignore_by_username,
+        )
+    }
+}
+
+fn union<T: Clone + PartialEq>(a: &[T], b: &[T]) -> Vec<T> {
+    let mut result = a.to_vec();
+    for value in b {
+        if !result.contains(value) {
+            result.push(value.clone());
+        }
+    }
+    result
 }
 
 #[cfg(test)]
 mod tests {
     use super::Settings;
-    use crate::types::{OwnerSettings, ReviewerSettings};
+    use crate::types::{
+        ChannelPreferences, GerritUsername, OwnerSettings, ProjectName, ReviewerSettings, Schedule,
+        TopicName,
+    };
+
+    fn create_reviewer_settings() -> ReviewerSettings {
+        ReviewerSettings {
+            subscribe: false,
+            ignore_projects: vec![],
+            ignore_topics: vec![TopicName::from("merge-commit")],
+            ignore_by_username: vec![
+                GerritUsername::from("tools.just"),
+                GerritUsername::from("ec2.gerrit"),
+            ],
+        }
+    }
+
+    fn create_owner_settings() -> OwnerSettings {
+        OwnerSettings {
+            subscribe_comment: false,
+            subscribe_verified: false,
+            subscribe_ready_for_submit: false,
+            subscribe_submitted: false,
+            ignore_by_username: vec![
+                GerritUsername::from("a.user"),
+                GerritUsername::from("another.user"),
+            ],
+            ignore_empty_review_comments: false,
+            ignore_projects: vec![],
+        }
+    }
 
     #[test]
     fn test_settings() {
-        Settings::V1 {
+        let settings = Settings::V1 {
+            as_reviewer: create_reviewer_settings(),
+            as_owner: create_owner_settings(),
+        };
+        assert!(!settings.is_current());
+    }
+
+    #[test]
+    fn migration_from_v1_keeps_user_choices() {
+        let as_owner = OwnerSettings {
+            subscribe_comment: true,
+            ignore_projects: vec![ProjectName::from("juco")],
+            ..create_owner_settings()
+        };
+        let settings = Settings::V1 {
+            as_reviewer: create_reviewer_settings(),
+            as_owner: as_owner.clone(),
+        };
+
+        let migrated = settings.migrate();
+
+        assert_eq!(
+            migrated,
+            Settings::V2 {
+                as_reviewer: create_reviewer_settings(),
+                as_owner,
+                labels: vec![],
+                watches: vec![],
+                schedule: Schedule::default(),
+                channel: ChannelPreferences::default(),
+            }
+        );
+        assert!(migrated.is_current());
+    }
+
+    #[test]
+    fn migration_of_current_settings_changes_nothing() {
+        let settings = Settings::V1 {
+            as_reviewer: create_reviewer_settings(),
+            as_owner: create_owner_settings(),
+        }
+        .migrate();
+
+        assert_eq!(settings.clone().migrate(), settings);
+    }
+
+    #[test]
+    fn default_settings_are_current() {
+        assert!(crate::default::default_settings().is_current());
+    }
+
+    #[test]
+    fn ignored_users_are_combined_without_duplicates() {
+        let settings = Settings::V1 {
             as_reviewer: ReviewerSettings {
-                subscribe: false,
-                ignore_projects: vec![],
-                ignore_topics: vec![TopicName::from("merge-commit")],
                 ignore_by_username: vec![
-                    GerritUsername::from("tools.just"),
-                    GerritUsername::from("ec2.gerrit"),
-                ],
-            },
-            as_owner: OwnerSettings {
-                subscribe_comment: false,
-                subscribe_verified: false,
-                subscribe_ready_for_submit: false,
-                subscribe_submitted: false,
-                ignore_by_username: vec![
-                    GerritUsername::from("a.user"),
                     GerritUsername::from("another.user"),
+                    GerritUsername::from("tools.just"),
                 ],
-                ignore_empty_review_comments: false,
-                ignore_projects: vec![],
+                ..create_reviewer_settings()
             },
+            as_owner: create_owner_settings(),
         };
+
+        assert_eq!(
+            settings.ignored_users(),
+            vec![
+                GerritUsername::from("a.user"),
+                GerritUsername::from("another.user"),
+                GerritUsername::from("tools.just"),
+            ]
+        );
+        assert!(settings.ignored_projects().is_empty());
     }
 }
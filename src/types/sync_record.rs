@@ -0,0 +1,118 @@
+use super::{ProfileId, Synchronization};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+/// How a `ProfileId` mapping was found.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ResolutionStrategy {
+    // Searching Just for the email of the Gerrit account
+    Email,
+    // Searching Just for the full name of the Gerrit account
+    FullName,
+    // Searching Just for the first and last name, leaving out middle names
+    FirstAndLastName,
+    // Set by an admin
+    Manual,
+    // Imported from the user directory
+    Directory,
+}
+
+/*
+ * The content of a users sync.ron. Older files only contain the bare
+ * `Synchronization`, they are read as a record without strategy and time.
+ */
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SyncRecord {
+    pub mapping: Synchronization<ProfileId>,
+
+    #[serde(default)]
+    pub resolved_by: Option<ResolutionStrategy>,
+
+    // When the mapping was resolved or set
+    #[serde(default)]
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+impl SyncRecord {
+    pub fn new(
+        mapping: Synchronization<ProfileId>,
+        resolved_by: Option<ResolutionStrategy>,
+    ) -> SyncRecord {
+        SyncRecord {
+            mapping,
+            resolved_by,
+            resolved_at: Some(Utc::now()),
+        }
+    }
+
+    /// Whether resolving the user failed longer than `ttl` ago, so it should
+    /// be tried again. Records without time are always retried.
+    pub fn should_retry(&self, ttl: Duration, now: &DateTime<Utc>) -> bool {
+        match (&self.mapping, &self.resolved_at) {
+            (Synchronization::None, Some(resolved_at)) => *resolved_at + ttl <= *now,
+            (Synchronization::None, None) => true,
+            _ => false,
+        }
+    }
+
+    /// Parses a sync file in the current or the legacy format.
+    pub fn parse(content: &str) -> Result<SyncRecord, ron::de::Error> {
+        match ron::de::from_str::<SyncRecord>(content) {
+            Ok(record) => Ok(record),
+            Err(e) => match ron::de::from_str::<Synchronization<ProfileId>>(content) {
+                Ok(mapping) => Ok(SyncRecord {
+                    mapping,
+                    resolved_by: None,
+                    resolved_at: None,
+                }),
+                Err(_) => Err(e),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ResolutionStrategy, SyncRecord};
+    use crate::types::{ProfileId, Synchronization};
+    use chrono::{Duration, Utc};
+
+    #[test]
+    fn parses_legacy_format() {
+        let record = SyncRecord::parse("Some((312))").unwrap();
+        assert_eq!(record.mapping, Synchronization::Some(ProfileId(312)));
+        assert_eq!(record.resolved_by, None);
+        assert_eq!(record.resolved_at, None);
+
+        let record = SyncRecord::parse("None").unwrap();
+        assert_eq!(record.mapping, Synchronization::None);
+        assert_eq!(record.resolved_at, None);
+    }
+
+    #[test]
+    fn retries_unresolved_users_after_ttl() {
+        let ttl = Duration::hours(24);
+        let now = Utc::now();
+        let mut record = SyncRecord::new(Synchronization::None, None);
+        assert!(!record.should_retry(ttl, &now));
+        assert!(record.should_retry(ttl, &(now + Duration::hours(25))));
+
+        record.resolved_at = None;
+        assert!(record.should_retry(ttl, &now));
+
+        let record = SyncRecord::new(Synchronization::Some(ProfileId(1)), None);
+        assert!(!record.should_retry(ttl, &(now + Duration::hours(25))));
+    }
+
+    #[test]
+    fn parses_current_format() {
+        let record = SyncRecord::new(
+            Synchronization::Some(ProfileId(1)),
+            Some(ResolutionStrategy::Email),
+        );
+        let serialized = ron::ser::to_string(&record).unwrap();
+
+        assert_eq!(SyncRecord::parse(&serialized), Ok(record));
+        assert!(SyncRecord::parse("Maybe").is_err());
+    }
+}
@@ -10,6 +10,16 @@ pub enum Synchronization<V> {
     Some(V),
     NotMappedYet,
     None,
+    // More than one profile matched, an admin has to pick one of them
+    Ambiguous(Vec<Candidate<V>>),
+}
+
+/// A profile that matched the search for a user, along with its display
+/// name so an admin can tell candidates apart.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Candidate<V> {
+    pub id: V,
+    pub name: String,
 }
 
 impl<V> Synchronization<V> {
@@ -22,18 +32,43 @@ impl<V> Synchronization<V> {
     ) -> Result<Option<V>, String> {
         match self {
             Synchronization::Some(v) => Ok(Some(v)),
-            Synchronization::None => Ok(None),
+            Synchronization::None | Synchronization::Ambiguous(_) => Ok(None),
             Synchronization::NotMappedYet => closure(),
         }
     }
 }
 
+impl<V: PartialEq> Synchronization<V> {
+    /// Whether `value` is one of the candidates of an ambiguous mapping.
+    pub fn is_candidate(&self, value: &V) -> bool {
+        match self {
+            Synchronization::Ambiguous(candidates) => {
+                candidates.iter().any(|candidate| &candidate.id == value)
+            }
+            _ => false,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::ProfileId;
+    use super::{Candidate, Synchronization};
+    use crate::types::ProfileId;
 
     #[test]
     fn test_synchronization() {
         Synchronization::Some(ProfileId { 0: 0 });
     }
+
+    #[test]
+    fn test_is_candidate() {
+        let ambiguous = Synchronization::Ambiguous(vec![Candidate {
+            id: ProfileId(1),
+            name: String::from("First Last"),
+        }]);
+
+        assert!(ambiguous.is_candidate(&ProfileId(1)));
+        assert!(!ambiguous.is_candidate(&ProfileId(2)));
+        assert!(!Synchronization::Some(ProfileId(1)).is_candidate(&ProfileId(1)));
+    }
 }
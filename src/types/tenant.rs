@@ -0,0 +1,27 @@
+use super::{MessageFormat, ProfileId, TenantId};
+
+/// A bot identity on a Just installation. Every tenant has its own chatbot
+/// profile, OAuth client and data directory, so one server can notify the
+/// users of several Just installations.
+///
+/// The default tenant is configured by the top level options, further tenants
+/// by a config file each (see `--tenants`). Triggers are routed to a tenant by
+/// their Gerrit instance.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tenant {
+    pub id: TenantId,
+    pub profile_id: ProfileId,
+    // The Just domain, e.g. just.installation.social
+    pub domain: String,
+    pub username: String,
+    pub password: String,
+    pub client_id: String,
+    pub data_dir: String,
+    // Path to a file mapping usernames to profiles, consulted before Just
+    pub user_directory: Option<String>,
+    // The richest format the Just installation shows messages in
+    pub message_format: MessageFormat,
+    // Names of the Gerrit instances notifying users of this tenant. The
+    // default tenant gets the triggers of all other instances.
+    pub gerrit_instances: Vec<String>,
+}
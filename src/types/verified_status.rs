@@ -8,6 +8,17 @@ pub enum VerifiedStatus {
     MinusOne,
 }
 
+impl VerifiedStatus {
+    /// The vote as number, e.g. `1` for `PlusOne`.
+    pub fn value(&self) -> i8 {
+        match self {
+            VerifiedStatus::PlusOne => 1,
+            VerifiedStatus::None => 0,
+            VerifiedStatus::MinusOne => -1,
+        }
+    }
+}
+
 impl fmt::Display for VerifiedStatus {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let result = match self {
@@ -0,0 +1,72 @@
+use super::ProjectName;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Watch a project, or a single change of it, to be notified about new comments
+/// and votes, even if you are neither owner nor reviewer.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Watch {
+    pub project: ProjectName,
+    // The change number, if only this change of the project is watched
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub change: Option<String>,
+}
+
+impl Watch {
+    /// Whether the watch covers the change of the project.
+    pub fn covers(&self, project: &str, change: &str) -> bool {
+        let change_matches = match &self.change {
+            Some(watched) => watched == change,
+            None => true,
+        };
+        self.project.0 == project && change_matches
+    }
+}
+
+/// Parses a project, e.g. `my-project`, or a change written like in Gerrit
+/// URLs, e.g. `my-project/+/1234`.
+impl From<&str> for Watch {
+    fn from(value: &str) -> Watch {
+        match value.rsplit_once("/+/") {
+            Some((project, change)) => Watch {
+                project: ProjectName::from(project.trim()),
+                change: Some(change.trim().to_string()),
+            },
+            None => Watch {
+                project: ProjectName::from(value.trim()),
+                change: None,
+            },
+        }
+    }
+}
+
+impl fmt::Display for Watch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.change {
+            Some(change) => write!(f, "{}/+/{}", self.project, change),
+            None => write!(f, "{}", self.project),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Watch;
+    use crate::types::ProjectName;
+
+    #[test]
+    fn parses_projects_and_changes() {
+        let project = Watch::from("juco");
+        assert_eq!(project.project, ProjectName::from("juco"));
+        assert_eq!(project.change, None);
+        assert!(project.covers("juco", "1234"));
+        assert!(!project.covers("tools", "1234"));
+
+        let change = Watch::from("tools/juco/+/1234");
+        assert_eq!(change.project, ProjectName::from("tools/juco"));
+        assert_eq!(change.change.as_deref(), Some("1234"));
+        assert!(change.covers("tools/juco", "1234"));
+        assert!(!change.covers("tools/juco", "1235"));
+        assert_eq!(change.to_string(), "tools/juco/+/1234");
+    }
+}